@@ -0,0 +1,213 @@
+// ScoreFall Ink - Music Composition Software
+//
+// Copyright (C) 2019-2020 Jeron Aldaron Lau <jeronlau@plopgrizzly.com>
+// Copyright (C) 2019-2020 Doug P. Lau
+//
+//     This program is free software: you can redistribute it and/or modify
+//     it under the terms of the GNU General Public License as published by
+//     the Free Software Foundation, either version 3 of the License, or
+//     (at your option) any later version.
+//
+//     This program is distributed in the hope that it will be useful,
+//     but WITHOUT ANY WARRANTY; without even the implied warranty of
+//     MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//     GNU General Public License for more details.
+//
+//     You should have received a copy of the GNU General Public License
+//     along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+/* ************************************************************************** */
+
+//! Diagnostics reported while loading and checking scores.
+
+use std::fmt;
+
+/// How serious a diagnostic is.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Severity {
+    /// The score can't be used.
+    Error,
+    /// The score can be used, but is probably wrong.
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// Position of a problem within a movement.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Location {
+    /// Movement number
+    pub movement: u16,
+    /// Bar number
+    pub bar: Option<u16>,
+    /// Channel number
+    pub chan: Option<u16>,
+    /// Voice number within channel (if it has more than one)
+    pub voice: Option<u16>,
+    /// Marking number within bar
+    pub marking: Option<u16>,
+}
+
+/// A problem with a score.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    /// How serious the problem is.
+    pub severity: Severity,
+    /// File containing the problem.
+    pub file: String,
+    /// Position of the problem (if known).
+    pub location: Option<Location>,
+    /// Description of the problem.
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.severity, self.file)?;
+        if let Some(loc) = &self.location {
+            // Numbers are shown starting from 1, like in the editor.
+            write!(f, ": movement {}", loc.movement + 1)?;
+            if let Some(bar) = loc.bar {
+                write!(f, ", bar {}", bar + 1)?;
+            }
+            if let Some(chan) = loc.chan {
+                write!(f, ", channel {}", chan + 1)?;
+            }
+            if let Some(voice) = loc.voice {
+                write!(f, ", voice {}", voice + 1)?;
+            }
+            if let Some(marking) = loc.marking {
+                write!(f, ", marking {}", marking + 1)?;
+            }
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+impl Diagnostic {
+    /// Create an error diagnostic.
+    pub fn error<F: Into<String>, M: Into<String>>(
+        file: F,
+        message: M,
+    ) -> Self {
+        Diagnostic {
+            severity: Severity::Error,
+            file: file.into(),
+            location: None,
+            message: message.into(),
+        }
+    }
+
+    /// Create a warning diagnostic.
+    pub fn warning<F: Into<String>, M: Into<String>>(
+        file: F,
+        message: M,
+    ) -> Self {
+        Diagnostic {
+            severity: Severity::Warning,
+            ..Self::error(file, message)
+        }
+    }
+
+    /// Set the location of the diagnostic.
+    pub fn at(mut self, location: Location) -> Self {
+        self.location = Some(location);
+        self
+    }
+
+    /// Check if the diagnostic is an error.
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    /// Format the diagnostic as a single line JSON object.  Location numbers
+    /// start from 0.
+    pub fn json(&self) -> String {
+        let mut json = format!(
+            "{{\"severity\":\"{}\",\"file\":{}",
+            self.severity,
+            json_string(&self.file)
+        );
+        if let Some(loc) = &self.location {
+            json.push_str(&format!(",\"movement\":{}", loc.movement));
+            let fields = [
+                ("bar", loc.bar),
+                ("chan", loc.chan),
+                ("voice", loc.voice),
+                ("marking", loc.marking),
+            ];
+            for (name, value) in &fields {
+                if let Some(value) = value {
+                    json.push_str(&format!(",\"{}\":{}", name, value));
+                }
+            }
+        }
+        json.push_str(&format!(
+            ",\"message\":{}}}",
+            json_string(&self.message)
+        ));
+        json
+    }
+}
+
+/// Quote and escape a JSON string.
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if (c as u32) < 0x20 => {
+                out.push_str(&format!("\\u{:04x}", c as u32))
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display() {
+        let diag =
+            Diagnostic::error("a.scof", "bar is too long").at(Location {
+                movement: 0,
+                bar: Some(2),
+                chan: Some(1),
+                voice: None,
+                marking: None,
+            });
+        assert_eq!(
+            diag.to_string(),
+            "error: a.scof: movement 1, bar 3, channel 2: bar is too long"
+        );
+        assert_eq!(
+            Diagnostic::warning("b.scof", "empty").to_string(),
+            "warning: b.scof: empty"
+        );
+    }
+
+    #[test]
+    fn json() {
+        let diag =
+            Diagnostic::warning("\"x\".scof", "a\nb").at(Location::default());
+        assert_eq!(
+            diag.json(),
+            "{\"severity\":\"warning\",\"file\":\"\\\"x\\\".scof\",\
+             \"movement\":0,\"message\":\"a\\nb\"}"
+        );
+    }
+}
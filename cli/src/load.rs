@@ -0,0 +1,244 @@
+// ScoreFall Ink - Music Composition Software
+//
+// Copyright (C) 2019-2020 Jeron Aldaron Lau <jeronlau@plopgrizzly.com>
+// Copyright (C) 2019-2020 Doug P. Lau
+//
+//     This program is free software: you can redistribute it and/or modify
+//     it under the terms of the GNU General Public License as published by
+//     the Free Software Foundation, either version 3 of the License, or
+//     (at your option) any later version.
+//
+//     This program is distributed in the hope that it will be useful,
+//     but WITHOUT ANY WARRANTY; without even the implied warranty of
+//     MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//     GNU General Public License for more details.
+//
+//     You should have received a copy of the GNU General Public License
+//     along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+/* ************************************************************************** */
+
+//! Load scores from a `.scof` zip file, or an unpacked `.scof` directory.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+use scof::{Meta, Movement, ParseError, Scof, Style};
+
+use crate::diagnostic::{Diagnostic, Location};
+
+/// Read the text files of a score, by path within the score.
+fn read_files(path: &Path) -> Result<BTreeMap<String, String>, String> {
+    let mut files = BTreeMap::new();
+    if path.is_dir() {
+        read_dir(path, "", &mut files)?;
+    } else {
+        let file = fs::File::open(path).map_err(|e| e.to_string())?;
+        let mut zip = zip::ZipArchive::new(file).map_err(|e| e.to_string())?;
+        for i in 0..zip.len() {
+            let mut file = zip.by_index(i).map_err(|e| e.to_string())?;
+            if !file.name().ends_with(".muon") {
+                continue;
+            }
+            let mut text = String::new();
+            file.read_to_string(&mut text).map_err(|e| e.to_string())?;
+            files.insert(file.name().to_string(), text);
+        }
+    }
+    Ok(files)
+}
+
+/// Read the text files of an unpacked score directory.
+fn read_dir(
+    dir: &Path,
+    prefix: &str,
+    files: &mut BTreeMap<String, String>,
+) -> Result<(), String> {
+    for entry in fs::read_dir(dir).map_err(|e| e.to_string())? {
+        let entry = entry.map_err(|e| e.to_string())?;
+        let name = format!("{}{}", prefix, entry.file_name().to_string_lossy());
+        let path = entry.path();
+        if path.is_dir() {
+            read_dir(&path, &format!("{}/", name), files)?;
+        } else if name.ends_with(".muon") {
+            let text = fs::read_to_string(&path).map_err(|e| e.to_string())?;
+            files.insert(name, text);
+        }
+    }
+    Ok(())
+}
+
+/// Get the title of a score from its file name.
+fn title(path: &Path) -> String {
+    let stem = path.file_stem().map(|s| s.to_string_lossy());
+    stem.unwrap_or_default().replace('\\', "/")
+}
+
+/// Load a score.  All problems found while parsing are returned as
+/// diagnostics.
+pub fn load(path: &Path) -> Result<Scof, Vec<Diagnostic>> {
+    let name = path.display().to_string();
+    let mut files =
+        read_files(path).map_err(|e| vec![Diagnostic::error(&name, e)])?;
+    let mut diagnostics = vec![];
+
+    let meta = match files.remove("Meta.muon") {
+        Some(text) => Meta::from_muon(&text).unwrap_or_else(|e| {
+            let file = format!("{}/Meta.muon", name);
+            diagnostics.push(Diagnostic::error(file, e.to_string()));
+            Meta::default()
+        }),
+        None => Meta::default(),
+    };
+
+    let style = match files.remove("Style.muon") {
+        Some(text) => Style::from_muon(&text).unwrap_or_else(|e| {
+            let file = format!("{}/Style.muon", name);
+            diagnostics.push(Diagnostic::error(file, e.to_string()));
+            Style::default()
+        }),
+        None => Style::default(),
+    };
+
+    // Movements listed in the metadata, or all of them in order.
+    let names: Vec<String> = if meta.movement.is_empty() {
+        files
+            .keys()
+            .filter_map(|f| f.strip_prefix("Movement/"))
+            .map(|f| f.trim_end_matches(".muon").to_string())
+            .collect()
+    } else {
+        meta.movement.clone()
+    };
+    let mut movement = vec![];
+    for (m, mvmt) in names.iter().enumerate() {
+        let file = format!("Movement/{}.muon", mvmt);
+        let full = format!("{}/{}", name, file);
+        let loc = Location {
+            movement: m as u16,
+            ..Default::default()
+        };
+        match files.get(&file).map(|text| Movement::from_muon(text)) {
+            Some(Ok(mvmt)) => movement.push(mvmt),
+            Some(Err(ParseError::Marking {
+                bar,
+                chan,
+                voice,
+                marking,
+                text,
+            })) => {
+                let message = format!("invalid marking `{}`", text);
+                let loc = Location {
+                    bar: Some(bar),
+                    chan: Some(chan),
+                    voice: Some(voice).filter(|v| *v > 0),
+                    marking: Some(marking),
+                    ..loc
+                };
+                diagnostics.push(Diagnostic::error(full, message).at(loc));
+            }
+            Some(Err(e)) => {
+                diagnostics.push(Diagnostic::error(full, e.to_string()).at(loc))
+            }
+            None => {
+                let message = format!("missing movement `{}`", mvmt);
+                diagnostics.push(Diagnostic::error(name.clone(), message));
+            }
+        }
+    }
+    if names.is_empty() {
+        diagnostics.push(Diagnostic::error(&name, "score has no movements"));
+    }
+
+    if diagnostics.is_empty() {
+        Ok(Scof {
+            title: title(path),
+            meta,
+            style,
+            movement,
+            cache: vec![vec![]; names.len()],
+            ..Default::default()
+        })
+    } else {
+        Err(diagnostics)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use scof::Grouping;
+    use std::path::PathBuf;
+
+    /// Create an unpacked score directory for testing.
+    pub(crate) fn score_dir(name: &str, movements: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir()
+            .join(format!("scorefall-{}-{}", std::process::id(), name))
+            .join("Test Score.scof");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("Movement")).unwrap();
+        let mut meta = "composer: Someone\nmovement: ".to_string();
+        let names: Vec<&str> = movements.iter().map(|m| m.0).collect();
+        meta.push_str(&names.join("\n        : "));
+        meta.push('\n');
+        fs::write(dir.join("Meta.muon"), meta).unwrap();
+        for (mvmt, text) in movements {
+            let file = dir.join("Movement").join(format!("{}.muon", mvmt));
+            fs::write(file, text).unwrap();
+        }
+        dir
+    }
+
+    const MOVEMENT: &str = "sig: 0\n  time: 4/4\n  tempo: 90\nbar:\n  sig: 0\
+        \n  chan: 1/2C4 1/2D4\nbar:\n  chan: 1/1E4\n";
+
+    #[test]
+    fn directory() {
+        let dir = score_dir("directory", &[("First", MOVEMENT)]);
+        let style = "group: brace\n  first: 0\n  last: 1\n";
+        fs::write(dir.join("Style.muon"), style).unwrap();
+        let scof = load(&dir).unwrap();
+        assert_eq!(scof.title, "Test Score");
+        assert_eq!(scof.meta.composer, "Someone");
+        assert_eq!(scof.movement.len(), 1);
+        assert_eq!(scof.movement[0].bar.len(), 2);
+        assert_eq!(scof.tempo(0), 90);
+        assert_eq!(scof.style.group[0].grouping(), Some(Grouping::Brace));
+    }
+
+    #[test]
+    fn zip_file() {
+        use std::io::Write;
+
+        let path = std::env::temp_dir()
+            .join(format!("scorefall-{}-zip.scof", std::process::id()));
+        let file = fs::File::create(&path).unwrap();
+        let mut zip = zip::ZipWriter::new(file);
+        let options = zip::write::FileOptions::default();
+        zip.start_file("Movement/One.muon", options).unwrap();
+        zip.write_all(MOVEMENT.as_bytes()).unwrap();
+        zip.finish().unwrap();
+
+        let scof = load(&path).unwrap();
+        assert_eq!(scof.meta.composer, "Anonymous");
+        assert_eq!(scof.movement[0].bar.len(), 2);
+    }
+
+    #[test]
+    fn errors() {
+        let bad = "bar:\n  chan: 1/4C4 1/4Q4\n";
+        let dir = score_dir("errors", &[("Bad", bad), ("Missing", "")]);
+        fs::remove_file(dir.join("Movement/Missing.muon")).unwrap();
+        let errors = match load(&dir) {
+            Ok(_) => panic!("loaded invalid score"),
+            Err(errors) => errors,
+        };
+        assert_eq!(errors.len(), 2);
+        let loc = errors[0].location.as_ref().unwrap();
+        assert_eq!(loc.marking, Some(1));
+        assert_eq!(errors[0].message, "invalid marking `1/4Q4`");
+        assert_eq!(errors[1].message, "missing movement `Missing`");
+    }
+}
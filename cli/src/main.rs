@@ -0,0 +1,414 @@
+// ScoreFall Ink - Music Composition Software
+//
+// Copyright (C) 2019-2020 Jeron Aldaron Lau <jeronlau@plopgrizzly.com>
+// Copyright (C) 2019-2020 Doug P. Lau
+//
+//     This program is free software: you can redistribute it and/or modify
+//     it under the terms of the GNU General Public License as published by
+//     the Free Software Foundation, either version 3 of the License, or
+//     (at your option) any later version.
+//
+//     This program is distributed in the hope that it will be useful,
+//     but WITHOUT ANY WARRANTY; without even the implied warranty of
+//     MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//     GNU General Public License for more details.
+//
+//     You should have received a copy of the GNU General Public License
+//     along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+/* ************************************************************************** */
+
+//! Command-line tool for rendering, converting and checking scores.
+
+mod diagnostic;
+mod load;
+mod midi;
+mod validate;
+
+use std::path::{Path, PathBuf};
+use std::process::exit;
+
+use diagnostic::Diagnostic;
+use scof::{Cursor, Grouping, Scof};
+use staverator::{LayoutOptions, PaperSize, PdfOptions, RasterOptions};
+
+/// Exit code when the score has errors.
+const EXIT_INVALID: i32 = 1;
+/// Exit code for invalid command-line arguments.
+const EXIT_USAGE: i32 = 2;
+/// Exit code when an output file can't be written.
+const EXIT_IO: i32 = 3;
+
+const USAGE: &str = "\
+Usage:
+    scorefall render <in.scof> -o <out.svg|out.pdf|out.png> [options]
+    scorefall parts <in.scof> -o <out.svg|out.pdf|out.png> [options]
+    scorefall convert <in.scof> <out.mid> [-m <movement>]
+    scorefall validate <in.scof>
+    scorefall info <in.scof>
+
+Options:
+    -o, --output <file>    Output file (format from extension)
+    -m, --movement <n>     Movement number, starting from 1 (default 1)
+    --paper <a4|letter>    Paper size for PDF (default a4)
+    --dpi <n>              Resolution for PNG (default 96)
+    --json                 Print diagnostics as JSON lines
+
+Exit codes:
+    0  success
+    1  the score has errors
+    2  invalid arguments
+    3  output can't be written";
+
+/// Parsed command-line arguments.
+#[derive(Debug, Default, PartialEq)]
+struct Args {
+    /// Subcommand
+    command: String,
+    /// Input and output files
+    files: Vec<PathBuf>,
+    /// Output file (`-o`)
+    output: Option<PathBuf>,
+    /// Movement index
+    movement: u16,
+    /// PDF paper size
+    paper: Option<PaperSize>,
+    /// PNG resolution
+    dpi: Option<f32>,
+    /// Print JSON diagnostics
+    json: bool,
+}
+
+impl Args {
+    /// Parse command-line arguments (not including the program name).
+    fn parse(args: &[String]) -> Result<Args, String> {
+        let mut iter = args.iter();
+        let mut parsed = Args {
+            command: iter.next().ok_or("missing command")?.clone(),
+            ..Default::default()
+        };
+        while let Some(arg) = iter.next() {
+            let mut value = || {
+                iter.next()
+                    .ok_or_else(|| format!("missing value for `{}`", arg))
+            };
+            match arg.as_str() {
+                "-o" | "--output" => parsed.output = Some(value()?.into()),
+                "-m" | "--movement" => {
+                    let m = value()?;
+                    parsed.movement = m
+                        .parse::<u16>()
+                        .ok()
+                        .and_then(|m| m.checked_sub(1))
+                        .ok_or_else(|| format!("invalid movement `{}`", m))?;
+                }
+                "--paper" => {
+                    parsed.paper = Some(match value()?.as_str() {
+                        "a4" | "A4" => PaperSize::A4,
+                        "letter" => PaperSize::LETTER,
+                        p => return Err(format!("unknown paper `{}`", p)),
+                    })
+                }
+                "--dpi" => {
+                    let dpi = value()?;
+                    parsed.dpi = Some(
+                        dpi.parse()
+                            .ok()
+                            .filter(|d: &f32| *d > 0.0)
+                            .ok_or_else(|| format!("invalid dpi `{}`", dpi))?,
+                    );
+                }
+                "--json" => parsed.json = true,
+                a if a.starts_with('-') => {
+                    return Err(format!("unknown option `{}`", a))
+                }
+                file => parsed.files.push(file.into()),
+            }
+        }
+        Ok(parsed)
+    }
+}
+
+/// Print diagnostics to stderr.
+fn report(diagnostics: &[Diagnostic], json: bool) {
+    for diag in diagnostics {
+        if json {
+            eprintln!("{}", diag.json());
+        } else {
+            eprintln!("{}", diag);
+        }
+    }
+}
+
+/// Load the input score, exiting on errors.
+fn load_input(path: &Path, json: bool) -> Scof {
+    load::load(path).unwrap_or_else(|diagnostics| {
+        report(&diagnostics, json);
+        exit(EXIT_INVALID)
+    })
+}
+
+/// Load the input score, exiting if the selected movement doesn't exist.
+fn load_movement(path: &Path, args: &Args) -> Scof {
+    let scof = load_input(path, args.json);
+    if scof.movement.len() <= args.movement as usize {
+        let file = path.display().to_string();
+        let message = format!(
+            "movement {} doesn't exist (score has {})",
+            args.movement + 1,
+            scof.movement.len()
+        );
+        report(&[Diagnostic::error(file, message)], args.json);
+        exit(EXIT_INVALID);
+    }
+    scof
+}
+
+/// Write an output file, exiting on errors.
+fn write_output(path: &Path, data: &[u8], json: bool) {
+    if let Err(e) = std::fs::write(path, data) {
+        let file = path.display().to_string();
+        report(&[Diagnostic::error(file, e.to_string())], json);
+        exit(EXIT_IO);
+    }
+}
+
+/// Get the path of page `i` of a multi-page output.
+fn page_path(path: &Path, i: usize, pages: usize) -> PathBuf {
+    if pages == 1 {
+        return path.to_path_buf();
+    }
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let ext = path.extension().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!("{}-{}.{}", stem, i + 1, ext))
+}
+
+/// Get the path of part `i` (one file for each player).
+fn part_path(path: &Path, i: usize) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let ext = path.extension().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!("{}-part{}.{}", stem, i + 1, ext))
+}
+
+/// Get the channels of each player's part.  Channels joined by a brace are
+/// played by one player (such as piano).
+fn part_chans(scof: &Scof, movement: u16) -> Vec<Vec<u16>> {
+    let chans = scof.chan_len(&Cursor::new(movement, 0, 0, 0));
+    let mut parts: Vec<Vec<u16>> = vec![];
+    for chan in 0..chans {
+        let braced = scof.style.stave_groups().any(|(grouping, range)| {
+            grouping == Grouping::Brace
+                && range.contains(&chan)
+                && *range.start() < chan
+        });
+        match parts.last_mut() {
+            Some(part) if braced => part.push(chan),
+            _ => parts.push(vec![chan]),
+        }
+    }
+    parts
+}
+
+/// Render a part for each player.
+fn render_parts(args: &Args, scof: &Scof, output: &Path) {
+    for (i, chans) in part_chans(scof, args.movement).iter().enumerate() {
+        let part = scof.extract_part(chans);
+        render(args, &part, &part_path(output, i));
+    }
+}
+
+/// Render a movement to SVG, PDF or PNG.
+fn render(args: &Args, scof: &Scof, output: &Path) {
+    let ext = output.extension().unwrap_or_default().to_string_lossy();
+    let ext = ext.to_lowercase();
+    if !["svg", "pdf", "png"].contains(&ext.as_str()) {
+        eprintln!("scorefall: unknown output format `{}`", ext);
+        exit(EXIT_USAGE);
+    }
+    let (meta, glyphs) = staverator::modern_glyphs();
+    if ext == "pdf" {
+        let options = PdfOptions {
+            movement: args.movement,
+            paper: args.paper.unwrap_or(PaperSize::A4),
+            ..Default::default()
+        };
+        let pdf = staverator::render_pdf(scof, &meta, &glyphs, &options);
+        return write_output(output, &pdf, args.json);
+    }
+    let options = LayoutOptions {
+        movement: args.movement,
+        ..Default::default()
+    };
+    let pages = staverator::layout(scof, &meta, &options);
+    let defs = sfff::generate_defs(&glyphs);
+    let raster = RasterOptions {
+        dpi: args.dpi.unwrap_or(96.0),
+        ..Default::default()
+    };
+    let mut text = false;
+    for (i, page) in pages.iter().enumerate() {
+        let data = if ext == "svg" {
+            page.svg(&defs).into_bytes()
+        } else {
+            text |= page.has_text();
+            page.png(&glyphs, &raster)
+        };
+        write_output(&page_path(output, i, pages.len()), &data, args.json);
+    }
+    if text {
+        eprintln!(
+            "scorefall: warning: text (titles, tempo marks and lyrics) is \
+             left out of PNG images, render SVG or PDF to include it"
+        );
+    }
+}
+
+/// Print information about a score.
+fn info(scof: &Scof) {
+    println!("Title: {}", scof.title);
+    if let Some(subtitle) = &scof.meta.subtitle {
+        println!("Subtitle: {}", subtitle);
+    }
+    println!("Composer: {}", scof.meta.composer);
+    if let Some(lyricist) = &scof.meta.lyricist {
+        println!("Lyricist: {}", lyricist);
+    }
+    for arranger in &scof.meta.arranger {
+        println!("Arranger: {}", arranger.name);
+    }
+    println!("Movements: {}", scof.movement.len());
+    for (m, mvmt) in scof.movement.iter().enumerate() {
+        let m = m as u16;
+        let name = scof.meta.movement.get(m as usize);
+        let name = name.cloned().unwrap_or_else(|| format!("{}", m + 1));
+        let chans = scof.chan_len(&Cursor::new(m, 0, 0, 0));
+        let time = mvmt.sig(0).map(|s| s.time.as_str()).unwrap_or("4/4");
+        let end = scof
+            .note_events(m)
+            .iter()
+            .map(|e| e.start + e.length)
+            .max()
+            .unwrap_or(0);
+        let seconds = scof.seconds(m, end) as u32;
+        println!(
+            "  {}: {} bars, {} channels, {}, {} BPM, {}:{:02}",
+            name,
+            mvmt.bar.len(),
+            chans,
+            time,
+            scof.tempo(m),
+            seconds / 60,
+            seconds % 60
+        );
+    }
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args = match Args::parse(&args) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("scorefall: {}\n\n{}", e, USAGE);
+            exit(EXIT_USAGE);
+        }
+    };
+    let input = match args.files.first() {
+        Some(input) => input,
+        None if args.command == "help" || args.command == "--help" => {
+            println!("{}", USAGE);
+            return;
+        }
+        None => {
+            eprintln!("scorefall: missing input file\n\n{}", USAGE);
+            exit(EXIT_USAGE);
+        }
+    };
+    let file = input.display().to_string();
+
+    match (args.command.as_str(), args.files.len(), &args.output) {
+        ("render", 1, Some(output)) => {
+            let scof = load_movement(input, &args);
+            render(&args, &scof, output);
+        }
+        ("parts", 1, Some(output)) => {
+            let scof = load_movement(input, &args);
+            render_parts(&args, &scof, output);
+        }
+        ("convert", 2, None) => {
+            let scof = load_movement(input, &args);
+            let smf = midi::write(&scof, args.movement);
+            write_output(&args.files[1], &smf, args.json);
+        }
+        ("validate", 1, None) => {
+            let scof = load_input(input, args.json);
+            let diagnostics = validate::check(&scof, &file);
+            report(&diagnostics, args.json);
+            if diagnostics.iter().any(Diagnostic::is_error) {
+                exit(EXIT_INVALID);
+            }
+        }
+        ("info", 1, None) => info(&load_input(input, args.json)),
+        _ => {
+            eprintln!("scorefall: invalid arguments\n\n{}", USAGE);
+            exit(EXIT_USAGE);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Args, String> {
+        let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        Args::parse(&args)
+    }
+
+    #[test]
+    fn arguments() {
+        let args =
+            parse(&["render", "in.scof", "-o", "out.pdf", "-m", "2", "--json"]);
+        assert_eq!(
+            args,
+            Ok(Args {
+                command: "render".to_string(),
+                files: vec!["in.scof".into()],
+                output: Some("out.pdf".into()),
+                movement: 1,
+                json: true,
+                ..Default::default()
+            })
+        );
+        let args = parse(&["render", "in.scof", "--paper", "letter"]).unwrap();
+        assert_eq!(args.paper, Some(PaperSize::LETTER));
+    }
+
+    #[test]
+    fn invalid_arguments() {
+        assert!(parse(&[]).is_err());
+        assert!(parse(&["info", "--bogus"]).is_err());
+        assert!(parse(&["render", "in.scof", "-o"]).is_err());
+        assert!(parse(&["convert", "in.scof", "-m", "0"]).is_err());
+        assert!(parse(&["render", "in.scof", "--dpi", "-5"]).is_err());
+    }
+
+    #[test]
+    fn page_paths() {
+        let out = Path::new("dir/out.png");
+        assert_eq!(page_path(out, 0, 1), Path::new("dir/out.png"));
+        assert_eq!(page_path(out, 1, 3), Path::new("dir/out-2.png"));
+        assert_eq!(part_path(out, 0), Path::new("dir/out-part1.png"));
+    }
+
+    #[test]
+    fn parts() {
+        let mut scof = Scof::default();
+        assert_eq!(part_chans(&scof, 0), vec![vec![0], vec![1]]);
+        scof.style.group.push(scof::StaveGroup {
+            kind: "brace".to_string(),
+            first: 0,
+            last: 1,
+        });
+        assert_eq!(part_chans(&scof, 0), vec![vec![0, 1]]);
+    }
+}
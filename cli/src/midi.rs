@@ -0,0 +1,143 @@
+// ScoreFall Ink - Music Composition Software
+//
+// Copyright (C) 2019-2020 Jeron Aldaron Lau <jeronlau@plopgrizzly.com>
+// Copyright (C) 2019-2020 Doug P. Lau
+//
+//     This program is free software: you can redistribute it and/or modify
+//     it under the terms of the GNU General Public License as published by
+//     the Free Software Foundation, either version 3 of the License, or
+//     (at your option) any later version.
+//
+//     This program is distributed in the hope that it will be useful,
+//     but WITHOUT ANY WARRANTY; without even the implied warranty of
+//     MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//     GNU General Public License for more details.
+//
+//     You should have received a copy of the GNU General Public License
+//     along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+/* ************************************************************************** */
+
+//! Write Standard MIDI Files.
+
+use scof::{Scof, TICKS_PER_WHOLE};
+
+/// Write a variable length quantity.
+fn var_len(out: &mut Vec<u8>, value: u32) {
+    let mut bytes = vec![(value & 0x7F) as u8];
+    let mut value = value >> 7;
+    while value > 0 {
+        bytes.push((value & 0x7F) as u8 | 0x80);
+        value >>= 7;
+    }
+    out.extend(bytes.iter().rev());
+}
+
+/// Write a track chunk from (tick, event bytes) pairs sorted by tick.
+fn track(out: &mut Vec<u8>, events: &[(u32, Vec<u8>)]) {
+    let mut data = vec![];
+    let mut time = 0;
+    for (tick, event) in events {
+        var_len(&mut data, tick - time);
+        data.extend_from_slice(event);
+        time = *tick;
+    }
+    // End of track
+    data.extend_from_slice(&[0x00, 0xFF, 0x2F, 0x00]);
+    out.extend_from_slice(b"MTrk");
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    out.extend_from_slice(&data);
+}
+
+/// Get the MIDI channel for a score channel, skipping the percussion channel.
+fn midi_chan(chan: u16) -> u8 {
+    let chan = (chan % 15) as u8;
+    if chan >= 9 {
+        chan + 1
+    } else {
+        chan
+    }
+}
+
+/// Convert a movement of the score to a format 1 Standard MIDI File, with a
+/// tempo track followed by one track for each channel.
+pub fn write(scof: &Scof, movement: u16) -> Vec<u8> {
+    let notes = scof.note_events(movement);
+    let chans = notes.iter().map(|n| n.chan + 1).max().unwrap_or(0);
+    let division = (TICKS_PER_WHOLE / 4) as u16;
+
+    let mut out = b"MThd".to_vec();
+    out.extend_from_slice(&6u32.to_be_bytes());
+    out.extend_from_slice(&1u16.to_be_bytes());
+    out.extend_from_slice(&(chans + 1).to_be_bytes());
+    out.extend_from_slice(&division.to_be_bytes());
+
+    // Tempo track
+    let tempos: Vec<(u32, Vec<u8>)> = scof
+        .tempo_events(movement)
+        .iter()
+        .map(|event| {
+            let tempo = event.quarter_micros().to_be_bytes();
+            (
+                event.start,
+                vec![0xFF, 0x51, 0x03, tempo[1], tempo[2], tempo[3]],
+            )
+        })
+        .collect();
+    track(&mut out, &tempos);
+
+    for chan in 0..chans {
+        let ch = midi_chan(chan);
+        let mut events = vec![];
+        for note in notes.iter().filter(|n| n.chan == chan) {
+            for key in &note.keys {
+                let end = note.start + note.length;
+                events.push((note.start, vec![0x90 | ch, *key, note.velocity]));
+                events.push((end, vec![0x80 | ch, *key, 0]));
+            }
+        }
+        // Note offs come before note ons at the same time.
+        events.sort_by_key(|(tick, event)| (*tick, event[0] & 0xF0 == 0x90));
+        track(&mut out, &events);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn variable_length() {
+        let mut out = vec![];
+        var_len(&mut out, 0);
+        var_len(&mut out, 0x7F);
+        var_len(&mut out, 0x80);
+        var_len(&mut out, 0x0FFF_FFFF);
+        assert_eq!(out, [0x00, 0x7F, 0x81, 0x00, 0xFF, 0xFF, 0xFF, 0x7F]);
+    }
+
+    #[test]
+    fn channels() {
+        assert_eq!(midi_chan(0), 0);
+        assert_eq!(midi_chan(9), 10);
+        assert_eq!(midi_chan(15), 0);
+    }
+
+    #[test]
+    fn default_score() {
+        let scof = Scof::default();
+        let smf = write(&scof, 0);
+        assert_eq!(
+            smf[..14],
+            [b'M', b'T', b'h', b'd', 0, 0, 0, 6, 0, 1, 0, 3, 0x01, 0xE0]
+        );
+        // 120 BPM = 500000 microseconds per quarter note
+        assert_eq!(smf[22..29], [0x00, 0xFF, 0x51, 0x03, 0x07, 0xA1, 0x20]);
+        assert_eq!(smf.windows(4).filter(|w| w == b"MTrk").count(), 3);
+        // First note: middle C, quarter note long (mezzo forte)
+        let first = smf.windows(3).position(|w| w == [0x90, 60, 80]);
+        let off = smf.windows(4).position(|w| w == [0x83, 0x60, 0x80, 60]);
+        assert_eq!(off, first.map(|f| f + 3));
+    }
+}
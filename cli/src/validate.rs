@@ -0,0 +1,255 @@
+// ScoreFall Ink - Music Composition Software
+//
+// Copyright (C) 2019-2020 Jeron Aldaron Lau <jeronlau@plopgrizzly.com>
+// Copyright (C) 2019-2020 Doug P. Lau
+//
+//     This program is free software: you can redistribute it and/or modify
+//     it under the terms of the GNU General Public License as published by
+//     the Free Software Foundation, either version 3 of the License, or
+//     (at your option) any later version.
+//
+//     This program is distributed in the hope that it will be useful,
+//     but WITHOUT ANY WARRANTY; without even the implied warranty of
+//     MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//     GNU General Public License for more details.
+//
+//     You should have received a copy of the GNU General Public License
+//     along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+/* ************************************************************************** */
+
+//! Check a loaded score for problems.
+
+use scof::{Cursor, Fraction, Marking, Repeat, Scof};
+
+use crate::diagnostic::{Diagnostic, Location};
+
+/// Check the bars of every movement in a score.
+pub fn check(scof: &Scof, file: &str) -> Vec<Diagnostic> {
+    let mut diagnostics = vec![];
+    for (m, mvmt) in scof.movement.iter().enumerate() {
+        let m = m as u16;
+        let loc = Location {
+            movement: m,
+            ..Default::default()
+        };
+        for (s, sig) in mvmt.sig.iter().enumerate() {
+            let valid = sig.time.parse::<Fraction>().map(|t| t.den != 0);
+            if valid != Ok(true) {
+                let message = format!(
+                    "invalid time signature `{}` in signature {}",
+                    sig.time,
+                    s + 1
+                );
+                diagnostics
+                    .push(Diagnostic::error(file, message).at(loc.clone()));
+            }
+        }
+        let chans = scof.chan_len(&Cursor::new(m, 0, 0, 0));
+        for group in &scof.style.group {
+            if group.grouping().is_none() {
+                let message =
+                    format!("invalid stave group kind `{}`", group.kind);
+                diagnostics
+                    .push(Diagnostic::error(file, message).at(loc.clone()));
+            } else if group.first > group.last || group.last >= chans {
+                let message = format!(
+                    "stave group of channels {} to {}, but there are {} \
+                     channels",
+                    group.first + 1,
+                    group.last + 1,
+                    chans
+                );
+                diagnostics
+                    .push(Diagnostic::warning(file, message).at(loc.clone()));
+            }
+        }
+        for bar in 0..mvmt.bar.len() as u16 {
+            let loc = Location {
+                bar: Some(bar),
+                ..loc.clone()
+            };
+            let bar_chans = scof.chan_len(&Cursor::new(m, bar, 0, 0));
+            if bar_chans != chans {
+                let message = format!(
+                    "bar has {} channels, but the first bar has {}",
+                    bar_chans, chans
+                );
+                diagnostics
+                    .push(Diagnostic::warning(file, message).at(loc.clone()));
+            }
+            for repeat in &mvmt.bar[bar as usize].repeat {
+                if repeat.parse::<Repeat>().is_err() {
+                    let message = format!("invalid repeat `{}`", repeat);
+                    diagnostics
+                        .push(Diagnostic::error(file, message).at(loc.clone()));
+                }
+            }
+            let length = mvmt.bar_length(bar);
+            for chan in 0..bar_chans {
+                let cursor = Cursor::new(m, bar, chan, 0);
+                let voices = scof.voice_len(&cursor);
+                for voice in 0..voices {
+                    let loc = Location {
+                        chan: Some(chan),
+                        voice: Some(voice).filter(|_| voices > 1),
+                        ..loc.clone()
+                    };
+                    let cursor = cursor.voice(voice);
+                    check_voice(
+                        scof,
+                        file,
+                        length,
+                        &cursor,
+                        &loc,
+                        &mut diagnostics,
+                    );
+                }
+            }
+        }
+    }
+    diagnostics
+}
+
+/// Check the markings of one voice of a channel in a bar.  Each voice must
+/// fill the bar on its own.
+fn check_voice(
+    scof: &Scof,
+    file: &str,
+    length: Fraction,
+    cursor: &Cursor,
+    loc: &Location,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let len = scof.marking_len(cursor);
+    if len == 0 {
+        // Whole measure rest
+        return;
+    }
+    let mut total = Fraction::new(0, 1);
+    let mut cursor = cursor.clone();
+    for i in 0..len {
+        let marking = scof.marking(&cursor);
+        cursor.right_unchecked();
+        let note = match marking {
+            Some(Marking::Note(note)) => note,
+            _ => continue,
+        };
+        let loc = Location {
+            marking: Some(i),
+            ..loc.clone()
+        };
+        if note.duration.num == 0 || note.duration.den == 0 {
+            let message = format!("invalid duration `{}`", note.duration);
+            diagnostics.push(Diagnostic::error(file, message).at(loc));
+            return;
+        }
+        if note.pitch.iter().any(|p| p.midi().is_none()) {
+            let message = format!("pitch of `{}` is out of range", note);
+            diagnostics.push(Diagnostic::warning(file, message).at(loc));
+        }
+        total += note.duration;
+    }
+    if total > length {
+        let message = format!(
+            "bar is {} long, but the time signature is {}",
+            total, length
+        );
+        diagnostics.push(Diagnostic::error(file, message).at(loc.clone()));
+    } else if total < length {
+        let message = format!(
+            "bar is only {} long, but the time signature is {}",
+            total, length
+        );
+        diagnostics.push(Diagnostic::warning(file, message).at(loc.clone()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::load::{load, tests::score_dir};
+    use scof::StaveGroup;
+
+    #[test]
+    fn default_score() {
+        assert!(check(&Scof::default(), "default").is_empty());
+    }
+
+    #[test]
+    fn bar_lengths() {
+        let mvmt = "sig: 0\n  time: 3/4\n  tempo: 90\nbar:\n  sig: 0\
+            \n  chan: 1/2C4 1/2D4\n  chan: 1/4C4\nbar:\n  chan: 1/2E4 1/4E4\
+            \n  chan: 3/4R\n";
+        let dir = score_dir("bar_lengths", &[("Waltz", mvmt)]);
+        let scof = load(&dir).unwrap();
+        let diagnostics = check(&scof, "waltz");
+        assert_eq!(diagnostics.len(), 2);
+        assert!(diagnostics[0].is_error());
+        assert_eq!(
+            diagnostics[0].to_string(),
+            "error: waltz: movement 1, bar 1, channel 1: \
+             bar is 1/1 long, but the time signature is 3/4"
+        );
+        assert!(!diagnostics[1].is_error());
+        assert_eq!(diagnostics[1].location.as_ref().unwrap().chan, Some(1));
+    }
+
+    #[test]
+    fn stave_groups() {
+        let mut scof = Scof::default();
+        scof.style.group = vec![
+            StaveGroup {
+                kind: "brace".to_string(),
+                first: 0,
+                last: 1,
+            },
+            StaveGroup {
+                kind: "bracket".to_string(),
+                first: 1,
+                last: 2,
+            },
+            StaveGroup {
+                kind: "curly".to_string(),
+                first: 0,
+                last: 1,
+            },
+        ];
+        let diagnostics = check(&scof, "groups");
+        assert_eq!(diagnostics.len(), 2);
+        assert!(!diagnostics[0].is_error());
+        assert_eq!(
+            diagnostics[0].message,
+            "stave group of channels 2 to 3, but there are 2 channels"
+        );
+        assert_eq!(diagnostics[1].message, "invalid stave group kind `curly`");
+    }
+
+    #[test]
+    fn voice_lengths() {
+        let mvmt = "sig: 0\n  time: 4/4\n  tempo: 90\nbar:\
+            \n  chan: 1/2C4 1/2D4\n    voice: 1/2E4\n";
+        let dir = score_dir("voice_lengths", &[("Duet", mvmt)]);
+        let scof = load(&dir).unwrap();
+        let diagnostics = check(&scof, "duet");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].to_string(),
+            "warning: duet: movement 1, bar 1, channel 1, voice 2: \
+             bar is only 1/2 long, but the time signature is 4/4"
+        );
+    }
+
+    #[test]
+    fn repeats() {
+        let mut scof = Scof::default();
+        let bar = &mut scof.movement[0].bar[1];
+        bar.repeat = vec!["Close".to_string(), "Ending".to_string()];
+        let diagnostics = check(&scof, "repeats");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].to_string(),
+            "error: repeats: movement 1, bar 2: invalid repeat `Ending`"
+        );
+    }
+}
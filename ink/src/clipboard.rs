@@ -0,0 +1,61 @@
+// ScoreFall Ink - Music Composition Software
+//
+// Copyright (C) 2019-2020 Jeron Aldaron Lau <jeronlau@plopgrizzly.com>
+// Copyright (C) 2019-2020 Doug P. Lau
+//
+//     This program is free software: you can redistribute it and/or modify
+//     it under the terms of the GNU General Public License as published by
+//     the Free Software Foundation, either version 3 of the License, or
+//     (at your option) any later version.
+//
+//     This program is distributed in the hope that it will be useful,
+//     but WITHOUT ANY WARRANTY; without even the implied warranty of
+//     MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//     GNU General Public License for more details.
+//
+//     You should have received a copy of the GNU General Public License
+//     along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+/* ************************************************************************** */
+
+//! Clipboard text for copying and pasting passages.
+//!
+//! A passage is written in the scof marking text format, with one line for
+//! each channel.  Barlines are not kept; notes are split again when pasted.
+
+use scof::{Cursor, Marking, Scof, Selection};
+
+/// Write the selected markings as clipboard text.
+pub(crate) fn copy(scof: &Scof, selection: &Selection) -> String {
+    let mut lines = vec![];
+    for chan in selection.chans() {
+        let markings: Vec<String> = selection
+            .chan_cursors(scof, chan)
+            .filter_map(|cursor| scof.marking(&cursor).map(Marking::to_string))
+            .collect();
+        lines.push(markings.join(" "));
+    }
+    lines.join("\n")
+}
+
+/// Parse clipboard text into markings for each channel.
+pub(crate) fn parse(text: &str) -> Option<Vec<Vec<Marking>>> {
+    text.lines()
+        .map(|line| {
+            line.split(' ')
+                .filter(|n| !n.is_empty())
+                .map(|n| n.parse().ok())
+                .collect()
+        })
+        .collect()
+}
+
+/// Paste markings for each channel, starting at the time of the cursor in
+/// its bar.  Channels which don't exist are left out.
+pub(crate) fn paste(scof: &mut Scof, cursor: &Cursor, chans: &[Vec<Marking>]) {
+    let offset = scof.offset(cursor);
+    for (i, markings) in chans.iter().enumerate() {
+        let start = scof.split_at(&cursor.chan_offset(i as u16), offset);
+        scof.overwrite(&start, markings);
+    }
+}
@@ -0,0 +1,182 @@
+// ScoreFall Ink - Music Composition Software
+//
+// Copyright (C) 2019-2020 Jeron Aldaron Lau <jeronlau@plopgrizzly.com>
+// Copyright (C) 2019-2020 Doug P. Lau
+//
+//     This program is free software: you can redistribute it and/or modify
+//     it under the terms of the GNU General Public License as published by
+//     the Free Software Foundation, either version 3 of the License, or
+//     (at your option) any later version.
+//
+//     This program is distributed in the hope that it will be useful,
+//     but WITHOUT ANY WARRANTY; without even the implied warranty of
+//     MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//     GNU General Public License for more details.
+//
+//     You should have received a copy of the GNU General Public License
+//     along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+/* ************************************************************************** */
+
+//! Undo / redo history of edits to a score.
+
+use scof::{Cursor, Measure, Scof};
+
+/// A replaced range of bars in a movement.  Applying it backwards undoes the
+/// change.
+#[derive(Clone, Debug)]
+struct BarChange {
+    /// Movement index
+    movement: usize,
+    /// First changed bar
+    start: usize,
+    /// Bars before the change
+    before: Vec<Measure>,
+    /// Bars after the change
+    after: Vec<Measure>,
+}
+
+impl BarChange {
+    /// Find the changed range of bars in a movement (if any).
+    fn diff(
+        movement: usize,
+        old: &[Measure],
+        new: &[Measure],
+    ) -> Option<BarChange> {
+        let start = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+        if start == old.len() && start == new.len() {
+            return None;
+        }
+        let (old_rest, new_rest) = (&old[start..], &new[start..]);
+        let end = old_rest
+            .iter()
+            .rev()
+            .zip(new_rest.iter().rev())
+            .take_while(|(a, b)| a == b)
+            .count();
+        Some(BarChange {
+            movement,
+            start,
+            before: old_rest[..old_rest.len() - end].to_vec(),
+            after: new_rest[..new_rest.len() - end].to_vec(),
+        })
+    }
+
+    /// Apply the change forwards (redo) or backwards (undo).
+    fn apply(&self, scof: &mut Scof, forward: bool) {
+        let (from, to) = if forward {
+            (&self.before, &self.after)
+        } else {
+            (&self.after, &self.before)
+        };
+        if let Some(movement) = scof.movement.get_mut(self.movement) {
+            let range = self.start..self.start + from.len();
+            movement.bar.splice(range, to.iter().cloned());
+        }
+    }
+}
+
+/// One undoable step, which may be made of several edits.
+#[derive(Clone, Debug)]
+struct Step {
+    /// Changed bars
+    changes: Vec<BarChange>,
+    /// Cursor before the step
+    cursor_before: Cursor,
+    /// Cursor after the step
+    cursor_after: Cursor,
+}
+
+/// Bars of every movement, from before a step started.
+struct Snapshot {
+    /// Bars of each movement
+    bars: Vec<Vec<Measure>>,
+    /// Cursor before the step
+    cursor: Cursor,
+}
+
+/// Unbounded undo / redo history.
+#[derive(Default)]
+pub struct History {
+    /// Steps which can be undone (most recent last)
+    undo: Vec<Step>,
+    /// Steps which can be redone (most recent last)
+    redo: Vec<Step>,
+    /// State from the start of the outermost open transaction
+    open: Option<Snapshot>,
+    /// Nesting depth of open transactions
+    depth: u32,
+}
+
+impl History {
+    /// Start a transaction.  Transactions can be nested; all edits until the
+    /// outermost transaction is committed become one undo step.
+    pub fn begin(&mut self, scof: &Scof, cursor: &Cursor) {
+        if self.depth == 0 {
+            self.open = Some(Snapshot {
+                bars: scof.movement.iter().map(|m| m.bar.clone()).collect(),
+                cursor: cursor.clone(),
+            });
+        }
+        self.depth += 1;
+    }
+
+    /// Commit a transaction, recording an undo step if the score changed.
+    pub fn commit(&mut self, scof: &Scof, cursor: &Cursor) {
+        self.depth = self.depth.saturating_sub(1);
+        if self.depth > 0 {
+            return;
+        }
+        let snapshot = match self.open.take() {
+            Some(snapshot) => snapshot,
+            None => return,
+        };
+        let changes: Vec<BarChange> = snapshot
+            .bars
+            .iter()
+            .zip(&scof.movement)
+            .enumerate()
+            .filter_map(|(m, (old, new))| BarChange::diff(m, old, &new.bar))
+            .collect();
+        if !changes.is_empty() {
+            self.undo.push(Step {
+                changes,
+                cursor_before: snapshot.cursor,
+                cursor_after: cursor.clone(),
+            });
+            self.redo.clear();
+        }
+    }
+
+    /// Undo the most recent step, returning the cursor from before it.
+    pub fn undo(&mut self, scof: &mut Scof) -> Option<Cursor> {
+        let step = self.undo.pop()?;
+        for change in step.changes.iter().rev() {
+            change.apply(scof, false);
+        }
+        let cursor = step.cursor_before.clone();
+        self.redo.push(step);
+        Some(cursor)
+    }
+
+    /// Redo the most recently undone step, returning the cursor from after it.
+    pub fn redo(&mut self, scof: &mut Scof) -> Option<Cursor> {
+        let step = self.redo.pop()?;
+        for change in &step.changes {
+            change.apply(scof, true);
+        }
+        let cursor = step.cursor_after.clone();
+        self.undo.push(step);
+        Some(cursor)
+    }
+
+    /// Check if there is a step to undo.
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    /// Check if there is a step to redo.
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+}
@@ -16,14 +16,47 @@
 //     You should have received a copy of the GNU General Public License
 //     along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
-use scof::{Cursor, Fraction, Marking, Note, Pitch, Scof};
+mod clipboard;
+mod history;
+
+pub use history::History;
+
+use scof::{Cursor, Fraction, Marking, Note, Pitch, Scof, Selection};
+
+/// Direction to move a passage in time.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Direction {
+    /// Earlier
+    Left,
+    /// Later
+    Right,
+}
+
+/// Shortest duration which can be notated (128th note).
+const SHORTEST: Fraction = Fraction { num: 1, den: 128 };
+
+/// Longest duration which can be notated (longa).
+const LONGEST: Fraction = Fraction { num: 4, den: 1 };
+
+/// Check if a duration can be notated (as 128th notes, up to a longa).
+fn is_notatable(duration: Fraction) -> bool {
+    let (num, den) = (u32::from(duration.num), u32::from(duration.den));
+    duration >= SHORTEST && duration <= LONGEST && (num * 128) % den == 0
+}
 
 /// This is the entire program context.
 pub struct Program {
-    /// The save file.
+    /// The save file.  Edits made directly (not through `Program`) can't be
+    /// undone.
     pub scof: Scof,
     /// Current cursor
     pub cursor: Cursor,
+    /// Selected range of markings (the cursor is at its head)
+    pub selection: Option<Selection>,
+    /// Copied passage, in scof note text format
+    pub clipboard: Option<String>,
+    /// Undo / redo history
+    history: History,
 }
 
 impl Default for Program {
@@ -31,6 +64,9 @@ impl Default for Program {
         Self {
             scof: Scof::default(),
             cursor: Cursor::default(),
+            selection: None,
+            clipboard: None,
+            history: History::default(),
         }
     }
 }
@@ -43,20 +79,272 @@ impl Program {
 
     /// Move cursor back.
     pub fn left(&mut self) {
+        self.selection = None;
         self.cursor.left(&self.scof);
     }
 
     /// Move cursor forward.
     pub fn right(&mut self) {
-        self.cursor.right(&self.scof);
-        // If measure doesn't exist, make a new one.
-        if self.scof.marking_is_empty(&self.cursor) {
-            self.scof.new_measure();
+        self.selection = None;
+        self.transaction(|program| {
+            program.cursor.right(&program.scof);
+            // If measure doesn't exist, make a new one.
+            if program.scof.marking_is_empty(&program.cursor) {
+                program.scof.new_measure();
+            }
+        });
+    }
+
+    /// Move the cursor to the start of the next bar with a rehearsal mark.
+    /// Returns false if there isn't one.
+    pub fn next_rehearsal(&mut self) -> bool {
+        let bar = self.cursor.bar_index();
+        let next = self.rehearsal_bars().into_iter().find(|b| *b > bar);
+        self.move_to_bar(next)
+    }
+
+    /// Move the cursor to the start of the previous bar with a rehearsal
+    /// mark (or the start of the current one).  Returns false if there isn't
+    /// one.
+    pub fn prev_rehearsal(&mut self) -> bool {
+        let bar = self.cursor.bar_index();
+        let within = self.cursor.marking_index() > 0;
+        let prev = self
+            .rehearsal_bars()
+            .into_iter()
+            .rev()
+            .find(|b| *b < bar || (*b == bar && within));
+        self.move_to_bar(prev)
+    }
+
+    /// Get the bars of the cursor's movement which have a rehearsal mark.
+    fn rehearsal_bars(&self) -> Vec<u16> {
+        self.scof
+            .movement
+            .get(self.cursor.movement_index() as usize)
+            .map(|mvmt| mvmt.rehearsal_bars().collect())
+            .unwrap_or_default()
+    }
+
+    /// Move the cursor to the start of a bar, in the same channel.
+    fn move_to_bar(&mut self, bar: Option<u16>) -> bool {
+        let bar = match bar {
+            Some(bar) => bar,
+            None => return false,
+        };
+        self.selection = None;
+        self.cursor = Cursor::new(
+            self.cursor.movement_index(),
+            bar,
+            self.cursor.chan_index(),
+            0,
+        );
+        true
+    }
+
+    /// Extend the selection from the cursor, moving the cursor to its head.
+    fn select<F: FnOnce(&mut Selection, &Scof)>(&mut self, extend: F) {
+        let cursor = &self.cursor;
+        let selection = self
+            .selection
+            .get_or_insert_with(|| Selection::new(cursor.clone()));
+        extend(selection, &self.scof);
+        self.cursor = selection.head().clone();
+    }
+
+    /// Extend the selection to the previous marking.
+    pub fn select_left(&mut self) {
+        self.select(Selection::extend_left);
+    }
+
+    /// Extend the selection to the next marking.
+    pub fn select_right(&mut self) {
+        self.select(Selection::extend_right);
+    }
+
+    /// Extend the selection to the channel above.
+    pub fn select_up(&mut self) {
+        self.select(Selection::extend_up);
+    }
+
+    /// Extend the selection to the channel below.
+    pub fn select_down(&mut self) {
+        self.select(Selection::extend_down);
+    }
+
+    /// Extend the selection to the start of the bar (or previous bar).
+    pub fn select_bar_left(&mut self) {
+        self.select(|selection, _| selection.extend_bar_left());
+    }
+
+    /// Extend the selection to the end of the bar (or next bar).
+    pub fn select_bar_right(&mut self) {
+        self.select(Selection::extend_bar_right);
+    }
+
+    /// Get the selection, or just the marking at the cursor.
+    fn selected(&self) -> Selection {
+        let cursor = self.cursor.clone();
+        self.selection
+            .clone()
+            .unwrap_or_else(|| Selection::new(cursor))
+    }
+
+    /// Copy the selected passage to the clipboard, returning its text.
+    pub fn copy(&mut self) -> String {
+        let text = clipboard::copy(&self.scof, &self.selected());
+        self.clipboard = Some(text.clone());
+        text
+    }
+
+    /// Copy the selected passage to the clipboard, and replace its notes
+    /// with rests.
+    pub fn cut(&mut self) -> String {
+        let text = self.copy();
+        let selection = self.selected();
+        self.transaction(|program| {
+            for cursor in selection.cursors(&program.scof).collect::<Vec<_>>() {
+                if let Some(Marking::Note(note)) =
+                    program.scof.marking_mut(&cursor)
+                {
+                    note.pitch.clear();
+                }
+            }
+            program.selection = None;
+            program.cursor = selection.start();
+        });
+        text
+    }
+
+    /// Paste the clipboard over the notes starting at the cursor.  Each line
+    /// of the clipboard goes to the next channel down.  Returns false if
+    /// the clipboard is empty or invalid.
+    pub fn paste(&mut self) -> bool {
+        let text = self.clipboard.as_deref();
+        let chans = match text.and_then(clipboard::parse) {
+            Some(chans) => chans,
+            None => return false,
+        };
+        self.selection = None;
+        self.transaction(|program| {
+            clipboard::paste(&mut program.scof, &program.cursor, &chans)
+        });
+        true
+    }
+
+    /// Move the selected passage earlier or later by a duration.
+    ///
+    /// The passage trades places with the music it moves over (back to the
+    /// start of the movement at most), and other markings move with the
+    /// notes they belong to.  Notes split at a barline are tied over it, and
+    /// bars are added to the end of the movement as needed.
+    pub fn shift_selection(&mut self, direction: Direction, amount: Fraction) {
+        let selection = self.selected();
+        let chans = selection.chans();
+        self.transaction(|program| {
+            let scof = &mut program.scof;
+            // Selected time span of each channel
+            let mut spans = vec![];
+            for chan in chans.clone() {
+                let cursors: Vec<Cursor> =
+                    selection.chan_cursors(scof, chan).collect();
+                let (first, last) = match (cursors.first(), cursors.last()) {
+                    (Some(first), Some(last)) => (first.clone(), last.clone()),
+                    _ => continue,
+                };
+                let begin = scof.time(&first);
+                let length = scof.note(&last).map(|note| note.duration);
+                let finish =
+                    scof.time(&last) + length.unwrap_or(Fraction::new(0, 1));
+                spans.push((chan, first, begin, finish));
+            }
+            // Channels move together, to the start of the movement at most.
+            let mut shift = amount;
+            for (_, _, begin, _) in &spans {
+                if direction == Direction::Left && shift > *begin {
+                    shift = *begin;
+                }
+            }
+            let (mut start, mut end) = (None, None);
+            for (chan, first, begin, finish) in spans {
+                let (begin, finish) = match direction {
+                    Direction::Left => {
+                        scof.swap_passages(
+                            &first,
+                            begin - shift,
+                            begin,
+                            finish,
+                        );
+                        (begin - shift, finish - shift)
+                    }
+                    Direction::Right => {
+                        scof.swap_passages(
+                            &first,
+                            begin,
+                            finish,
+                            finish + shift,
+                        );
+                        (begin + shift, finish + shift)
+                    }
+                };
+                if chan == *chans.start() {
+                    start = scof.cursor_at(&first, begin);
+                }
+                if chan == *chans.end() {
+                    end = scof.cursor_before(&first, finish);
+                }
+            }
+            if let (Some(start), Some(end)) = (start, end) {
+                if program.selection.is_some() {
+                    program.selection =
+                        Some(Selection::span(start, end.clone()));
+                }
+                program.cursor = end;
+            }
+        });
+    }
+
+    /// Make edits as a single undo step.  Transactions may be nested.
+    pub fn transaction<F: FnOnce(&mut Self)>(&mut self, edit: F) {
+        self.history.begin(&self.scof, &self.cursor);
+        edit(self);
+        self.history.commit(&self.scof, &self.cursor);
+    }
+
+    /// Undo the last edit.  Returns false if there is nothing to undo.
+    pub fn undo(&mut self) -> bool {
+        if let Some(cursor) = self.history.undo(&mut self.scof) {
+            self.selection = None;
+            self.cursor = cursor;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Redo the last undone edit.  Returns false if there is nothing to redo.
+    pub fn redo(&mut self) -> bool {
+        if let Some(cursor) = self.history.redo(&mut self.scof) {
+            self.selection = None;
+            self.cursor = cursor;
+            true
+        } else {
+            false
         }
     }
 
+    /// Get the undo / redo history.
+    pub fn history(&self) -> &History {
+        &self.history
+    }
+
     /// Step up or down within the key.
     fn move_step(&mut self, up: bool, gran: u8) {
+        self.transaction(|program| program.step(up, gran));
+    }
+
+    /// Step up or down within the key (without recording history).
+    fn step(&mut self, up: bool, gran: u8) {
         let step_up_fn = match gran {
             0 => Note::step_up,
             1 => Note::half_step_up,
@@ -159,6 +447,11 @@ impl Program {
 
     /// Set duration of a note.
     pub fn set_dur(&mut self, dur: Fraction) {
+        self.transaction(|program| program.set_duration(dur));
+    }
+
+    /// Set duration of a note (without recording history).
+    fn set_duration(&mut self, dur: Fraction) {
         if let Some(mark) = self.scof.marking(&self.cursor) {
             match mark {
                 Marking::Dynamic(_) => { /*Do nothing*/ }
@@ -187,6 +480,138 @@ impl Program {
         }
     }
 
+    /// Halve the duration of the note at the cursor, or of every note in the
+    /// selection.  Returns false if a duration would be shorter than a 128th
+    /// note.
+    pub fn halve_duration(&mut self) -> bool {
+        if self.selection.is_some() {
+            self.diminish()
+        } else {
+            self.scale_duration(Fraction::new(1, 2))
+        }
+    }
+
+    /// Double the duration of the note at the cursor, or of every note in the
+    /// selection.  Returns false if a duration would be longer than a longa.
+    pub fn double_duration(&mut self) -> bool {
+        if self.selection.is_some() {
+            self.augment()
+        } else {
+            self.scale_duration(Fraction::new(2, 1))
+        }
+    }
+
+    /// Scale the duration of the note at the cursor.  A longer note replaces
+    /// the notes after it (tied into following bars), and a shorter note is
+    /// followed by a rest.
+    fn scale_duration(&mut self, factor: Fraction) -> bool {
+        let note = match self.scof.note(&self.cursor) {
+            Some(note) => note.clone(),
+            None => return false,
+        };
+        let duration = note.duration * factor;
+        if !is_notatable(duration) {
+            return false;
+        }
+        let mut markings = vec![Marking::Note(Note {
+            duration,
+            ..note.clone()
+        })];
+        if duration < note.duration {
+            markings.push(Marking::Note(Note {
+                pitch: vec![],
+                duration: note.duration - duration,
+                articulation: vec![],
+            }));
+        }
+        self.transaction(|program| {
+            program.scof.overwrite(&program.cursor, &markings)
+        });
+        true
+    }
+
+    /// Double the durations of the selected passage, replacing the notes
+    /// after it.  Returns false if a duration would be longer than a longa.
+    pub fn augment(&mut self) -> bool {
+        self.scale_selection(Fraction::new(2, 1))
+    }
+
+    /// Halve the durations of the selected passage, filling the rest of its
+    /// time with a rest.  Returns false if a duration would be shorter than a
+    /// 128th note.
+    pub fn diminish(&mut self) -> bool {
+        self.scale_selection(Fraction::new(1, 2))
+    }
+
+    /// Scale the durations of the notes of the selected passage in each
+    /// channel.  Other markings stay with their notes, and notes which spill
+    /// over a barline are tied over it.
+    fn scale_selection(&mut self, factor: Fraction) -> bool {
+        let selection = self.selected();
+        let chans = selection.chans();
+        let mut passages = vec![];
+        for chan in chans.clone() {
+            let cursors: Vec<Cursor> =
+                selection.chan_cursors(&self.scof, chan).collect();
+            let (first, last) = match (cursors.first(), cursors.last()) {
+                (Some(first), Some(last)) => (first.clone(), last.clone()),
+                _ => continue,
+            };
+            let begin = self.scof.time(&first);
+            let length = self.scof.note(&last).map(|note| note.duration);
+            let finish =
+                self.scof.time(&last) + length.unwrap_or(Fraction::new(0, 1));
+            let markings = self
+                .scof
+                .passages(&first, &[begin, finish])
+                .pop()
+                .unwrap_or_default();
+            for marking in &markings {
+                if let Marking::Note(note) = marking {
+                    if !is_notatable(note.duration * factor) {
+                        return false;
+                    }
+                }
+            }
+            passages.push((chan, first, finish - begin, markings));
+        }
+        self.transaction(|program| {
+            let scof = &mut program.scof;
+            let (mut start, mut end) = (None, None);
+            for (chan, first, length, mut markings) in passages {
+                for marking in markings.iter_mut() {
+                    if let Marking::Note(note) = marking {
+                        note.duration *= factor;
+                    }
+                }
+                let scaled = length * factor;
+                if scaled < length {
+                    markings.push(Marking::Note(Note {
+                        pitch: vec![],
+                        duration: length - scaled,
+                        articulation: vec![],
+                    }));
+                }
+                let begin = scof.time(&first);
+                scof.overwrite(&first, &markings);
+                if chan == *chans.start() {
+                    start = scof.cursor_at(&first, begin);
+                }
+                if chan == *chans.end() {
+                    end = scof.cursor_before(&first, begin + scaled);
+                }
+            }
+            if let (Some(start), Some(end)) = (start, end) {
+                if program.selection.is_some() {
+                    program.selection =
+                        Some(Selection::span(start, end.clone()));
+                }
+                program.cursor = end;
+            }
+        });
+        true
+    }
+
     /// Set duration of a note to tuplet.
     pub fn tuplet(&mut self) {
         // FIXME
@@ -197,3 +622,416 @@ impl Program {
         // FIXME
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn undo_pitch() {
+        let mut program = Program::new();
+        program.up_step();
+        program.up_half_step();
+        assert_ne!(program.scof, Scof::default());
+        assert!(program.undo());
+        assert!(program.undo());
+        assert!(!program.undo());
+        assert_eq!(program.scof, Scof::default());
+        assert!(program.redo());
+        assert!(program.redo());
+        assert!(!program.redo());
+        let mut expected = Program::new();
+        expected.up_step();
+        expected.up_half_step();
+        assert_eq!(program.scof, expected.scof);
+    }
+
+    #[test]
+    fn undo_spilled_duration() {
+        let mut program = Program::new();
+        let bars = program.scof.movement[0].bar.len();
+        // A breve spills into the next bar, adding a new bar at the end.
+        program.set_dur(Fraction::new(2, 1));
+        assert_eq!(program.scof.movement[0].bar.len(), bars + 1);
+        assert!(program.history().can_undo());
+        assert!(program.undo());
+        assert!(!program.history().can_undo());
+        assert_eq!(program.scof, Scof::default());
+        assert!(program.redo());
+        assert_eq!(program.scof.movement[0].bar.len(), bars + 1);
+    }
+
+    #[test]
+    fn undo_cursor() {
+        let mut program = Program::new();
+        program.right();
+        program.right();
+        let cursor = program.cursor.clone();
+        program.down_step();
+        program.right();
+        program.undo();
+        assert_eq!(program.cursor, cursor);
+        program.undo();
+        assert_eq!(program.cursor, cursor);
+        assert_eq!(program.scof, Scof::default());
+        // Moving the cursor within existing bars isn't an edit.
+        assert!(!program.history().can_undo());
+    }
+
+    #[test]
+    fn transactions() {
+        let mut program = Program::new();
+        program.transaction(|p| {
+            p.up_step();
+            p.set_dur(Fraction::new(1, 8));
+            p.transaction(|p| p.up_quarter_step());
+        });
+        program.up_step();
+        assert!(program.undo());
+        assert!(program.undo());
+        assert!(!program.history().can_undo());
+        assert_eq!(program.scof, Scof::default());
+        // A new edit clears redo.
+        program.down_step();
+        assert!(!program.history().can_redo());
+        assert!(!program.redo());
+    }
+
+    #[test]
+    fn select() {
+        let mut program = Program::new();
+        program.right();
+        program.select_right();
+        program.select_down();
+        assert_eq!(program.cursor, Cursor::new(0, 0, 1, 2));
+        let selection = program.selection.clone().unwrap();
+        assert_eq!(selection.anchor(), &Cursor::new(0, 0, 0, 1));
+        assert_eq!(selection.cursors(&program.scof).count(), 4);
+        program.select_bar_right();
+        assert_eq!(program.cursor, Cursor::new(0, 0, 1, 4));
+        program.left();
+        assert_eq!(program.selection, None);
+        assert_eq!(program.cursor, Cursor::new(0, 0, 1, 3));
+    }
+
+    #[test]
+    fn rehearsals() {
+        let mut program = Program::new();
+        program.scof.movement[0].bar[1].rehearsal = Some("A".to_string());
+        program.scof.movement[0].bar[3].rehearsal = Some("*".to_string());
+        program.cursor = Cursor::new(0, 0, 1, 1);
+        assert!(program.next_rehearsal());
+        assert_eq!(program.cursor, Cursor::new(0, 1, 1, 0));
+        assert!(program.next_rehearsal());
+        assert_eq!(program.cursor, Cursor::new(0, 3, 1, 0));
+        assert!(!program.next_rehearsal());
+        program.right();
+        assert!(program.prev_rehearsal());
+        assert_eq!(program.cursor, Cursor::new(0, 3, 1, 0));
+        assert!(program.prev_rehearsal());
+        assert_eq!(program.cursor, Cursor::new(0, 1, 1, 0));
+        assert!(!program.prev_rehearsal());
+        // Moving between rehearsal marks isn't an edit.
+        assert!(!program.history().can_undo());
+    }
+
+    /// Get the notes of a bar as text.
+    fn bar(program: &Program, bar: u16, chan: u16) -> String {
+        let cursor = Cursor::new(0, bar, chan, 0);
+        (0..program.scof.marking_len(&cursor))
+            .map(|i| Cursor::new(0, bar, chan, i))
+            .map(|c| program.scof.marking(&c).unwrap().to_string())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Create a program with one channel of 4/4 bars.
+    fn with_bars(bars: &[&str]) -> Program {
+        let mut muon = "sig: 0\n  time: 4/4\n  tempo: 120\n".to_string();
+        for notes in bars {
+            muon.push_str(&format!("bar:\n  chan: {}\n", notes));
+        }
+        let mut program = Program::new();
+        program.scof.movement = vec![scof::Movement::from_muon(&muon).unwrap()];
+        program
+    }
+
+    #[test]
+    fn copy_paste() {
+        let mut program = Program::new();
+        program.select_right();
+        program.select_down();
+        assert_eq!(program.copy(), "1/4C4 1/16D4\n1/8A3 1/8A3");
+        // Paste at the 1/2A3 in the last beats of bar 1.
+        program.cursor = Cursor::new(0, 0, 0, 5);
+        assert!(program.paste());
+        let notes = "1/4C4 1/16D4 1/16D4 1/16D4 1/16D4 1/4C4 1/16D4 3/16A3";
+        assert_eq!(bar(&program, 0, 0), notes);
+        let notes = "1/8A3 1/8A3 1/8A3 1/8A3 1/8A3 1/8A3 1/4A3";
+        assert_eq!(bar(&program, 0, 1), notes);
+        assert!(program.undo());
+        assert_eq!(program.scof, Scof::default());
+    }
+
+    #[test]
+    fn copy_markings() {
+        let mut program = with_bars(&[
+            "mf 1/4C4 cresc 1/4D4 ` {1/8B4 1/4E4 1/4F4",
+            "ff 1/1G4",
+        ]);
+        for _ in 0..6 {
+            program.select_right();
+        }
+        let markings = "mf 1/4C4 cresc 1/4D4 ` {1/8B4 1/4E4";
+        assert_eq!(program.copy(), markings);
+        program.cursor = Cursor::new(0, 1, 0, 1);
+        assert!(program.paste());
+        assert_eq!(bar(&program, 1, 0), format!("{} 1/4G4", markings));
+    }
+
+    #[test]
+    fn paste_across_bars() {
+        let mut program = Program::new();
+        program.clipboard = Some("1/2C4 1/1E4\n1/2G4".to_string());
+        // Offset of 1/2 in the last bar, a new bar is added.
+        program.cursor = Cursor::new(0, 3, 0, 4);
+        assert!(program.paste());
+        assert_eq!(program.scof.movement[0].bar.len(), 5);
+        assert_eq!(bar(&program, 3, 0), "1/8B3 1/8B3 1/8B3 1/8B3 1/2C4");
+        assert_eq!(bar(&program, 4, 0), "1/1E4");
+        assert_eq!(bar(&program, 3, 1), "1/2R 1/2G4");
+        program.clipboard = Some("1/4Q4".to_string());
+        assert!(!program.paste());
+    }
+
+    #[test]
+    fn cut() {
+        let mut program = Program::new();
+        program.cursor = Cursor::new(0, 1, 1, 0);
+        program.select_right();
+        assert_eq!(program.cut(), "1/2B3 1/2B3");
+        assert_eq!(program.selection, None);
+        assert_eq!(bar(&program, 1, 1), "1/2R 1/2R");
+        program.cursor = Cursor::new(0, 0, 1, 4);
+        assert!(program.paste());
+        assert_eq!(bar(&program, 0, 1), "1/8A3 1/8A3 1/8A3 1/8A3 1/2B3");
+        assert_eq!(bar(&program, 1, 1), "1/2B3 1/2R");
+    }
+
+    #[test]
+    fn shift_later() {
+        let mut program = Program::new();
+        program.select_right();
+        program.shift_selection(Direction::Right, Fraction::new(1, 8));
+        let notes = "1/16D4 1/16D4 1/4C4 1/16D4 1/16D4 1/2A3";
+        assert_eq!(bar(&program, 0, 0), notes);
+        let selection = program.selection.clone().unwrap();
+        assert_eq!(selection.start(), Cursor::new(0, 0, 0, 2));
+        assert_eq!(selection.end(), Cursor::new(0, 0, 0, 3));
+
+        // Notes split by a barline are tied over it.
+        program.shift_selection(Direction::Right, Fraction::new(3, 4));
+        let notes = "1/16D4 1/16D4 1/16D4 1/2A3 1/16D4 1/16D4 1/16D4 1/8C4)";
+        assert_eq!(bar(&program, 0, 0), notes);
+        let notes =
+            "1/8C4 1/16D4 1/16D4 1/16D4 1/16D4 1/16D4 1/16D4 1/4R 1/4B3";
+        assert_eq!(bar(&program, 1, 0), notes);
+        assert_eq!(program.scof.movement[0].bar.len(), 4);
+        let selection = program.selection.clone().unwrap();
+        assert_eq!(selection.start(), Cursor::new(0, 0, 0, 7));
+        assert_eq!(selection.end(), Cursor::new(0, 1, 0, 1));
+
+        // Past the end of the movement, bars are added.
+        program.cursor = Cursor::new(0, 3, 0, 6);
+        program.selection = None;
+        program.shift_selection(Direction::Right, Fraction::new(1, 8));
+        let notes = "1/8B3 1/8B3 1/8B3 1/8B3 1/8B3 1/8B3 1/8R 1/8A3)";
+        assert_eq!(bar(&program, 3, 0), notes);
+        assert_eq!(bar(&program, 4, 0), "1/8A3 7/8R");
+        // Other channels get whole measure rests in the new bar.
+        assert_eq!(bar(&program, 4, 1), "");
+        assert_eq!(program.cursor, Cursor::new(0, 4, 0, 0));
+        assert!(program.undo());
+        assert!(program.undo());
+        assert!(program.undo());
+        assert_eq!(program.scof, Scof::default());
+    }
+
+    #[test]
+    fn shift_earlier() {
+        let mut program = Program::new();
+        // The cursor is at marking 5, so selecting down selects from marking
+        // 4 (the last 1/16D4 and 1/2A3 of channel 0, and 1/2A3 of channel 1).
+        program.cursor = Cursor::new(0, 0, 0, 5);
+        program.select_down();
+        program.shift_selection(Direction::Left, Fraction::new(1, 8));
+        let notes = "1/4C4 1/16D4 1/16D4 1/2A3 1/16D4 1/16D4";
+        assert_eq!(bar(&program, 0, 0), notes);
+        assert_eq!(bar(&program, 0, 1), "1/8A3 1/8A3 1/8A3 1/2A3 1/8A3");
+        let selection = program.selection.clone().unwrap();
+        assert_eq!(selection.start(), Cursor::new(0, 0, 0, 2));
+        assert_eq!(selection.end(), Cursor::new(0, 0, 1, 3));
+        assert_eq!(program.cursor, Cursor::new(0, 0, 1, 3));
+
+        // Can't move before the start of the movement, in any channel.
+        program.shift_selection(Direction::Left, Fraction::new(1, 1));
+        let notes = "1/16C4 1/16D4 1/2A3 3/16C4 1/16D4 1/16D4 1/16D4";
+        assert_eq!(bar(&program, 0, 0), notes);
+        assert_eq!(bar(&program, 0, 1), "1/8A3 1/2A3 1/8A3 1/8A3 1/8A3");
+        assert_eq!(program.cursor, Cursor::new(0, 0, 1, 1));
+    }
+
+    #[test]
+    fn shift_markings() {
+        let mut program = with_bars(&[
+            "mf 1/4C4 cresc 1/4D4 ` {1/8B4 1/4E4 1/4F4",
+            "1/2R ff 1/2G4",
+        ]);
+        for _ in 0..6 {
+            program.select_right();
+        }
+        program.shift_selection(Direction::Right, Fraction::new(1, 4));
+        let bar0 = "1/4F4 mf 1/4C4 cresc 1/4D4 ` {1/8B4 1/4E4";
+        assert_eq!(bar(&program, 0, 0), bar0);
+        assert_eq!(bar(&program, 1, 0), "1/2R ff 1/2G4");
+
+        program.shift_selection(Direction::Right, Fraction::new(1, 8));
+        let bar0 = "1/4F4 1/8R mf 1/4C4 cresc 1/4D4 ` {1/8B4 1/8E4)";
+        assert_eq!(bar(&program, 0, 0), bar0);
+        assert_eq!(bar(&program, 1, 0), "1/8E4 3/8R ff 1/2G4");
+
+        program.shift_selection(Direction::Right, Fraction::new(3, 8));
+        assert_eq!(bar(&program, 0, 0), "1/4F4 1/2R mf 1/4C4");
+        let bar1 = "cresc 1/4D4 ` {1/8B4 1/4E4 ff 1/2G4";
+        assert_eq!(bar(&program, 1, 0), bar1);
+
+        program.shift_selection(Direction::Left, Fraction::new(3, 4));
+        let bar0 = "mf 1/4C4 cresc 1/4D4 ` {1/8B4 1/4E4 1/4F4";
+        assert_eq!(bar(&program, 0, 0), bar0);
+        assert_eq!(bar(&program, 1, 0), "1/2R ff 1/2G4");
+    }
+
+    #[test]
+    fn notatable() {
+        assert!(is_notatable(Fraction::new(1, 128)));
+        assert!(is_notatable(Fraction::new(3, 128)));
+        assert!(is_notatable(Fraction::new(4, 1)));
+        assert!(!is_notatable(Fraction::new(1, 256)));
+        assert!(!is_notatable(Fraction::new(3, 256)));
+        assert!(!is_notatable(Fraction::new(6, 1)));
+    }
+
+    #[test]
+    fn halve_double() {
+        let mut program = Program::new();
+        assert!(program.halve_duration());
+        assert!(bar(&program, 0, 0).starts_with("1/8C4 1/8R 1/16D4"));
+        assert!(program.double_duration());
+        assert_eq!(bar(&program, 0, 0), bar(&Program::new(), 0, 0));
+        assert!(program.double_duration());
+        assert_eq!(bar(&program, 0, 0), "1/2C4 1/2A3");
+        assert!(program.double_duration());
+        // A breve spills into the next bar, tied over the barline.
+        assert!(program.double_duration());
+        assert_eq!(bar(&program, 0, 0), "1/1C4)");
+        assert_eq!(bar(&program, 1, 0), "1/1C4");
+        assert_eq!(bar(&program, 2, 0), bar(&Program::new(), 2, 0));
+        assert_eq!(program.scof.movement[0].bar.len(), 4);
+        // Longas only fit in a single bar with a long time signature.
+        if let Some(Marking::Note(note)) =
+            program.scof.marking_mut(&program.cursor)
+        {
+            note.duration = Fraction::new(4, 1);
+        }
+        assert!(!program.double_duration());
+
+        program.set_dur(Fraction::new(1, 128));
+        assert!(!program.halve_duration());
+    }
+
+    #[test]
+    fn dotted() {
+        let mut program = Program::new();
+        program.cursor = Cursor::new(0, 0, 1, 0);
+        program.set_dur(Fraction::new(3, 16));
+        assert!(program.halve_duration());
+        assert!(bar(&program, 0, 1).starts_with("3/32A3 3/32R 1/16A3"));
+        assert!(program.double_duration());
+        assert!(program.double_duration());
+        assert_eq!(bar(&program, 0, 1), "3/8A3 1/8A3 1/2A3");
+    }
+
+    #[test]
+    fn scale_markings() {
+        let mut program =
+            with_bars(&["mf 1/4C4 cresc 1/4D4 ` {1/8B4 1/4E4 1/4F4", "1/1G4"]);
+        program.cursor = Cursor::new(0, 0, 0, 2);
+        for _ in 0..4 {
+            program.select_right();
+        }
+        // Other markings stay with their notes, and the last note is tied
+        // into the next bar.
+        assert!(program.augment());
+        let bar0 = "mf 1/4C4 cresc 1/2D4 ` {1/8B4 1/4E4)";
+        assert_eq!(bar(&program, 0, 0), bar0);
+        assert_eq!(bar(&program, 1, 0), "1/4E4 3/4G4");
+        assert!(program.diminish());
+        let bar0 = "mf 1/4C4 cresc 1/4D4 ` {1/8B4 1/4E4 1/4R";
+        assert_eq!(bar(&program, 0, 0), bar0);
+        assert_eq!(bar(&program, 1, 0), "1/4R 3/4G4");
+    }
+
+    #[test]
+    fn augment_diminish() {
+        let mut program = Program::new();
+        program.cursor = Cursor::new(0, 0, 0, 1);
+        for _ in 0..3 {
+            program.select_right();
+        }
+        assert!(program.halve_duration());
+        let notes = "1/4C4 1/32D4 1/32D4 1/32D4 1/32D4 1/8R 1/2A3";
+        assert_eq!(bar(&program, 0, 0), notes);
+        let selection = program.selection.clone().unwrap();
+        assert_eq!(selection.end(), Cursor::new(0, 0, 0, 4));
+        assert!(program.double_duration());
+        assert_eq!(bar(&program, 0, 0), bar(&Program::new(), 0, 0));
+        assert!(program.augment());
+        assert_eq!(bar(&program, 0, 0), "1/4C4 1/8D4 1/8D4 1/8D4 1/8D4 1/4A3");
+        // Spill into the next bar.
+        assert!(program.augment());
+        assert_eq!(bar(&program, 0, 0), "1/4C4 1/4D4 1/4D4 1/4D4");
+        let notes = "1/4D4 1/16D4 1/16D4 1/16D4 1/16D4 1/4R 1/4B3";
+        assert_eq!(bar(&program, 1, 0), notes);
+        let selection = program.selection.clone().unwrap();
+        assert_eq!(selection.start(), Cursor::new(0, 0, 0, 1));
+        assert_eq!(selection.end(), Cursor::new(0, 1, 0, 0));
+        while program.undo() {}
+        assert_eq!(program.scof, Scof::default());
+    }
+
+    #[test]
+    fn voice_selection() {
+        let mut program =
+            with_bars(&["1/1C4\n    voice: 1/4E4 1/4F4 1/4G4 1/4A4"]);
+        let voice = |program: &Program| {
+            let chan = &program.scof.movement[0].bar[0].chan[0];
+            let markings = chan.voice(1).unwrap().iter();
+            markings
+                .map(|m| m.to_string())
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        program.cursor = Cursor::new(0, 0, 0, 0).voice(1);
+        program.right();
+        program.select_right();
+        assert_eq!(program.copy(), "1/4F4 1/4G4");
+        program.shift_selection(Direction::Right, Fraction::new(1, 4));
+        assert_eq!(voice(&program), "1/4E4 1/4A4 1/4F4 1/4G4");
+        assert_eq!(bar(&program, 0, 0), "1/1C4");
+        assert_eq!(program.cursor.voice_index(), 1);
+        // Cutting leaves the cursor in the voice.
+        program.cut();
+        assert_eq!(voice(&program), "1/4E4 1/4A4 1/4R 1/4R");
+        assert_eq!(program.cursor.voice_index(), 1);
+    }
+}
@@ -0,0 +1,472 @@
+//! Dynamics (how strongly notes are played) and hairpins
+
+use std::str::FromStr;
+
+use crate::playback::ticks;
+use crate::{Cursor, Dynamic, Marking, Scof};
+
+/// Velocity before the first dynamic of a channel (mezzo forte).
+const DEFAULT_VELOCITY: u8 = 80;
+/// Change in velocity of a hairpin without a dynamic to end at.
+const HAIRPIN_VELOCITY: u8 = 16;
+
+impl FromStr for Dynamic {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        use Dynamic::*;
+        Ok(match s {
+            "pppppp" => PPPPPP,
+            "ppppp" => PPPPP,
+            "pppp" => PPPP,
+            "ppp" => PPP,
+            "pp" => PP,
+            "p" => P,
+            "mp" => MP,
+            "mf" => MF,
+            "f" => F,
+            "ff" => FF,
+            "fff" => FFF,
+            "ffff" => FFFF,
+            "fffff" => FFFFF,
+            "ffffff" => FFFFFF,
+            "n" => N,
+            "sf" => SF,
+            "sfz" => SFZ,
+            "fp" => FP,
+            "sfp" => SFP,
+            _ => return Err(()),
+        })
+    }
+}
+
+impl Dynamic {
+    /// Get the text of the dynamic, as engraved.
+    pub fn text(&self) -> &'static str {
+        use Dynamic::*;
+        match self {
+            PPPPPP => "pppppp",
+            PPPPP => "ppppp",
+            PPPP => "pppp",
+            PPP => "ppp",
+            PP => "pp",
+            P => "p",
+            MP => "mp",
+            MF => "mf",
+            F => "f",
+            FF => "ff",
+            FFF => "fff",
+            FFFF => "ffff",
+            FFFFF => "fffff",
+            FFFFFF => "ffffff",
+            N => "n",
+            SF => "sf",
+            SFZ => "sfz",
+            FP => "fp",
+            SFP => "sfp",
+        }
+    }
+
+    /// Get the MIDI velocity of the note where the dynamic is marked.
+    pub fn velocity(&self) -> u8 {
+        use Dynamic::*;
+        match self {
+            PPPPPP => 6,
+            PPPPP => 12,
+            PPPP => 20,
+            PPP => 28,
+            PP => 38,
+            P => 49,
+            MP => 64,
+            MF => 80,
+            F => 96,
+            FF => 108,
+            FFF => 116,
+            FFFF => 121,
+            FFFFF => 125,
+            FFFFFF => 127,
+            N => 0,
+            SF | SFP => 112,
+            SFZ => 120,
+            FP => 96,
+        }
+    }
+
+    /// Get the velocity of the notes after the one where the dynamic is
+    /// marked, or `None` if an accent (returning to the previous dynamic).
+    fn velocity_after(&self) -> Option<u8> {
+        match self {
+            Dynamic::SF | Dynamic::SFZ => None,
+            Dynamic::FP | Dynamic::SFP => Some(Dynamic::P.velocity()),
+            dynamic => Some(dynamic.velocity()),
+        }
+    }
+}
+
+/// A crescendo or decrescendo hairpin, from a `cresc` or `dim` marking to the
+/// next dynamic or hairpin of the same voice (which may be in a later bar).
+/// Without one to end at, a hairpin ends with the movement.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Hairpin {
+    /// Whether the hairpin opens (crescendo) or closes (decrescendo)
+    pub cresc: bool,
+    /// Marking where the hairpin starts
+    pub start: Cursor,
+    /// Marking where the hairpin ends (one past the last marking of the voice
+    /// if it ends with the movement)
+    pub end: Cursor,
+}
+
+impl Hairpin {
+    /// Check if the hairpin starts, ends or passes through a bar.
+    pub fn crosses(&self, bar: u16) -> bool {
+        self.start.bar <= bar && bar <= self.end.bar
+    }
+}
+
+/// A change of dynamic in a channel.
+#[derive(Clone, Debug)]
+struct Change {
+    /// Time of the change (in ticks from the start of the movement)
+    at: u32,
+    /// Dynamic, or `None` for the start of a hairpin
+    dynamic: Option<Dynamic>,
+    /// Whether a hairpin is a crescendo
+    cresc: bool,
+}
+
+/// A point of a dynamic envelope, where a dynamic or hairpin is marked.
+#[derive(Clone, Debug)]
+struct Point {
+    /// Time of the point (in ticks from the start of the movement)
+    at: u32,
+    /// Velocity of a marking at the point, if a dynamic is marked
+    marked: Option<f32>,
+    /// Velocity after the point
+    start: f32,
+    /// Velocity at the end of a hairpin
+    target: f32,
+    /// Time at the end of a hairpin
+    end: u32,
+}
+
+/// The dynamic envelope of a channel through a movement.
+#[derive(Clone, Debug)]
+pub(crate) struct Envelope {
+    /// Time at the start of each bar (as written, without repeats)
+    bar_start: Vec<u32>,
+    /// Points where the dynamic changes, in order
+    points: Vec<Point>,
+}
+
+impl Envelope {
+    /// Create an envelope from the changes of dynamic in a channel, ending
+    /// hairpins at the next change (or the `end` of the movement).
+    fn new(bar_start: Vec<u32>, changes: &[Change], end: u32) -> Self {
+        let mut points: Vec<Point> = vec![];
+        let mut velocity = f32::from(DEFAULT_VELOCITY);
+        for (i, change) in changes.iter().enumerate() {
+            let mut point = Point {
+                at: change.at,
+                marked: None,
+                start: velocity,
+                target: velocity,
+                end: change.at,
+            };
+            match &change.dynamic {
+                Some(dynamic) => {
+                    point.marked = Some(f32::from(dynamic.velocity()));
+                    if let Some(after) = dynamic.velocity_after() {
+                        velocity = f32::from(after);
+                    }
+                    point.start = velocity;
+                    point.target = velocity;
+                }
+                None => {
+                    let step = f32::from(HAIRPIN_VELOCITY);
+                    let step = if change.cresc { step } else { -step };
+                    let (end, target) = match changes.get(i + 1) {
+                        Some(Change {
+                            at,
+                            dynamic: Some(dynamic),
+                            ..
+                        }) => (*at, f32::from(dynamic.velocity())),
+                        Some(next) => (next.at, velocity + step),
+                        None => (end, velocity + step),
+                    };
+                    velocity = target.clamp(0.0, 127.0);
+                    point.target = velocity;
+                    point.end = end;
+                }
+            }
+            // A dynamic marked with a hairpin at the same time still applies.
+            if let Some(last) = points.last().filter(|p| p.at == point.at) {
+                point.marked = point.marked.or(last.marked);
+            }
+            points.push(point);
+        }
+        Envelope { bar_start, points }
+    }
+
+    /// Get the envelope at a time in ticks from the start of a bar, from 0.0
+    /// (silent) to 1.0 (loudest).
+    pub(crate) fn level(&self, bar: u16, time: u32) -> f32 {
+        let at = match self.bar_start.get(bar as usize) {
+            Some(start) => start + time,
+            None => return f32::from(DEFAULT_VELOCITY) / 127.0,
+        };
+        let i = self.points.partition_point(|p| p.at <= at);
+        let point = match i.checked_sub(1) {
+            Some(i) => &self.points[i],
+            None => return f32::from(DEFAULT_VELOCITY) / 127.0,
+        };
+        if let (true, Some(marked)) = (point.at == at, point.marked) {
+            return marked / 127.0;
+        }
+        let span = point.end.saturating_sub(point.at).max(1) as f32;
+        let amount = ((at - point.at) as f32 / span).min(1.0);
+        let velocity = point.start + (point.target - point.start) * amount;
+        velocity.clamp(0.0, 127.0) / 127.0
+    }
+}
+
+impl Scof {
+    /// Get the hairpins of a movement, in order within each voice.
+    pub fn hairpins(&self, movement: u16) -> Vec<Hairpin> {
+        let mut hairpins = vec![];
+        let mvmt = match self.movement.get(movement as usize) {
+            Some(mvmt) => mvmt,
+            None => return hairpins,
+        };
+        let chans = mvmt.bar.iter().map(|m| m.chan.len()).max().unwrap_or(0);
+        for chan in 0..chans as u16 {
+            for voice in 0..self.chan_voices(movement, chan) {
+                let mut open: Option<Hairpin> = None;
+                for (bar, measure) in mvmt.bar.iter().enumerate() {
+                    let markings = measure
+                        .chan
+                        .get(chan as usize)
+                        .and_then(|c| c.voice(voice));
+                    let markings = match markings {
+                        Some(markings) => markings,
+                        None => continue,
+                    };
+                    let curs =
+                        Cursor::new(movement, bar as u16, chan, 0).voice(voice);
+                    for (i, marking) in markings.iter().enumerate() {
+                        let curs = Cursor {
+                            marking: i as u16,
+                            ..curs.clone()
+                        };
+                        let cresc = match marking {
+                            Marking::Cresc => true,
+                            Marking::Dim => false,
+                            Marking::Dynamic(_) => {
+                                if let Some(mut hairpin) = open.take() {
+                                    hairpin.end = curs;
+                                    hairpins.push(hairpin);
+                                }
+                                continue;
+                            }
+                            _ => continue,
+                        };
+                        if let Some(mut hairpin) = open.take() {
+                            hairpin.end = curs.clone();
+                            hairpins.push(hairpin);
+                        }
+                        open = Some(Hairpin {
+                            cresc,
+                            start: curs.clone(),
+                            end: curs,
+                        });
+                    }
+                    // Until ended, a hairpin runs to the end of the bar.
+                    if let Some(hairpin) = &mut open {
+                        hairpin.end = Cursor {
+                            marking: markings.len() as u16,
+                            ..curs
+                        };
+                    }
+                }
+                hairpins.extend(open);
+            }
+        }
+        hairpins
+    }
+
+    /// Get the dynamic envelope of a channel at a marking, from 0.0 (silent)
+    /// to 1.0 (loudest).  Hairpins change smoothly to the next dynamic, and
+    /// accents (such as sf) only apply to the marking where they're written.
+    pub fn dynamic_at(&self, cursor: &Cursor) -> f32 {
+        let envelope = self.envelope(cursor.movement, cursor.chan);
+        let time = self.bar_time(cursor).unwrap_or(0);
+        envelope.level(cursor.bar, time)
+    }
+
+    /// Get the dynamic envelope of a channel through a movement.
+    pub(crate) fn envelope(&self, movement: u16, chan: u16) -> Envelope {
+        let mut bar_start = vec![];
+        let mut end = 0;
+        if let Some(mvmt) = self.movement.get(movement as usize) {
+            for bar in 0..mvmt.bar.len() as u16 {
+                bar_start.push(end);
+                end += ticks(mvmt.bar_length(bar));
+            }
+        }
+        let changes = self.dynamic_changes(movement, chan);
+        Envelope::new(bar_start, &changes, end)
+    }
+
+    /// Get the number of voices of a channel in any bar of a movement.
+    fn chan_voices(&self, movement: u16, chan: u16) -> u16 {
+        self.movement[movement as usize]
+            .bar
+            .iter()
+            .filter_map(|m| m.chan.get(chan as usize))
+            .map(|c| c.voice_len())
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Get the time of a marking in ticks from the start of its bar.
+    fn bar_time(&self, cursor: &Cursor) -> Option<u32> {
+        let markings = self
+            .movement
+            .get(cursor.movement as usize)?
+            .bar
+            .get(cursor.bar as usize)?
+            .chan
+            .get(cursor.chan as usize)?
+            .voice(cursor.voice)?;
+        let before = markings.iter().take(cursor.marking as usize);
+        let time: u32 = before
+            .filter_map(|m| match m {
+                Marking::Note(note) => Some(ticks(note.duration)),
+                _ => None,
+            })
+            .sum();
+        Some(time)
+    }
+
+    /// Get the changes of dynamic in all voices of a channel, in order.
+    fn dynamic_changes(&self, movement: u16, chan: u16) -> Vec<Change> {
+        let mut changes = vec![];
+        let mvmt = match self.movement.get(movement as usize) {
+            Some(mvmt) => mvmt,
+            None => return changes,
+        };
+        let mut bar_start = 0;
+        for (bar, measure) in mvmt.bar.iter().enumerate() {
+            let bar_end = bar_start + ticks(mvmt.bar_length(bar as u16));
+            let chan = match measure.chan.get(chan as usize) {
+                Some(chan) => chan,
+                None => {
+                    bar_start = bar_end;
+                    continue;
+                }
+            };
+            for voice in 0..chan.voice_len() {
+                let mut at = bar_start;
+                for marking in chan.voice(voice).into_iter().flatten() {
+                    let (dynamic, cresc) = match marking {
+                        Marking::Note(note) => {
+                            at += ticks(note.duration);
+                            continue;
+                        }
+                        Marking::Dynamic(dynamic) => {
+                            (Some(dynamic.clone()), false)
+                        }
+                        Marking::Cresc => (None, true),
+                        Marking::Dim => (None, false),
+                        _ => continue,
+                    };
+                    changes.push(Change { at, dynamic, cresc });
+                }
+            }
+            bar_start = bar_end;
+        }
+        changes.sort_by_key(|c| c.at);
+        changes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Movement;
+
+    /// Create a score with two bars of one channel.
+    fn score(bar0: &str, bar1: &str) -> Scof {
+        let text = format!(
+            "sig: 0\n  time: 2/4\n  tempo: 120\n\
+             bar:\n  chan: {}\n\
+             bar:\n  chan: {}\n",
+            bar0, bar1
+        );
+        Scof {
+            movement: vec![Movement::from_muon(&text).unwrap()],
+            ..Default::default()
+        }
+    }
+
+    /// Get the velocity at a marking.
+    fn velocity(scof: &Scof, bar: u16, marking: u16) -> u8 {
+        let level = scof.dynamic_at(&Cursor::new(0, bar, 0, marking));
+        (level * 127.0).round() as u8
+    }
+
+    #[test]
+    fn parse() {
+        for text in &["pppppp", "mp", "mf", "ffffff", "n", "sfz", "sfp"] {
+            let dynamic = text.parse::<Dynamic>().unwrap();
+            assert_eq!(dynamic.text(), *text);
+        }
+        assert!("pf".parse::<Dynamic>().is_err());
+        assert_eq!("cresc".parse::<Marking>(), Ok(Marking::Cresc));
+    }
+
+    #[test]
+    fn hairpins() {
+        let scof = score("p 1/4C4 cresc 1/4D4", "f 1/4E4 dim 1/4F4");
+        assert_eq!(
+            scof.hairpins(0),
+            vec![
+                Hairpin {
+                    cresc: true,
+                    start: Cursor::new(0, 0, 0, 2),
+                    end: Cursor::new(0, 1, 0, 0),
+                },
+                Hairpin {
+                    cresc: false,
+                    start: Cursor::new(0, 1, 0, 2),
+                    end: Cursor::new(0, 1, 0, 4),
+                },
+            ]
+        );
+        // Open across the barline, until the next hairpin.
+        let scof = score("1/4C4 cresc 1/4D4", "1/4E4 dim 1/4F4");
+        let hairpins = scof.hairpins(0);
+        assert_eq!(hairpins[0].end, Cursor::new(0, 1, 0, 1));
+        assert!(hairpins[0].crosses(1));
+        assert_eq!(hairpins[1].end, Cursor::new(0, 1, 0, 3));
+    }
+
+    #[test]
+    fn envelope() {
+        let scof = score("mp 1/4C4 cresc 1/8D4 1/8E4", "ff 1/4E4 sf 1/4F4");
+        assert_eq!(velocity(&scof, 0, 1), 64);
+        assert_eq!(velocity(&scof, 0, 3), 64);
+        // Halfway from mp to ff.
+        assert_eq!(velocity(&scof, 0, 4), 86);
+        assert_eq!(velocity(&scof, 1, 1), 108);
+        assert_eq!(velocity(&scof, 1, 3), 112);
+        let scof = score("1/4C4 dim 1/4D4", "1/2E4");
+        assert_eq!(velocity(&scof, 0, 0), 80);
+        assert_eq!(velocity(&scof, 0, 2), 80);
+        // A third of the way to the end of the movement.
+        assert_eq!(velocity(&scof, 1, 0), 75);
+        let scof = score("p 1/4C4 cresc 1/4D4", "1/4E4 f 1/4F4");
+        // Halfway from p to f, over the barline.
+        assert_eq!(velocity(&scof, 1, 0), 73);
+    }
+}
@@ -21,20 +21,34 @@
 use muon_rs as muon;
 use serde_derive::{Deserialize, Serialize};
 use std::convert::TryInto;
+use std::ops::RangeInclusive;
 use std::str::FromStr;
 
 use cala::log::{Tag, log};
 
 const SCOF: Tag = Tag::new("SCOF");
 
+mod dynamic;
 mod fraction;
+mod lyric;
 pub mod note;
-
+mod part;
+mod passage;
+mod playback;
+mod rehearsal;
+mod selection;
+mod spanner;
+
+pub use dynamic::Hairpin;
 pub use fraction::{Fraction, IsZero};
+pub use lyric::Syllable;
 pub use note::{
     Articulation, Note, Pitch, PitchAccidental, PitchClass, PitchName,
     PitchOctave, Steps,
 };
+pub use playback::{NoteEvent, TempoEvent, TICKS_PER_WHOLE};
+pub use selection::Selection;
+pub use spanner::Spanner;
 
 /// Cursor pointing to a marking
 #[derive(Clone, Default, Debug, PartialEq)]
@@ -47,6 +61,8 @@ pub struct Cursor {
     chan: u16,
     /// Marking number within bar
     marking: u16,
+    /// Voice number within channel
+    voice: u16,
 }
 
 impl Cursor {
@@ -57,16 +73,15 @@ impl Cursor {
             bar,
             chan,
             marking,
+            voice: 0,
         }
     }
 
     /// Create a cursor from the first marking
     pub fn first_marking(&self) -> Self {
         Cursor {
-            movement: self.movement,
-            bar: self.bar,
-            chan: self.chan,
             marking: 0,
+            ..*self
         }
     }
 
@@ -77,9 +92,49 @@ impl Cursor {
             bar: self.bar,
             chan,
             marking: self.marking,
+            voice: 0,
+        }
+    }
+
+    /// Create a cursor from the first marking of a voice in the channel.
+    pub fn voice(&self, voice: u16) -> Self {
+        Cursor {
+            marking: 0,
+            voice,
+            ..*self
         }
     }
 
+    /// Get the movement number of the cursor.
+    pub fn movement_index(&self) -> u16 {
+        self.movement
+    }
+
+    /// Get the bar number of the cursor.
+    pub fn bar_index(&self) -> u16 {
+        self.bar
+    }
+
+    /// Get the channel number of the cursor.
+    pub fn chan_index(&self) -> u16 {
+        self.chan
+    }
+
+    /// Get the voice number of the cursor.
+    pub fn voice_index(&self) -> u16 {
+        self.voice
+    }
+
+    /// Get the marking number of the cursor within its bar.
+    pub fn marking_index(&self) -> u16 {
+        self.marking
+    }
+
+    /// Create a cursor at a channel some number of channels below this one.
+    pub fn chan_offset(&self, offset: u16) -> Self {
+        self.chan(self.chan.saturating_add(offset))
+    }
+
     /// Move cursor left.
     pub fn left(&mut self, scof: &Scof) {
         if self.marking > 0 {
@@ -200,11 +255,50 @@ impl FromStr for Marking {
     type Err = ();
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Ok(Marking::Note(s.parse::<Note>().and_then(Ok)?))
+        match s {
+            "`" => Ok(Marking::Breath),
+            "||" => Ok(Marking::CaesuraShort),
+            "//" => Ok(Marking::CaesuraLong),
+            "cresc" => Ok(Marking::Cresc),
+            "dim" => Ok(Marking::Dim),
+            "pizz" => Ok(Marking::Pizz),
+            "arco" => Ok(Marking::Arco),
+            "mute" => Ok(Marking::Mute),
+            "open" => Ok(Marking::Open),
+            "%" => Ok(Marking::Repeat),
+            _ if s.starts_with('{') => Ok(Marking::GraceInto(s[1..].parse()?)),
+            _ if s.starts_with('}') => Ok(Marking::GraceOutOf(s[1..].parse()?)),
+            _ => match s.parse::<Dynamic>() {
+                Ok(dynamic) => Ok(Marking::Dynamic(dynamic)),
+                Err(_) => Ok(Marking::Note(s.parse::<Note>().and_then(Ok)?)),
+            },
+        }
+    }
+}
+
+impl std::fmt::Display for Marking {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Marking::Dynamic(dynamic) => write!(f, "{}", dynamic.text()),
+            Marking::GraceInto(note) => write!(f, "{{{}", note),
+            Marking::GraceOutOf(note) => write!(f, "}}{}", note),
+            Marking::Note(note) => write!(f, "{}", note),
+            Marking::Breath => write!(f, "`"),
+            Marking::CaesuraShort => write!(f, "||"),
+            Marking::CaesuraLong => write!(f, "//"),
+            Marking::Cresc => write!(f, "cresc"),
+            Marking::Dim => write!(f, "dim"),
+            Marking::Pizz => write!(f, "pizz"),
+            Marking::Arco => write!(f, "arco"),
+            Marking::Mute => write!(f, "mute"),
+            Marking::Open => write!(f, "open"),
+            Marking::Repeat => write!(f, "%"),
+        }
     }
 }
 
 /// A repeat marking for a bar.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Repeat {
     /// Repeat sign open ||:
     Open,
@@ -226,6 +320,43 @@ pub enum Repeat {
     Ending(u8),
 }
 
+impl FromStr for Repeat {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Open" => Ok(Repeat::Open),
+            "Close" => Ok(Repeat::Close),
+            "Segno" => Ok(Repeat::Segno),
+            "DC" => Ok(Repeat::DC),
+            "DS" => Ok(Repeat::DS),
+            "Coda" => Ok(Repeat::Coda),
+            "ToCoda" => Ok(Repeat::ToCoda),
+            "Fine" => Ok(Repeat::Fine),
+            _ => match s.strip_prefix("Ending").map(str::parse) {
+                Some(Ok(n)) if n > 0 => Ok(Repeat::Ending(n)),
+                _ => Err(()),
+            },
+        }
+    }
+}
+
+impl std::fmt::Display for Repeat {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Repeat::Open => write!(f, "Open"),
+            Repeat::Close => write!(f, "Close"),
+            Repeat::Segno => write!(f, "Segno"),
+            Repeat::DC => write!(f, "DC"),
+            Repeat::DS => write!(f, "DS"),
+            Repeat::Coda => write!(f, "Coda"),
+            Repeat::ToCoda => write!(f, "ToCoda"),
+            Repeat::Fine => write!(f, "Fine"),
+            Repeat::Ending(n) => write!(f, "Ending{}", n),
+        }
+    }
+}
+
 /////////////////////
 ////             ////
 /////////////////////
@@ -271,7 +402,7 @@ pub struct Synth {
 }
 
 /// A signature.
-#[derive(PartialEq, Debug, Default, Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize)]
 pub struct Sig {
     /// The key signature (0-23 quarter steps above C, 24+ reserved for middle
     /// eastern and Indian key signatures).
@@ -284,6 +415,22 @@ pub struct Sig {
     pub swing: Option<u8>,
 }
 
+impl Sig {
+    /// Get the length of the beat counted by the tempo: a dotted note in
+    /// compound meters (such as 6/8 or 12/8), otherwise the note of the time
+    /// signature (so a half note in 2/2).
+    pub fn beat(&self) -> Fraction {
+        match self.time.parse::<Fraction>() {
+            Ok(time) if time.den == 0 => Fraction::new(1, 4),
+            Ok(time) if time.num > 3 && time.num % 3 == 0 => {
+                Fraction::new(3, time.den)
+            }
+            Ok(time) => Fraction::new(1, time.den),
+            Err(()) => Fraction::new(1, 4),
+        }
+    }
+}
+
 /// Channel information for a specific bar of music.
 #[derive(PartialEq, Debug, Serialize, Deserialize)]
 struct Chan {
@@ -291,22 +438,39 @@ struct Chan {
     notes: String,
     /// Channel lyrics for 1 bar.
     lyric: Option<String>,
+    /// Additional voices for 1 bar (an `Option` so that the `chan: notes`
+    /// shorthand still works without them).
+    voice: Option<Vec<Voice>>,
+}
+
+/// An additional voice of a channel for a specific bar of music.
+#[derive(PartialEq, Debug, Serialize, Deserialize)]
+struct Voice {
+    /// Voice notes for 1 bar.
+    notes: String,
 }
 
 /// A parsed and transformed channel information for a specific bar of music.
-#[derive(PartialEq, Debug)]
+#[derive(Clone, PartialEq, Debug)]
 pub struct Channel {
     /// Channel notes for 1 bar.
     notes: Vec<Marking>,
     /// Channel lyrics for 1 bar.
     lyric: Option<String>,
+    /// Markings for each additional voice in 1 bar.
+    voice: Vec<Vec<Marking>>,
 }
 
 impl Default for Chan {
     fn default() -> Self {
         let notes = String::new(); // no notes = whole measure rest
         let lyric = None;
-        Chan { notes, lyric }
+        let voice = None;
+        Chan {
+            notes,
+            lyric,
+            voice,
+        }
     }
 }
 
@@ -318,21 +482,80 @@ impl Default for Channel {
 
 impl From<Chan> for Channel {
     fn from(chan: Chan) -> Self {
-        let mut notes = vec![];
+        chan.parse().unwrap_or_else(|(_, _, marking)| {
+            panic!("Invalid marking: {}", marking);
+        })
+    }
+}
 
-        for marking in chan.notes.split(' ').filter(|m| !m.is_empty()) {
-            notes.push(marking.parse().unwrap_or_else(|_| {
-                panic!("Invalid marking: {}", marking);
-            }));
+impl Chan {
+    /// Parse the markings, returning the voice, index and text of the first
+    /// invalid marking on failure.
+    fn parse(self) -> Result<Channel, (u16, u16, String)> {
+        let notes = parse_markings(&self.notes)
+            .map_err(|(i, marking)| (0, i, marking))?;
+
+        let lyric = self.lyric;
+
+        let mut voice = vec![];
+        for (v, text) in self.voice.iter().flatten().enumerate() {
+            let v = v as u16 + 1;
+            voice.push(
+                parse_markings(&text.notes)
+                    .map_err(|(i, marking)| (v, i, marking))?,
+            );
         }
 
-        let lyric = chan.lyric;
+        Ok(Channel {
+            notes,
+            lyric,
+            voice,
+        })
+    }
+}
+
+/// Parse the markings of one voice, returning the index and text of the
+/// first invalid marking on failure.
+fn parse_markings(text: &str) -> Result<Vec<Marking>, (u16, String)> {
+    let mut notes = vec![];
 
-        Channel { notes, lyric }
+    for marking in chan_markings(text) {
+        let i = notes.len() as u16;
+        notes.push(marking.parse().or(Err((i, marking.to_string())))?);
     }
+
+    Ok(notes)
 }
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+impl Channel {
+    /// Get the number of voices in the channel.
+    pub fn voice_len(&self) -> u16 {
+        self.voice.len() as u16 + 1
+    }
+
+    /// Get the markings of a voice.
+    pub fn voice(&self, voice: u16) -> Option<&Vec<Marking>> {
+        match voice {
+            0 => Some(&self.notes),
+            v => self.voice.get(v as usize - 1),
+        }
+    }
+
+    /// Get the mutable markings of a voice.
+    fn voice_mut(&mut self, voice: u16) -> Option<&mut Vec<Marking>> {
+        match voice {
+            0 => Some(&mut self.notes),
+            v => self.voice.get_mut(v as usize - 1),
+        }
+    }
+}
+
+/// Split the markings of a channel.
+fn chan_markings(notes: &str) -> impl Iterator<Item = &str> {
+    notes.split(' ').filter(|m| !m.is_empty())
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct SigRef {
     /// Index into sig list.
     index: u32,
@@ -345,6 +568,8 @@ pub struct SigRef {
 pub struct Bar {
     /// Signature reference (index)
     sig: Option<SigRef>,
+    /// Rehearsal mark at the start of the bar.
+    rehearsal: Option<String>,
     /// All of the channels in this piece.
     chan: Vec<Chan>,
     /// Repeat symbols for this measure.
@@ -352,16 +577,41 @@ pub struct Bar {
 }
 
 /// A bar (or measure) of music.
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Measure {
     /// Signature reference (index)
     pub sig: Option<SigRef>,
+    /// Rehearsal mark at the start of the bar (`*` for the next in
+    /// sequence).
+    pub rehearsal: Option<String>,
     /// All of the channels in this piece.
     pub chan: Vec<Channel>,
     /// Repeat symbols for this measure.
     pub repeat: Vec<String>,
 }
 
+impl Measure {
+    /// Get the valid repeat markings of the measure.
+    pub fn repeats(&self) -> impl Iterator<Item = Repeat> + '_ {
+        self.repeat.iter().filter_map(|r| r.parse().ok())
+    }
+
+    /// Check if the measure has a repeat marking.
+    pub fn has_repeat(&self, repeat: Repeat) -> bool {
+        self.repeats().any(|r| r == repeat)
+    }
+
+    /// Get the numbers of the endings (voltas) the measure is in.
+    pub fn endings(&self) -> Vec<u8> {
+        self.repeats()
+            .filter_map(|r| match r {
+                Repeat::Ending(n) => Some(n),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
 impl From<Bar> for Measure {
     fn from(mut bar: Bar) -> Self {
         let mut chan = vec![];
@@ -371,9 +621,15 @@ impl From<Bar> for Measure {
         }
 
         let sig = bar.sig;
+        let rehearsal = bar.rehearsal;
         let repeat = bar.repeat;
 
-        Measure { sig, chan, repeat }
+        Measure {
+            sig,
+            rehearsal,
+            chan,
+            repeat,
+        }
     }
 }
 
@@ -418,6 +674,74 @@ impl From<Mvmt> for Movement {
     }
 }
 
+impl Movement {
+    /// Parse a movement from muon text.
+    pub fn from_muon(text: &str) -> Result<Movement, ParseError> {
+        let mvmt: Mvmt = muon::from_str(text)
+            .map_err(|e| ParseError::Muon(e.to_string()))?;
+        let sig = mvmt.sig;
+        let mut bar = Vec::new();
+        for (b, measure) in mvmt.bar.into_iter().enumerate() {
+            let mut chan = vec![];
+            for (c, ch) in measure.chan.into_iter().enumerate() {
+                let channel =
+                    ch.parse().map_err(|(voice, marking, text)| {
+                        ParseError::Marking {
+                            bar: b as u16,
+                            chan: c as u16,
+                            voice,
+                            marking,
+                            text,
+                        }
+                    })?;
+                chan.push(channel);
+            }
+            let sig = measure.sig;
+            let rehearsal = measure.rehearsal;
+            let repeat = measure.repeat;
+            bar.push(Measure {
+                sig,
+                rehearsal,
+                chan,
+                repeat,
+            });
+        }
+        Ok(Movement { sig, bar })
+    }
+
+    /// Get the signature in effect for a bar.
+    pub fn sig(&self, bar: u16) -> Option<&Sig> {
+        self.sig.get(self.sig_index(bar)?)
+    }
+
+    /// Get the index of the signature in effect for a bar.
+    pub fn sig_index(&self, bar: u16) -> Option<usize> {
+        let measures = self.bar.get(..=bar as usize)?;
+        let sig = measures.iter().rev().find_map(|m| m.sig.as_ref());
+        Some(sig.map(|s| s.index as usize).unwrap_or(0))
+    }
+
+    /// Get the index of the signature a bar changes to, if any (the first bar
+    /// always starts with a signature).
+    pub fn sig_change(&self, bar: u16) -> Option<usize> {
+        let measure = self.bar.get(bar as usize)?;
+        let index = match &measure.sig {
+            Some(sig) => sig.index as usize,
+            None if bar == 0 => 0,
+            None => return None,
+        };
+        self.sig.get(index).map(|_| index)
+    }
+
+    /// Get the length of a bar from its time signature (4/4 if not set).
+    pub fn bar_length(&self, bar: u16) -> Fraction {
+        self.sig(bar)
+            .and_then(|sig| sig.time.parse::<Fraction>().ok())
+            .filter(|time| time.den != 0)
+            .unwrap_or_else(|| Fraction::new(1, 1))
+    }
+}
+
 /// An instrument in the soundfont for this score.
 #[derive(PartialEq, Debug, Default, Serialize, Deserialize)]
 pub struct Instrument {
@@ -464,7 +788,7 @@ fn default_symtime() -> bool {
 }
 
 /// Signature Style.
-#[derive(PartialEq, Debug, Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 pub struct SigStyle {
     /// Text that should show up.  Default="beat = BPM" marking.
     pub tempo: Option<String>,
@@ -477,17 +801,139 @@ pub struct SigStyle {
     pub swing_text: Option<String>,
 }
 
+/// Kind of stave group.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Grouping {
+    /// Brace, joining the staves of one instrument (grand staff).
+    Brace,
+    /// Bracket, joining the staves of a section.
+    Bracket,
+    /// Sub-bracket, joining the staves of a subsection.
+    SubBracket,
+}
+
+impl FromStr for Grouping {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "brace" => Ok(Grouping::Brace),
+            "bracket" => Ok(Grouping::Bracket),
+            "subbracket" => Ok(Grouping::SubBracket),
+            _ => Err(()),
+        }
+    }
+}
+
+/// A group of staves, joined at the start of each system.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct StaveGroup {
+    /// Kind of group: brace, bracket or subbracket.
+    pub kind: String,
+    /// First channel in the group.
+    pub first: u16,
+    /// Last channel in the group.
+    pub last: u16,
+}
+
+impl StaveGroup {
+    /// Get the kind of group, if valid.
+    pub fn grouping(&self) -> Option<Grouping> {
+        self.kind.parse().ok()
+    }
+
+    /// Get the range of channels in the group.
+    pub fn chans(&self) -> RangeInclusive<u16> {
+        self.first..=self.last
+    }
+}
+
 /// Style file.
-#[derive(PartialEq, Debug, Default, Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize)]
 pub struct Style {
     pub sig: Vec<SigStyle>,
+    /// Groups of staves.
+    pub group: Vec<StaveGroup>,
+    /// Name of the instrument (or part) played by each channel.
+    pub instrument: Vec<String>,
+    /// Number of empty bars before an entrance to leave out of multi-bar
+    /// rests, as space for cue notes.
+    pub cue_bars: Option<u16>,
+    /// Whether runs of empty bars are collapsed into multi-bar rests.
+    /// Default=true
+    pub multi_rests: Option<bool>,
+    /// Whether glissandi are drawn as wavy lines, rather than straight.
+    /// Default=false
+    pub wavy_glissando: Option<bool>,
+    /// Percentage of its length that a note with a fermata is held for.
+    /// Default=200
+    pub fermata: Option<u16>,
+    /// Length of the pause at a short caesura, in eighth notes (a long
+    /// caesura pauses twice as long).  Default=2
+    pub caesura: Option<u16>,
+    /// Number every this many bars, rather than at the start of each system
+    /// (0 for no bar numbers).
+    pub bar_numbers: Option<u16>,
+}
+
+impl Style {
+    /// Parse style from muon text.
+    pub fn from_muon(text: &str) -> Result<Style, ParseError> {
+        muon::from_str(text).map_err(|e| ParseError::Muon(e.to_string()))
+    }
+
+    /// Get the percentage of its length that a note with a fermata is held
+    /// for.
+    pub fn fermata_stretch(&self) -> u16 {
+        self.fermata.unwrap_or(200)
+    }
+
+    /// Get the length of the pause at a short caesura, in eighth notes.
+    pub fn caesura_pause(&self) -> u16 {
+        self.caesura.unwrap_or(2)
+    }
+
+    /// Check if runs of empty bars are collapsed into multi-bar rests.
+    pub fn collapses_rests(&self) -> bool {
+        self.multi_rests.unwrap_or(true)
+    }
+
+    /// Check if a bar is numbered, given whether it starts a system.  The
+    /// first bar of a movement is never numbered at the start of a system.
+    pub fn numbers_bar(&self, bar: u16, system_start: bool) -> bool {
+        match self.bar_numbers {
+            None => system_start && bar > 0,
+            Some(every) => (bar + 1).checked_rem(every) == Some(0),
+        }
+    }
+
+    /// Get the stave groups which are valid, in order.
+    pub fn stave_groups(
+        &self,
+    ) -> impl Iterator<Item = (Grouping, RangeInclusive<u16>)> + '_ {
+        self.group
+            .iter()
+            .filter(|g| g.first <= g.last)
+            .filter_map(|g| Some((g.grouping()?, g.chans())))
+    }
+
+    /// Check if barlines run from a channel's stave through to the next.
+    pub fn joins_barlines(&self, chan: u16) -> bool {
+        self.stave_groups().any(|(grouping, chans)| {
+            grouping != Grouping::SubBracket
+                && chans.contains(&chan)
+                && chans.contains(&(chan + 1))
+        })
+    }
 }
 
 /// Arranger & Ensemble
-#[derive(PartialEq, Debug, Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 pub struct Arranger {
-    name: String,
-    ensemble: Option<String>,
+    /// Who arranged the music.
+    pub name: String,
+    /// Ensemble the music was arranged for.
+    pub ensemble: Option<String>,
 }
 
 fn default_composer() -> String {
@@ -495,7 +941,7 @@ fn default_composer() -> String {
 }
 
 /// Score metadata.
-#[derive(PartialEq, Debug, Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 pub struct Meta {
     /// Who wrote the original music "{}"
     #[serde(default = "default_composer")]
@@ -504,6 +950,8 @@ pub struct Meta {
     pub subtitle: Option<String>,
     /// Work number.
     pub number: Option<u32>,
+    /// Name of the part, for a score extracted for one player.
+    pub part: Option<String>,
     /// Who wrote the lyrics to the music "Words by {}"
     pub lyricist: Option<String>,
     /// Who translated the lyrics "Translated by {}"
@@ -528,6 +976,7 @@ impl Default for Meta {
         Self {
             subtitle: None,
             number: None,
+            part: None,
             composer: default_composer(),
             lyricist: None,
             translator: None,
@@ -541,7 +990,48 @@ impl Default for Meta {
     }
 }
 
+impl Meta {
+    /// Parse metadata from muon text.
+    pub fn from_muon(text: &str) -> Result<Meta, ParseError> {
+        muon::from_str(text).map_err(|e| ParseError::Muon(e.to_string()))
+    }
+}
+
+/// An error parsing a file of a score.
+#[derive(Debug, PartialEq)]
+pub enum ParseError {
+    /// Invalid muon syntax or structure.
+    Muon(String),
+    /// Invalid marking text.
+    Marking {
+        /// Bar number
+        bar: u16,
+        /// Channel number
+        chan: u16,
+        /// Voice number within channel
+        voice: u16,
+        /// Marking number within bar
+        marking: u16,
+        /// Text of the marking
+        text: String,
+    },
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ParseError::Muon(e) => write!(f, "{}", e),
+            ParseError::Marking { text, .. } => {
+                write!(f, "invalid marking `{}`", text)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
 /// The entire Scof zip file.
+#[derive(Debug, PartialEq)]
 pub struct Scof {
     /// The title of the piece.  When the zip file's name is
     /// "My Score \ Symphony No. 1.scof" => "My Score / Symphony No. 1".
@@ -589,7 +1079,7 @@ impl Scof {
             .get(cursor.bar as usize)?
             .chan
             .get(cursor.chan as usize)?
-            .notes
+            .voice(cursor.voice)?
             .get(cursor.marking as usize)
     }
 
@@ -601,16 +1091,13 @@ impl Scof {
 
     /// Get mutable vec of markings for measure at cursor position.
     fn chan_notes_mut(&mut self, cursor: &Cursor) -> Option<&mut Vec<Marking>> {
-        Some(
-            &mut self
-                .movement
-                .get_mut(cursor.movement as usize)?
-                .bar
-                .get_mut(cursor.bar as usize)?
-                .chan
-                .get_mut(cursor.chan as usize)?
-                .notes,
-        )
+        self.movement
+            .get_mut(cursor.movement as usize)?
+            .bar
+            .get_mut(cursor.bar as usize)?
+            .chan
+            .get_mut(cursor.chan as usize)?
+            .voice_mut(cursor.voice)
     }
 
     /// Get the last measure of a movement
@@ -636,8 +1123,9 @@ impl Scof {
             self.push_measure(
                 0,
                 Measure {
-                    sig: None,      // No signature changes
-                    repeat: vec![], // No repeat symbols
+                    sig: None,       // No signature changes
+                    rehearsal: None, // No rehearsal mark
+                    repeat: vec![],  // No repeat symbols
                     chan,
                 },
             );
@@ -654,6 +1142,30 @@ impl Scof {
         curs.marking
     }
 
+    /// Get the count of channels in the measure at a cursor
+    pub fn chan_len(&self, cursor: &Cursor) -> u16 {
+        let mut curs = cursor.chan(0);
+        while self
+            .movement
+            .get(curs.movement as usize)
+            .and_then(|m| m.bar.get(curs.bar as usize))
+            .and_then(|b| b.chan.get(curs.chan as usize))
+            .is_some()
+        {
+            curs.chan += 1;
+        }
+        curs.chan
+    }
+
+    /// Get the count of voices in the channel at a cursor
+    pub fn voice_len(&self, cursor: &Cursor) -> u16 {
+        self.movement
+            .get(cursor.movement as usize)
+            .and_then(|m| m.bar.get(cursor.bar as usize))
+            .and_then(|b| b.chan.get(cursor.chan as usize))
+            .map_or(0, Channel::voice_len)
+    }
+
     /// Return true if there are no markings in a measure (measure doesn't
     /// exist).
     pub fn marking_is_empty(&self, cursor: &Cursor) -> bool {
@@ -813,6 +1325,146 @@ impl Scof {
         self.set_empty_measure(cursor, &note);
     }
 
+    /// Make sure the bar at a cursor exists (adding bars to the end of the
+    /// movement), and fill an empty channel with a rest.  Returns false if
+    /// the channel doesn't exist.
+    fn fill_bar(&mut self, cursor: &Cursor) -> bool {
+        let movement = match self.movement.get_mut(cursor.movement as usize) {
+            Some(movement) => movement,
+            None => return false,
+        };
+        while movement.bar.len() <= cursor.bar as usize {
+            let chans = movement.bar.last().map_or(0, |bar| bar.chan.len());
+            movement.bar.push(Measure {
+                sig: None,
+                rehearsal: None,
+                repeat: vec![],
+                chan: vec![Channel::default(); chans],
+            });
+        }
+        let duration = movement.bar_length(cursor.bar);
+        match self.chan_notes_mut(cursor) {
+            Some(notes) => {
+                if notes.is_empty() {
+                    notes.push(Marking::Note(Note {
+                        pitch: vec![],
+                        duration,
+                        articulation: vec![],
+                    }));
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Get the time from the start of the bar to the marking at a cursor.
+    pub fn offset(&self, cursor: &Cursor) -> Fraction {
+        let mut offset = Fraction::new(0, 1);
+        for marking in 0..cursor.marking {
+            let cursor = Cursor { marking, ..*cursor };
+            if let Some(Marking::Note(note)) = self.marking(&cursor) {
+                offset += note.duration;
+            }
+        }
+        offset
+    }
+
+    /// Get a cursor to the marking which starts at an offset into the bar,
+    /// splitting the note sounding at that time if necessary.  The cursor is
+    /// past the last marking if the offset is at (or beyond) the end of the
+    /// bar.
+    pub fn split_at(&mut self, cursor: &Cursor, offset: Fraction) -> Cursor {
+        let mut cursor = cursor.first_marking();
+        if !self.fill_bar(&cursor) {
+            return cursor;
+        }
+        let notes = self.chan_notes_mut(&cursor).unwrap();
+        let mut time = Fraction::new(0, 1);
+        for (i, marking) in notes.iter_mut().enumerate() {
+            cursor.marking = i as u16;
+            if time >= offset {
+                return cursor;
+            }
+            if let Marking::Note(note) = marking {
+                if time + note.duration > offset {
+                    let mut after = note.clone();
+                    after.duration = time + note.duration - offset;
+                    note.duration = offset - time;
+                    notes.insert(i + 1, Marking::Note(after));
+                    cursor.marking += 1;
+                    return cursor;
+                }
+                time += note.duration;
+            }
+        }
+        cursor.marking = notes.len() as u16;
+        cursor
+    }
+
+    /// Get the time from the start of the movement to the marking at a
+    /// cursor.
+    pub fn time(&self, cursor: &Cursor) -> Fraction {
+        let mut time = self.offset(cursor);
+        if let Some(movement) = self.movement.get(cursor.movement as usize) {
+            for bar in 0..cursor.bar {
+                time += movement.bar_length(bar);
+            }
+        }
+        time
+    }
+
+    /// Get a cursor to the marking sounding at a time from the start of the
+    /// movement, in the channel of a cursor.  Returns `None` if the time is
+    /// after the end of the movement.
+    pub fn cursor_at(&self, cursor: &Cursor, time: Fraction) -> Option<Cursor> {
+        let movement = self.movement.get(cursor.movement as usize)?;
+        let mut start = Fraction::new(0, 1);
+        for bar in 0..movement.bar.len() as u16 {
+            let end = start + movement.bar_length(bar);
+            if time < end {
+                let mut cursor = Cursor {
+                    bar,
+                    marking: 0,
+                    ..*cursor
+                };
+                let len = self.marking_len(&cursor);
+                for marking in 0..len {
+                    cursor.marking = marking;
+                    if let Some(note) = self.note(&cursor) {
+                        start += note.duration;
+                        if time < start {
+                            break;
+                        }
+                    }
+                }
+                return Some(cursor);
+            }
+            start = end;
+        }
+        None
+    }
+
+    /// Get a cursor to the last marking which starts before a time from the
+    /// start of the movement, in the channel of a cursor.
+    pub fn cursor_before(
+        &self,
+        cursor: &Cursor,
+        time: Fraction,
+    ) -> Option<Cursor> {
+        if let Some(mut cursor) = self.cursor_at(cursor, time) {
+            cursor.left(self);
+            return Some(cursor);
+        }
+        let bars = self.movement.get(cursor.movement as usize)?.bar.len();
+        let mut cursor = Cursor {
+            bar: bars.checked_sub(1)? as u16,
+            ..*cursor
+        };
+        cursor.marking = self.marking_len(&cursor).saturating_sub(1);
+        Some(cursor)
+    }
+
     // FIXME: Needed?
     /// Insert a note after the cursor.
     fn insert_after(
@@ -0,0 +1,227 @@
+//! Lyrics (syllables sung on the notes of a channel)
+
+use crate::{Articulation, Cursor, Marking, Scof};
+
+/// A syllable of lyrics, sung on one note or held over several (a melisma).
+///
+/// The lyrics of a bar are written as syllables separated by spaces, one for
+/// each pitched note of the first voice (not counting notes tied from the
+/// note before).  A syllable ending with `-` is joined to the next syllable
+/// by a hyphen, and `_` holds the previous syllable over another note.
+/// Verses are separated by `|`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Syllable {
+    /// Text of the syllable (empty for a syllable held from the previous
+    /// bar, or a hyphen continuing a word from it)
+    pub text: String,
+    /// Whether the syllable is joined to the next by a hyphen
+    pub hyphen: bool,
+    /// Note where the syllable is sung
+    pub start: Cursor,
+    /// Last note the syllable is held over (the same as `start` without a
+    /// melisma)
+    pub end: Cursor,
+}
+
+impl Syllable {
+    /// Check if the syllable is held over more than one note.
+    pub fn is_melisma(&self) -> bool {
+        self.start != self.end
+    }
+}
+
+impl Scof {
+    /// Get the verses of lyrics of a channel in a bar, aligned to its notes.
+    /// Syllables without a note to be sung on are dropped.  A verse
+    /// continuing a word from the previous bar starts with a hyphen.
+    pub fn lyrics(&self, cursor: &Cursor) -> Vec<Vec<Syllable>> {
+        let mut verses = bar_lyrics(self, cursor);
+        if let Some(bar) = cursor.bar.checked_sub(1) {
+            let prev = bar_lyrics(self, &Cursor { bar, ..*cursor });
+            for (verse, before) in verses.iter_mut().zip(prev) {
+                if matches!(before.last(), Some(s) if s.hyphen) {
+                    carry_hyphen(verse);
+                }
+            }
+        }
+        verses
+    }
+}
+
+/// Get the verses of lyrics of a channel in a bar, without looking at the
+/// bars before it.
+fn bar_lyrics(scof: &Scof, cursor: &Cursor) -> Vec<Vec<Syllable>> {
+    let chan = scof
+        .movement
+        .get(cursor.movement as usize)
+        .and_then(|mvmt| mvmt.bar.get(cursor.bar as usize))
+        .and_then(|measure| measure.chan.get(cursor.chan as usize));
+    let (chan, lyric) = match chan {
+        Some(chan) => match &chan.lyric {
+            Some(lyric) => (chan, lyric),
+            None => return vec![],
+        },
+        None => return vec![],
+    };
+    let first = Cursor::new(cursor.movement, cursor.bar, cursor.chan, 0);
+    let mut tied = tied_in(scof, &first);
+    let mut notes = vec![];
+    for (i, marking) in chan.voice(0).into_iter().flatten().enumerate() {
+        if let Marking::Note(note) = marking {
+            if !note.pitch.is_empty() && !tied {
+                notes.push(Cursor {
+                    marking: i as u16,
+                    ..first.clone()
+                });
+            }
+            tied = note.articulation.contains(&Articulation::Tie);
+        }
+    }
+    lyric
+        .split('|')
+        .map(|verse| syllables(verse, &notes))
+        .collect()
+}
+
+/// Start a verse with a hyphen from the previous bar, on the syllable held
+/// from it (or before the first syllable).
+fn carry_hyphen(verse: &mut Vec<Syllable>) {
+    let start = match verse.first_mut() {
+        Some(first) if first.text.is_empty() => {
+            first.hyphen = true;
+            return;
+        }
+        Some(first) => first.start.clone(),
+        None => return,
+    };
+    verse.insert(
+        0,
+        Syllable {
+            text: String::new(),
+            hyphen: true,
+            start: start.clone(),
+            end: start,
+        },
+    );
+}
+
+/// Check if the last note of the channel in the bar before a cursor is tied
+/// over the barline.
+fn tied_in(scof: &Scof, cursor: &Cursor) -> bool {
+    let prev = match cursor.bar.checked_sub(1) {
+        Some(bar) => Cursor { bar, ..*cursor },
+        None => return false,
+    };
+    for marking in (0..scof.marking_len(&prev)).rev() {
+        if let Some(note) = scof.note(&Cursor { marking, ..prev }) {
+            return note.articulation.contains(&Articulation::Tie);
+        }
+    }
+    false
+}
+
+/// Align the syllables of a verse to the notes they're sung on.
+fn syllables(verse: &str, notes: &[Cursor]) -> Vec<Syllable> {
+    let mut syllables: Vec<Syllable> = vec![];
+    for (text, note) in verse.split_whitespace().zip(notes) {
+        if text == "_" {
+            match syllables.last_mut() {
+                Some(syllable) => syllable.end = note.clone(),
+                // Held from the previous bar
+                None => syllables.push(Syllable {
+                    text: String::new(),
+                    hyphen: false,
+                    start: note.clone(),
+                    end: note.clone(),
+                }),
+            }
+            continue;
+        }
+        let hyphen = text.ends_with('-');
+        syllables.push(Syllable {
+            text: text.trim_end_matches('-').to_string(),
+            hyphen,
+            start: note.clone(),
+            end: note.clone(),
+        });
+    }
+    syllables
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Movement;
+
+    #[test]
+    fn syllables() {
+        let text = "sig: 0\n  time: 4/4\n  tempo: 120\n\
+             bar:\n  chan: 1/8C4 1/8D4 1/4R 1/8E4 1/8F4 1/4G4\n\
+             \x20   lyric: sil- ly _ ly- rics | _ and more\n";
+        let scof = Scof {
+            movement: vec![Movement::from_muon(text).unwrap()],
+            ..Default::default()
+        };
+        let verses = scof.lyrics(&Cursor::new(0, 0, 0, 0));
+        assert_eq!(verses.len(), 2);
+        let words: Vec<(&str, bool, u16, u16)> = verses[0]
+            .iter()
+            .map(|s| {
+                (s.text.as_str(), s.hyphen, s.start.marking, s.end.marking)
+            })
+            .collect();
+        // The rest doesn't get a syllable.
+        assert_eq!(
+            words,
+            vec![
+                ("sil", true, 0, 0),
+                ("ly", false, 1, 3),
+                ("ly", true, 4, 4),
+                ("rics", false, 5, 5)
+            ]
+        );
+        assert!(verses[0][1].is_melisma());
+        // A verse starting with a held syllable continues the previous bar.
+        assert_eq!(verses[1][0].text, "");
+        assert_eq!(verses[1][1].start.marking, 1);
+    }
+
+    #[test]
+    fn tied_notes() {
+        let text = "sig: 0\n  time: 2/4\n  tempo: 120\n\
+             bar:\n  chan: 1/4C4) 1/4C4)\n\
+             \x20   lyric: la- di\n\
+             bar:\n  chan: 1/4C4 1/4D4\n\
+             \x20   lyric: da\n";
+        let scof = Scof {
+            movement: vec![Movement::from_muon(text).unwrap()],
+            ..Default::default()
+        };
+        // Notes tied from the note before don't get a syllable.
+        let verses = scof.lyrics(&Cursor::new(0, 0, 0, 0));
+        assert_eq!(verses[0].len(), 1);
+        let verses = scof.lyrics(&Cursor::new(0, 1, 0, 0));
+        assert_eq!(verses[0][0].start.marking, 1);
+    }
+
+    #[test]
+    fn carried_hyphen() {
+        let text = "sig: 0\n  time: 2/4\n  tempo: 120\n\
+             bar:\n  chan: 1/4C4 1/4D4\n\
+             \x20   lyric: a sing- | b held-\n\
+             bar:\n  chan: 1/4C4 1/4D4\n\
+             \x20   lyric: ing c | _ d\n";
+        let scof = Scof {
+            movement: vec![Movement::from_muon(text).unwrap()],
+            ..Default::default()
+        };
+        let verses = scof.lyrics(&Cursor::new(0, 1, 0, 0));
+        // The hyphen continues before the first syllable.
+        assert_eq!(verses[0].len(), 3);
+        assert!(verses[0][0].text.is_empty() && verses[0][0].hyphen);
+        assert_eq!(verses[0][1].text, "ing");
+        // A held syllable keeps its hyphen.
+        assert_eq!(verses[1].len(), 2);
+        assert!(verses[1][0].hyphen);
+    }
+}
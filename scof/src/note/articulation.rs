@@ -27,6 +27,8 @@ pub enum Articulation {
 
     /// Slur
     Slur,
+    /// Tie (to the next note, of the same pitch)
+    Tie,
     /// Glissando
     Glissando,
     /// Pitch bend slide up into
@@ -44,8 +46,8 @@ pub enum Articulation {
     TurnInverted,
     /// Trill
     Trill,
-    /// Tremelo
-    Tremelo,
+    /// Tremelo (with the number of slashes on the stem, 1 to 5)
+    Tremelo(u8),
     /// Arpeggio (strum) pitch up, strum guitar down.
     StrumDown,
     /// Arpeggio (strum) pitch down, strum guitar up
@@ -72,23 +74,24 @@ impl fmt::Display for Articulation {
             Harmonic => write!(f, "@"),
             Pedal => write!(f, "|"),
             // Connections between notes.
-            Slur => panic!("Should this be articulation?"), // FIXME
-            Glissando => panic!("Should this be articulation?"), // FIXME
-            BendUpInto => panic!("Should this be articulation?"), // FIXME
-            BendDownInto => panic!("Should this be articulation?"), // FIXME
-            BendUpOut => panic!("Should this be articulation?"), // FIXME
-            BendDownOut => panic!("Should this be articulation?"), // FIXME
+            Slur => write!(f, "&"),
+            Tie => write!(f, ")"),
+            Glissando => write!(f, "-"),
+            BendUpInto => write!(f, "/-"),
+            BendDownInto => write!(f, "\\-"),
+            BendUpOut => write!(f, "-/"),
+            BendDownOut => write!(f, "-\\"),
 
             // Adds extra notes within one note.
-            Turn => panic!("Should this be articulation?"), // FIXME
-            TurnInverted => panic!("Should this be articulation?"), // FIXME
-            Trill => panic!("Should this be articulation?"), // FIXME
-            Tremelo => panic!("Should this be articulation?"), // FIXME
-            StrumDown => panic!("Should this be articulation?"), // FIXME
-            StrumUp => panic!("Should this be articulation?"), // FIXME
+            Turn => write!(f, "s"),
+            TurnInverted => write!(f, "z"),
+            Trill => write!(f, "~"),
+            Tremelo(slashes) => write!(f, "={}", slashes),
+            StrumDown => write!(f, "M"),
+            StrumUp => write!(f, "W"),
 
             // Applies to all staves at the same time.
-            Fermata => panic!("Should this be articulation?"), // FIXME
+            Fermata => write!(f, "$"),
         }
     }
 }
@@ -97,19 +100,90 @@ impl FromStr for Articulation {
     type Err = ();
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Ok(match s.chars().next().ok_or(())? {
+        Ok(match s {
             // Articulation
-            '\'' => Articulation::Staccatissimo,
-            '.' => Articulation::Staccato,
-            '_' => Articulation::Tenuto,
-            '^' => Articulation::Marcato,
-            '>' => Articulation::Accent,
+            "'" => Articulation::Staccatissimo,
+            "." => Articulation::Staccato,
+            "_" => Articulation::Tenuto,
+            "^" => Articulation::Marcato,
+            ">" => Articulation::Accent,
             // Sound modifiers
-            '+' => Articulation::Mute,
-            'o' => Articulation::Open,
-            '@' => Articulation::Harmonic,
-            '|' => Articulation::Pedal,
-            _ => return Err(()),
+            "+" => Articulation::Mute,
+            "o" => Articulation::Open,
+            "@" => Articulation::Harmonic,
+            "|" => Articulation::Pedal,
+            // Connections between notes.
+            "&" => Articulation::Slur,
+            ")" => Articulation::Tie,
+            "-" => Articulation::Glissando,
+            "/-" => Articulation::BendUpInto,
+            "\\-" => Articulation::BendDownInto,
+            "-/" => Articulation::BendUpOut,
+            "-\\" => Articulation::BendDownOut,
+            // Adds extra notes within one note.
+            "s" => Articulation::Turn,
+            "z" => Articulation::TurnInverted,
+            "~" => Articulation::Trill,
+            "M" => Articulation::StrumDown,
+            "W" => Articulation::StrumUp,
+            // Applies to all staves at the same time.
+            "$" => Articulation::Fermata,
+            _ => match s.strip_prefix('=').map(str::parse) {
+                Some(Ok(slashes)) if (1..=5).contains(&slashes) => {
+                    Articulation::Tremelo(slashes)
+                }
+                _ => return Err(()),
+            },
         })
     }
 }
+
+impl Articulation {
+    /// Check if the articulation connects notes (or slides into or out of a
+    /// note), rather than marking one note.
+    pub fn is_spanner(self) -> bool {
+        use Articulation::*;
+        matches!(
+            self,
+            Slur | Tie
+                | Glissando
+                | BendUpInto
+                | BendDownInto
+                | BendUpOut
+                | BendDownOut
+        )
+    }
+
+    /// Check if the articulation connects the end of a note to the next
+    /// note (so it goes with the last part of a split note).
+    pub fn connects_next(self) -> bool {
+        use Articulation::*;
+        matches!(self, Slur | Tie | Glissando | BendUpOut | BendDownOut)
+    }
+
+    /// Check if the articulation adds extra notes within one note.
+    pub fn is_ornament(self) -> bool {
+        use Articulation::*;
+        matches!(
+            self,
+            Turn | TurnInverted | Trill | Tremelo(_) | StrumDown | StrumUp
+        )
+    }
+
+    /// Split articulation symbols into the text of each articulation
+    /// (bends and tremelos take two characters).
+    pub(crate) fn split(text: &str) -> Vec<&str> {
+        let mut symbols = vec![];
+        let mut rest = text;
+        while let Some(c) = rest.chars().next() {
+            let len = match rest.get(..2) {
+                Some("/-") | Some("\\-") | Some("-/") | Some("-\\") => 2,
+                _ if c == '=' => rest.chars().take(2).map(char::len_utf8).sum(),
+                _ => c.len_utf8(),
+            };
+            symbols.push(&rest[..len]);
+            rest = &rest[len..];
+        }
+        symbols
+    }
+}
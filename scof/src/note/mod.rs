@@ -59,6 +59,20 @@
 //! - `o`: open mute
 //! - `@`: harmonic (smaller o)
 //! - `|`: pedal
+//! - `&`: slur (to the next note)
+//! - `)`: tie (to the next note)
+//! - `-`: glissando (to the next note)
+//! - `/-`: pitch bend slide up into
+//! - `\-`: pitch bend slide down into
+//! - `-/`: pitch bend slide up out of
+//! - `-\`: pitch bend slide down out of (fall)
+//! - `s`: turn
+//! - `z`: inverted turn
+//! - `~`: trill
+//! - `=1`: tremelo (with 1 to 5 slashes)
+//! - `M`: arpeggio (strum) pitch up, strum guitar down
+//! - `W`: arpeggio (strum) pitch down, strum guitar up
+//! - `$`: fermata (everyone holds the note)
 
 use crate::Fraction;
 use std::convert::TryInto;
@@ -173,6 +187,32 @@ impl Note {
         self.duration
     }
 
+    /// Split the note at a duration, returning the part after it.  The
+    /// articulations connecting the note to the next one go with the part
+    /// after, and the others stay with this part.
+    pub fn split(&mut self, duration: Fraction) -> Note {
+        let (before, after) =
+            self.articulation.iter().partition(|a| !a.connects_next());
+        let after = Note {
+            pitch: self.pitch.clone(),
+            duration: (self.duration - duration).simplify(),
+            articulation: after,
+        };
+        self.duration = duration.simplify();
+        self.articulation = before;
+        after
+    }
+
+    /// Split the note at a duration, returning the part after it, tied from
+    /// this part (unless it's a rest).
+    pub fn split_tied(&mut self, duration: Fraction) -> Note {
+        let after = self.split(duration);
+        if !self.pitch.is_empty() {
+            self.articulation.push(Articulation::Tie);
+        }
+        after
+    }
+
     fn move_step(
         &self,
         i: usize,
@@ -303,9 +343,10 @@ impl FromStr for Note {
         'note_pitches: loop {
             let begin_index = end_index;
             pitch.push(match s.get(begin_index..).ok_or(())? {
-                // Find rest, used in absence of notes.
+                // Find rest, used in absence of notes (may have
+                // articulations, such as a fermata).
                 "" => break 'note_pitches,
-                "R" => {
+                text if text.starts_with('R') => {
                     end_index += 1;
                     break 'note_pitches;
                 }
@@ -335,14 +376,10 @@ impl FromStr for Note {
         }
 
         // Read articulation symbols.
-        let mut articulation = vec![];
-        let mut articulation_str = "".to_string();
-        for articulation_char in s[end_index..].chars() {
-            articulation_str.clear();
-            articulation_str.push(articulation_char);
-            articulation
-                .push(articulation_str.parse::<Articulation>().or(Err(()))?);
-        }
+        let articulation = Articulation::split(&s[end_index..])
+            .into_iter()
+            .map(str::parse)
+            .collect::<Result<_, _>>()?;
 
         Ok(Note {
             pitch,
@@ -367,4 +404,34 @@ mod tests {
             }
         );
     }
+
+    #[test]
+    fn midi() {
+        let midi = |s: &str| s.parse::<Pitch>().unwrap().midi();
+        assert_eq!(midi("C4"), Some(60));
+        assert_eq!(midi("A4"), Some(69));
+        assert_eq!(midi("G9"), Some(127));
+        let pitch = |name, accidental, octave| {
+            Pitch(PitchClass { name, accidental }, octave).midi()
+        };
+        let (b, c) = (PitchName::B, PitchName::C);
+        let o3 = PitchOctave::Octave3;
+        let o_ = PitchOctave::Octave_;
+        assert_eq!(pitch(b, Some(PitchAccidental::Flat), o3), Some(58));
+        assert_eq!(pitch(c, Some(PitchAccidental::QuarterSharp), o3), Some(48));
+        assert_eq!(pitch(c, Some(PitchAccidental::Sharp), o_), Some(1));
+        assert_eq!(pitch(c, Some(PitchAccidental::Flat), o_), None);
+    }
+
+    #[test]
+    fn split_tied() {
+        let mut note: Note = "1/2C4&.".parse().unwrap();
+        let after = note.split_tied(Fraction::new(1, 8));
+        // The slur goes on from the last part, which the first is tied to.
+        assert_eq!(note.to_string(), "1/8C4.)");
+        assert_eq!(after.to_string(), "3/8C4&");
+        let mut rest: Note = "1/2R".parse().unwrap();
+        assert_eq!(rest.split_tied(Fraction::new(1, 4)).to_string(), "1/4R");
+        assert_eq!(rest.to_string(), "1/4R");
+    }
 }
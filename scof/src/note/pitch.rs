@@ -270,6 +270,32 @@ impl Pitch {
             0: steps + octaves * 7,
         }
     }
+
+    /// Get the MIDI note number (middle C is 60).  Quarter tones are rounded
+    /// down to the nearest half step.
+    pub fn midi(self) -> Option<u8> {
+        use PitchAccidental::*;
+
+        let semitones = [0, 2, 4, 5, 7, 9, 11][self.0.name as usize];
+        let quarters = match self.0.accidental {
+            Some(DoubleFlat) => -4,
+            Some(FlatQuarterFlat) => -3,
+            Some(Flat) => -2,
+            Some(QuarterFlat) => -1,
+            None | Some(Natural) => 0,
+            Some(QuarterSharp) => 1,
+            Some(Sharp) => 2,
+            Some(SharpQuarterSharp) => 3,
+            Some(DoubleSharp) => 4,
+        };
+        let quarters = (self.1 as i32 + 1) * 24 + semitones * 2 + quarters;
+        let key = quarters.div_euclid(2);
+        if (0..128).contains(&key) {
+            Some(key as u8)
+        } else {
+            None
+        }
+    }
 }
 
 impl fmt::Display for Pitch {
@@ -0,0 +1,300 @@
+//! Part extraction (a score for one player)
+
+use std::ops::Range;
+
+use crate::{
+    Channel, Fraction, Marking, Measure, Movement, Scof, StaveGroup, Style,
+};
+
+/// Number of empty bars left before an entrance in a part, for cue notes.
+const CUE_BARS: u16 = 1;
+
+impl Scof {
+    /// Extract a part for one player, with only some channels of the score.
+    ///
+    /// Signatures and repeats are kept.  Bars with only a full bar rest
+    /// become whole measure rests, so that they can be merged into
+    /// multi-bar rests (see [`multi_rests`]).
+    ///
+    /// [`multi_rests`]: Scof::multi_rests
+    pub fn extract_part(&self, chans: &[u16]) -> Scof {
+        let movement: Vec<Movement> = self
+            .movement
+            .iter()
+            .map(|mvmt| part_movement(mvmt, chans))
+            .collect();
+        let mut meta = self.meta.clone();
+        meta.part = Some(self.part_name(chans));
+        let style = Style {
+            sig: self.style.sig.clone(),
+            group: part_groups(&self.style.group, chans),
+            instrument: part_instruments(&self.style.instrument, chans),
+            cue_bars: Some(self.style.cue_bars.unwrap_or(CUE_BARS)),
+            multi_rests: None,
+            wavy_glissando: self.style.wavy_glissando,
+            fermata: self.style.fermata,
+            caesura: self.style.caesura,
+            bar_numbers: self.style.bar_numbers,
+        };
+        Scof {
+            title: self.title.clone(),
+            cover: self.cover.clone(),
+            meta,
+            style,
+            cache: vec![vec![]; movement.len()],
+            movement,
+            ..Default::default()
+        }
+    }
+
+    /// Get the runs of empty bars in a movement which can be shown as
+    /// multi-bar rests.  Runs are broken at signature changes and rehearsal
+    /// marks, bars with repeats are left out, and the style's `cue_bars` are
+    /// left out before an entrance.  There are none if the style doesn't
+    /// collapse rests.
+    pub fn multi_rests(&self, movement: u16) -> Vec<Range<u16>> {
+        let mut runs = vec![];
+        let mvmt = match self.movement.get(movement as usize) {
+            Some(mvmt) if self.style.collapses_rests() => mvmt,
+            _ => return runs,
+        };
+        let cue = self.style.cue_bars.unwrap_or(0);
+        let mut start = None;
+        for (b, measure) in mvmt.bar.iter().enumerate() {
+            let b = b as u16;
+            let rest = is_rest(measure);
+            let mergeable = rest && measure.repeat.is_empty();
+            let section = b > 0
+                && (mvmt.sig_index(b) != mvmt.sig_index(b - 1)
+                    || measure.rehearsal.is_some());
+            if let Some(s) = start {
+                if !mergeable || section {
+                    let end = if rest { b } else { b.saturating_sub(cue) };
+                    runs.push(s..end.max(s));
+                    start = None;
+                }
+            }
+            if mergeable && start.is_none() {
+                start = Some(b);
+            }
+        }
+        if let Some(s) = start {
+            runs.push(s..mvmt.bar.len() as u16);
+        }
+        runs.retain(|run| run.end - run.start >= 2);
+        runs
+    }
+
+    /// Get the name of a part from the instruments of its channels, falling
+    /// back to the channel numbers.
+    fn part_name(&self, chans: &[u16]) -> String {
+        let instrument = |chan: &u16| {
+            let name = self.style.instrument.get(*chan as usize);
+            name.filter(|name| !name.is_empty())
+        };
+        if chans.iter().all(|chan| instrument(chan).is_none()) {
+            let numbers: Vec<String> =
+                chans.iter().map(|chan| (chan + 1).to_string()).collect();
+            return match numbers.len() {
+                1 => format!("Channel {}", numbers[0]),
+                _ => format!("Channels {}", numbers.join(", ")),
+            };
+        }
+        let mut names: Vec<String> = vec![];
+        for chan in chans {
+            let name = match instrument(chan) {
+                Some(name) => name.clone(),
+                None => format!("Channel {}", chan + 1),
+            };
+            // Staves of one instrument share its name.
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+        names.join(", ")
+    }
+}
+
+/// Get the instruments of the channels within a part, up to the last named.
+fn part_instruments(instruments: &[String], chans: &[u16]) -> Vec<String> {
+    let mut names: Vec<String> = chans
+        .iter()
+        .map(|chan| {
+            instruments.get(*chan as usize).cloned().unwrap_or_default()
+        })
+        .collect();
+    while names.last() == Some(&String::new()) {
+        names.pop();
+    }
+    names
+}
+
+/// Extract some channels of a movement.
+fn part_movement(mvmt: &Movement, chans: &[u16]) -> Movement {
+    let bar = mvmt
+        .bar
+        .iter()
+        .enumerate()
+        .map(|(b, measure)| {
+            let length = mvmt.bar_length(b as u16);
+            let chan = chans
+                .iter()
+                .filter_map(|chan| measure.chan.get(*chan as usize))
+                .map(|channel| whole_measure_rest(channel, length))
+                .collect();
+            Measure {
+                sig: measure.sig.clone(),
+                rehearsal: measure.rehearsal.clone(),
+                chan,
+                repeat: measure.repeat.clone(),
+            }
+        })
+        .collect();
+    Movement {
+        sig: mvmt.sig.clone(),
+        bar,
+    }
+}
+
+/// Replace a channel with only a full bar rest by a whole measure rest.
+fn whole_measure_rest(channel: &Channel, length: Fraction) -> Channel {
+    let mut channel = channel.clone();
+    let rest = match channel.notes.as_slice() {
+        [Marking::Note(note)] => {
+            note.pitch.is_empty() && note.duration == length
+        }
+        _ => false,
+    };
+    if rest && channel.voice.is_empty() {
+        channel.notes.clear();
+    }
+    channel
+}
+
+/// Get the stave groups within a part, with channels renumbered.
+fn part_groups(groups: &[StaveGroup], chans: &[u16]) -> Vec<StaveGroup> {
+    let index = |chan| chans.iter().position(|c| *c == chan);
+    groups
+        .iter()
+        .filter(|group| group.chans().all(|chan| index(chan).is_some()))
+        .filter_map(|group| {
+            Some(StaveGroup {
+                kind: group.kind.clone(),
+                first: index(group.first)? as u16,
+                last: index(group.last)? as u16,
+            })
+        })
+        .collect()
+}
+
+/// Check if a bar has only rests in every channel.
+fn is_rest(measure: &Measure) -> bool {
+    measure.chan.iter().all(|channel| {
+        (0..channel.voice_len())
+            .filter_map(|voice| channel.voice(voice))
+            .flatten()
+            .all(|marking| match marking {
+                Marking::Note(note) => note.pitch.is_empty(),
+                _ => true,
+            })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extract() {
+        let mut scof = Scof::default();
+        scof.style.group.push(StaveGroup {
+            kind: "brace".to_string(),
+            first: 0,
+            last: 1,
+        });
+        let part = scof.extract_part(&[1]);
+        assert_eq!(part.meta.part.as_deref(), Some("Channel 2"));
+        assert_eq!(part.meta.composer, scof.meta.composer);
+        assert!(part.style.group.is_empty());
+        let mvmt = &part.movement[0];
+        assert_eq!(mvmt.bar.len(), 4);
+        assert_eq!(mvmt.bar[0].chan.len(), 1);
+        assert_eq!(mvmt.bar[0].chan[0], scof.movement[0].bar[0].chan[1]);
+        assert_eq!(mvmt.sig, scof.movement[0].sig);
+        let both = scof.extract_part(&[0, 1]);
+        assert_eq!(both.meta.part.as_deref(), Some("Channels 1, 2"));
+        assert_eq!(both.style.group, scof.style.group);
+    }
+
+    #[test]
+    fn instruments() {
+        let mut scof = Scof::default();
+        scof.style.instrument = vec!["Flute".to_string()];
+        let part = scof.extract_part(&[0]);
+        assert_eq!(part.meta.part.as_deref(), Some("Flute"));
+        assert_eq!(part.style.instrument, vec!["Flute"]);
+        // Unnamed channels fall back to their numbers.
+        let part = scof.extract_part(&[1]);
+        assert_eq!(part.meta.part.as_deref(), Some("Channel 2"));
+        assert!(part.style.instrument.is_empty());
+        let both = scof.extract_part(&[0, 1]);
+        assert_eq!(both.meta.part.as_deref(), Some("Flute, Channel 2"));
+        scof.style.instrument.push("Flute".to_string());
+        let both = scof.extract_part(&[0, 1]);
+        assert_eq!(both.meta.part.as_deref(), Some("Flute"));
+    }
+
+    #[test]
+    fn rests() {
+        let text = "sig: 0\n  time: 4/4\n  tempo: 120\n\
+            bar:\n  chan: 1/1C4\n  chan: 1/1R\n\
+            bar:\n  chan: 1/1R\n  chan: 1/1R\n\
+            bar:\n  chan: 1/1R\n  chan: 1/1R\n\
+            bar:\n  chan: 1/1R\n  chan: 1/1R\n\
+            bar:\n  chan: 1/2R 1/2R\n  chan: 1/1R\n\
+            bar:\n  chan: 1/1D4\n  chan: 1/1D4\n";
+        let scof = Scof {
+            movement: vec![Movement::from_muon(text).unwrap()],
+            ..Default::default()
+        };
+        assert_eq!(scof.multi_rests(0), vec![1..5]);
+        // A rehearsal mark starts a new multi-bar rest.
+        let mut marked = Scof {
+            movement: vec![Movement::from_muon(text).unwrap()],
+            ..Default::default()
+        };
+        marked.movement[0].bar[3].rehearsal = Some("A".to_string());
+        assert_eq!(marked.multi_rests(0), vec![1..3, 3..5]);
+        // So does a change of signature, but not restating the same one.
+        let sig = |index| {
+            let text = format!(
+                "sig: 0\n  time: 4/4\n  tempo: 120\n\
+                 sig: 0\n  time: 4/4\n  tempo: 60\n\
+                 bar:\n  chan: 1/1C4\n\
+                 bar:\n  chan: 1/1R\n\
+                 bar:\n  sig: {0}\n  chan: 1/1R\n\
+                 bar:\n  sig: {0}\n  chan: 1/1R\n\
+                 bar:\n  chan: 1/1R\n\
+                 bar:\n  chan: 1/1D4\n",
+                index
+            );
+            let scof = Scof {
+                movement: vec![Movement::from_muon(&text).unwrap()],
+                ..Default::default()
+            };
+            scof.multi_rests(0)
+        };
+        assert_eq!(sig(0), vec![1..5]);
+        assert_eq!(sig(1), vec![2..5]);
+        let part = scof.extract_part(&[1]);
+        assert!(part.movement[0].bar[0].chan[0].notes.is_empty());
+        // One bar before the entrance is left for cues.
+        assert_eq!(part.multi_rests(0), vec![0..4]);
+        assert!(scof.multi_rests(1).is_empty());
+        let mut full = scof;
+        full.style.multi_rests = Some(false);
+        assert!(full.multi_rests(0).is_empty());
+        let part = full.extract_part(&[1]);
+        assert_eq!(part.multi_rests(0), vec![0..4]);
+    }
+}
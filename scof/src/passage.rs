@@ -0,0 +1,381 @@
+//! Passages of a voice, cut out by time and written back across barlines
+
+use crate::{
+    Articulation, Cursor, Fraction, IsZero, Marking, Movement, Note, Scof,
+};
+
+/// Get the length of a bar, continuing the last time signature past the end
+/// of the movement.
+fn bar_length(mvmt: &Movement, bar: u16) -> Fraction {
+    let last = mvmt.bar.len().saturating_sub(1) as u16;
+    mvmt.bar_length(bar.min(last))
+}
+
+/// Check if a marking between notes goes with the note before it, rather
+/// than the note after it.
+fn follows_note(marking: &Marking) -> bool {
+    matches!(
+        marking,
+        Marking::Breath
+            | Marking::CaesuraShort
+            | Marking::CaesuraLong
+            | Marking::GraceOutOf(_)
+    )
+}
+
+/// Check if the markings of a bar are only a rest for the whole bar.
+fn is_bar_rest(markings: &[Marking], length: Fraction) -> bool {
+    match markings {
+        [Marking::Note(note)] => {
+            note.pitch.is_empty()
+                && note.articulation.is_empty()
+                && note.duration == length
+        }
+        _ => false,
+    }
+}
+
+/// Create a rest.
+fn rest(duration: Fraction) -> Marking {
+    Marking::Note(Note {
+        pitch: vec![],
+        duration,
+        articulation: vec![],
+    })
+}
+
+/// Check if a duration can be written as one note (plain or dotted).
+fn is_simple(duration: Fraction) -> bool {
+    let duration = duration.simplify();
+    (duration.num == 1 || duration.num == 3) && duration.den.is_power_of_two()
+}
+
+/// Add a note to the markings of a bar, joining it to the note before if
+/// that's tied to it (or both are rests) and they fit in one note.
+fn push_note(bar: &mut Vec<Marking>, mut note: Note) {
+    if let Some(Marking::Note(prev)) = bar.last_mut() {
+        let tied = prev.articulation.contains(&Articulation::Tie)
+            && prev.pitch == note.pitch;
+        let rests = prev.pitch.is_empty()
+            && note.pitch.is_empty()
+            && prev.articulation.is_empty()
+            && note.articulation.is_empty();
+        if (tied || rests) && is_simple(prev.duration + note.duration) {
+            prev.duration = (prev.duration + note.duration).simplify();
+            prev.articulation.retain(|a| *a != Articulation::Tie);
+            prev.articulation.append(&mut note.articulation);
+            return;
+        }
+    }
+    bar.push(Marking::Note(note));
+}
+
+/// Get the index of the passage between `cuts` which a time is in.
+fn passage(cuts: &[Fraction], time: Fraction) -> usize {
+    let last = cuts.len().saturating_sub(2);
+    cuts.iter().rposition(|c| *c <= time).unwrap_or(0).min(last)
+}
+
+impl Scof {
+    /// Get the passages of the voice at a cursor between each pair of
+    /// `times` (from the start of the movement).  Notes crossing a time are
+    /// split there.  Markings between notes go with the note they belong to:
+    /// breath marks, caesuras and grace notes out of a note with the note
+    /// before, and the others with the note after.
+    pub fn passages(
+        &self,
+        cursor: &Cursor,
+        times: &[Fraction],
+    ) -> Vec<Vec<Marking>> {
+        match self.cut(cursor, times) {
+            Some((_, mut passages)) => {
+                passages.pop();
+                passages.remove(0);
+                passages
+            }
+            None => vec![],
+        }
+    }
+
+    /// Swap the passages of the voice at a cursor from `start` to `middle`
+    /// and from `middle` to `end` (times from the start of the movement).
+    /// Bars are added to the end of the movement as needed.
+    pub fn swap_passages(
+        &mut self,
+        cursor: &Cursor,
+        start: Fraction,
+        middle: Fraction,
+        end: Fraction,
+    ) {
+        self.rewrite(cursor, &[start, middle, end], |mut passages| {
+            passages.swap(1, 2);
+            passages
+        });
+    }
+
+    /// Overwrite the voice at a cursor with markings, from the time of the
+    /// cursor.  Notes are split across barlines (and tied), and bars are
+    /// added to the end of the movement as needed.
+    pub fn overwrite(&mut self, cursor: &Cursor, markings: &[Marking]) {
+        let start = self.time(cursor);
+        let mut end = start;
+        for marking in markings {
+            if let Marking::Note(note) = marking {
+                end += note.duration;
+            }
+        }
+        self.rewrite(cursor, &[start, end], |mut passages| {
+            passages[1] = markings.to_vec();
+            passages
+        });
+    }
+
+    /// Cut the voice at a cursor into passages between `times`, and write
+    /// back the passages returned by `edit`.  The first and last passages
+    /// are the parts of the bars before and after the times.
+    fn rewrite<F>(&mut self, cursor: &Cursor, times: &[Fraction], edit: F)
+    where
+        F: FnOnce(Vec<Vec<Marking>>) -> Vec<Vec<Marking>>,
+    {
+        if let Some((bar, passages)) = self.cut(cursor, times) {
+            let markings = edit(passages).into_iter().flatten().collect();
+            let cursor = Cursor {
+                bar,
+                marking: 0,
+                ..*cursor
+            };
+            self.set_bars(&cursor, markings);
+        }
+    }
+
+    /// Cut the bars of the voice at a cursor containing `times` into
+    /// passages, returning the first bar.  Bars past the end of the movement
+    /// are rests.
+    fn cut(
+        &self,
+        cursor: &Cursor,
+        times: &[Fraction],
+    ) -> Option<(u16, Vec<Vec<Marking>>)> {
+        let mvmt = self.movement.get(cursor.movement as usize)?;
+        let (first, last) = (*times.first()?, *times.last()?);
+        let (mut bar, mut time) = (0, Fraction::new(0, 1));
+        while time + bar_length(mvmt, bar) <= first {
+            time += bar_length(mvmt, bar);
+            bar += 1;
+        }
+        let first_bar = bar;
+        let mut cuts = vec![time];
+        cuts.extend_from_slice(times);
+        // Markings of the bars, with the time each starts
+        let mut timed = vec![];
+        loop {
+            let length = bar_length(mvmt, bar);
+            let markings = mvmt
+                .bar
+                .get(bar as usize)
+                .and_then(|measure| measure.chan.get(cursor.chan as usize))
+                .and_then(|chan| chan.voice(cursor.voice))
+                .filter(|markings| !markings.is_empty())
+                .cloned()
+                .unwrap_or_else(|| vec![rest(length)]);
+            let mut start = time;
+            for marking in markings {
+                let duration = match &marking {
+                    Marking::Note(note) => note.duration,
+                    _ => Fraction::new(0, 1),
+                };
+                timed.push((start, marking));
+                start += duration;
+            }
+            time += length;
+            bar += 1;
+            if time >= last {
+                break;
+            }
+        }
+        cuts.push(time);
+        // Split notes at the cuts, and find the passage of each note.
+        let mut placed: Vec<(Option<usize>, Marking)> = vec![];
+        for (mut start, marking) in timed {
+            let mut note = match marking {
+                Marking::Note(note) => note,
+                marking => {
+                    placed.push((None, marking));
+                    continue;
+                }
+            };
+            let end = start + note.duration;
+            for cut in cuts.iter().cloned() {
+                if start < cut && cut < end {
+                    let after = note.split(cut - start);
+                    placed.push((
+                        Some(passage(&cuts, start)),
+                        Marking::Note(note),
+                    ));
+                    note = after;
+                    start = cut;
+                }
+            }
+            placed.push((Some(passage(&cuts, start)), Marking::Note(note)));
+        }
+        // Markings between notes go with the note before or after.
+        let mut passages = vec![vec![]; cuts.len() - 1];
+        for i in 0..placed.len() {
+            let before = placed[..i].iter().rev().find_map(|(p, _)| *p);
+            let after = placed[i..].iter().find_map(|(p, _)| *p);
+            let (index, marking) = &placed[i];
+            let index = match (index, before, after) {
+                (Some(index), _, _) => *index,
+                (None, Some(before), _) if follows_note(marking) => before,
+                (None, _, Some(after)) => after,
+                (None, before, None) => before.unwrap_or(0),
+            };
+            passages[index].push(marking.clone());
+        }
+        Some((first_bar, passages))
+    }
+
+    /// Write markings to the voice at a cursor from the start of its bar,
+    /// splitting notes at the barlines (tied over them).  Bars are added to
+    /// the end of the movement as needed, and bars with only a rest become
+    /// whole measure rests.
+    fn set_bars(&mut self, cursor: &Cursor, markings: Vec<Marking>) {
+        let mut cursor = cursor.first_marking();
+        let mvmt = cursor.movement as usize;
+        let mut room = bar_length(&self.movement[mvmt], cursor.bar);
+        let mut bar = vec![];
+        // Where the next bar starts, once this bar is full
+        let mut next = None;
+        for marking in markings {
+            let mut note = match marking {
+                Marking::Note(note) => note,
+                marking => {
+                    if room.is_zero()
+                        && next.is_none()
+                        && !follows_note(&marking)
+                    {
+                        next = Some(bar.len());
+                    }
+                    bar.push(marking);
+                    continue;
+                }
+            };
+            loop {
+                if room.is_zero() {
+                    let markings =
+                        bar.split_off(next.take().unwrap_or(bar.len()));
+                    if !self
+                        .set_bar(&cursor, std::mem::replace(&mut bar, markings))
+                    {
+                        return;
+                    }
+                    cursor.bar += 1;
+                    room = bar_length(&self.movement[mvmt], cursor.bar);
+                }
+                if note.duration <= room {
+                    room -= note.duration;
+                    push_note(&mut bar, note);
+                    break;
+                }
+                let after = note.split_tied(room);
+                push_note(&mut bar, note);
+                room = Fraction::new(0, 1);
+                note = after;
+            }
+        }
+        if !bar.is_empty() {
+            if !room.is_zero() {
+                bar.push(rest(room.simplify()));
+            }
+            self.set_bar(&cursor, bar);
+        }
+    }
+
+    /// Set the markings of the voice at a cursor's bar, adding bars to the
+    /// end of the movement as needed.  Returns false if the channel doesn't
+    /// exist.
+    fn set_bar(&mut self, cursor: &Cursor, mut markings: Vec<Marking>) -> bool {
+        if !self.fill_bar(cursor) {
+            return false;
+        }
+        let length =
+            self.movement[cursor.movement as usize].bar_length(cursor.bar);
+        if is_bar_rest(&markings, length) {
+            markings.clear();
+        }
+        *self.chan_notes_mut(cursor).unwrap() = markings;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Create a score with one channel of 2/4 bars.
+    fn score(bars: &[&str]) -> Scof {
+        let mut text = "sig: 0\n  time: 2/4\n  tempo: 120\n".to_string();
+        for chan in bars {
+            text.push_str(&format!("bar:\n  chan: {}\n", chan));
+        }
+        Scof {
+            movement: vec![Movement::from_muon(&text).unwrap()],
+            ..Default::default()
+        }
+    }
+
+    /// Get the markings of each bar as text.
+    fn bars(scof: &Scof) -> Vec<String> {
+        let bars = scof.movement[0].bar.len() as u16;
+        (0..bars)
+            .map(|bar| {
+                let cursor = Cursor::new(0, bar, 0, 0);
+                let len = scof.marking_len(&cursor);
+                let markings: Vec<String> = (0..len)
+                    .filter_map(|marking| {
+                        let cursor = Cursor { marking, ..cursor };
+                        Some(scof.marking(&cursor)?.to_string())
+                    })
+                    .collect();
+                markings.join(" ")
+            })
+            .collect()
+    }
+
+    #[test]
+    fn passages() {
+        let scof = score(&["mf 1/4C4 1/4D4 `", "1/2E4"]);
+        let cursor = Cursor::new(0, 0, 0, 0);
+        let times = [Fraction::new(1, 8), Fraction::new(5, 8)];
+        let passages = scof.passages(&cursor, &times);
+        let text: Vec<String> =
+            passages[0].iter().map(|m| m.to_string()).collect();
+        // The breath mark goes with the note before it.
+        assert_eq!(text, vec!["1/8C4", "1/4D4", "`", "1/8E4"]);
+    }
+
+    #[test]
+    fn overwrite() {
+        let mut scof = score(&["1/4C4 1/4D4", "1/2R"]);
+        let markings: Vec<Marking> = "1/2E4 p 1/2F4"
+            .split(' ')
+            .map(|m| m.parse().unwrap())
+            .collect();
+        scof.overwrite(&Cursor::new(0, 0, 0, 1), &markings);
+        // Split at the barline and tied, and a bar is added at the end.
+        assert_eq!(
+            bars(&scof),
+            vec!["1/4C4 1/4E4)", "1/4E4 p 1/4F4)", "1/4F4 1/4R"]
+        );
+        let mut scof = score(&["1/2C4", "1/4D4 1/4E4"]);
+        let start = Fraction::new(0, 1);
+        scof.swap_passages(
+            &Cursor::new(0, 0, 0, 0),
+            start,
+            Fraction::new(1, 4),
+            Fraction::new(5, 8),
+        );
+        let notes = vec!["1/4C4 1/8D4 1/8C4)", "1/8C4 1/8D4 1/4E4"];
+        assert_eq!(bars(&scof), notes);
+    }
+}
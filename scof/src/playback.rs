@@ -0,0 +1,802 @@
+//! Playback (convert markings to timed note events)
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+use crate::{
+    Articulation, Fraction, Marking, Measure, Note, Repeat, Scof, Style,
+};
+
+/// Number of ticks in a whole note (divisible by 3 for triplets).
+pub const TICKS_PER_WHOLE: u32 = 1920;
+
+/// A note (or chord) to be played.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NoteEvent {
+    /// Channel playing the note.
+    pub chan: u16,
+    /// Start time in ticks from the beginning of the movement.
+    pub start: u32,
+    /// Length of the note in ticks.
+    pub length: u32,
+    /// MIDI note number for each pitch of the note.
+    pub keys: Vec<u8>,
+    /// MIDI velocity of the note (from the dynamic envelope).
+    pub velocity: u8,
+}
+
+/// A change of tempo.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TempoEvent {
+    /// Start time in ticks from the beginning of the movement.
+    pub start: u32,
+    /// Beats per minute.
+    pub tempo: u16,
+    /// Length of a beat in ticks.
+    pub beat: u32,
+}
+
+impl Default for TempoEvent {
+    fn default() -> Self {
+        TempoEvent {
+            start: 0,
+            tempo: 120,
+            beat: TICKS_PER_WHOLE / 4,
+        }
+    }
+}
+
+impl TempoEvent {
+    /// Get the length of a quarter note in microseconds.
+    pub fn quarter_micros(&self) -> u32 {
+        let quarter = u64::from(TICKS_PER_WHOLE / 4);
+        let beat = u64::from(self.beat.max(1));
+        let tempo = u64::from(self.tempo.max(1));
+        (60_000_000 * quarter / (beat * tempo)) as u32
+    }
+
+    /// Check if another tempo event has the same tempo.
+    fn same_tempo(&self, other: &TempoEvent) -> bool {
+        self.tempo == other.tempo && self.beat == other.beat
+    }
+}
+
+impl NoteEvent {
+    /// Get the gain (amplitude from 0.0 to 1.0) to play the note with.
+    pub fn gain(&self) -> f32 {
+        let level = f32::from(self.velocity) / 127.0;
+        level * level
+    }
+}
+
+/// Length of each note of a trill in ticks (32nd notes).
+const TRILL_TICKS: u32 = TICKS_PER_WHOLE / 32;
+/// Delay between the notes of an arpeggio in ticks (64th notes).
+const STRUM_TICKS: u32 = TICKS_PER_WHOLE / 64;
+/// Length of a grace note in ticks (32nd notes).
+const GRACE_TICKS: u32 = TICKS_PER_WHOLE / 32;
+
+/// Convert a fraction of a whole note to ticks.
+pub(crate) fn ticks(duration: Fraction) -> u32 {
+    u32::from(duration.num) * TICKS_PER_WHOLE / u32::from(duration.den)
+}
+
+impl Scof {
+    /// Get the note events of a movement, sorted by start time.  Bars are
+    /// played in [`playback_order`].  Each bar starts after the length of the
+    /// previous bar's time signature, so incomplete bars are padded with
+    /// silence.  Every channel holds at a fermata and waits at a caesura,
+    /// for the lengths set in the style.  Grace notes take their time from
+    /// the start of the next note, or the end of the previous note.  A note
+    /// tied to the next note is held through it, as one event.
+    ///
+    /// [`playback_order`]: Scof::playback_order
+    pub fn note_events(&self, movement: u16) -> Vec<NoteEvent> {
+        let mut events = vec![];
+        let mvmt = match self.movement.get(movement as usize) {
+            Some(mvmt) => mvmt,
+            None => return events,
+        };
+        let chans = mvmt.bar.iter().map(|m| m.chan.len()).max().unwrap_or(0);
+        let envelopes: Vec<_> = (0..chans as u16)
+            .map(|chan| self.envelope(movement, chan))
+            .collect();
+        // Event held by a tie, for each channel and voice
+        let mut held: HashMap<(usize, u16), usize> = HashMap::new();
+        let mut bar_start = 0;
+        for b in self.playback_order(movement) {
+            let measure = &mvmt.bar[b as usize];
+            let pauses = pauses(measure, &self.style);
+            for (c, chan) in measure.chan.iter().enumerate() {
+                for v in 0..chan.voice_len() {
+                    let mut start = 0;
+                    let markings = match chan.voice(v) {
+                        Some(markings) => markings,
+                        None => continue,
+                    };
+                    for (i, marking) in markings.iter().enumerate() {
+                        if let Marking::Note(note) = marking {
+                            let level = envelopes[c].level(b, start);
+                            let velocity = (level * 127.0).round() as u8;
+                            let chan = c as u16;
+                            let end = start + ticks(note.duration);
+                            let keys: Vec<u8> = note
+                                .pitch
+                                .iter()
+                                .filter_map(|p| p.midi())
+                                .collect();
+                            let mut tie = held.remove(&(c, v));
+                            let mut main = None;
+                            for mut event in play_graces(
+                                markings,
+                                i,
+                                chan,
+                                start..end,
+                                velocity,
+                            ) {
+                                let end = event.start + event.length;
+                                let end = warp(&pauses, end, true);
+                                event.start = warp(&pauses, event.start, false);
+                                event.length = end - event.start;
+                                event.start += bar_start;
+                                if let Some(j) = tie.take() {
+                                    if events[j].keys == event.keys {
+                                        let end = event.start + event.length;
+                                        events[j].length =
+                                            end - events[j].start;
+                                        main = Some(j);
+                                        continue;
+                                    }
+                                }
+                                if event.keys == keys {
+                                    main = Some(events.len());
+                                }
+                                events.push(event);
+                            }
+                            if note.articulation.contains(&Articulation::Tie) {
+                                if let Some(main) = main {
+                                    held.insert((c, v), main);
+                                }
+                            }
+                            start += ticks(note.duration);
+                        }
+                    }
+                }
+            }
+            bar_start += warp(&pauses, ticks(mvmt.bar_length(b)), false);
+        }
+        events.sort_by_key(|e| (e.start, e.chan));
+        events
+    }
+
+    /// Get the order that the bars of a movement are played in, unrolling
+    /// repeats, endings and jumps.  Repeats and jumps are not taken again
+    /// after a D.C. or D.S. jump, and only the last ending is played.
+    pub fn playback_order(&self, movement: u16) -> Vec<u16> {
+        let mut order = vec![];
+        let bars = match self.movement.get(movement as usize) {
+            Some(mvmt) => &mvmt.bar,
+            None => return order,
+        };
+        // Number of times each close repeat has been taken.
+        let mut taken = vec![0; bars.len()];
+        let mut jumped = false;
+        let mut b = 0;
+        while b < bars.len() {
+            let measure = &bars[b];
+            let group = volta(bars, b);
+            let passes = group
+                .clone()
+                .flat_map(|v| bars[v].endings())
+                .max()
+                .unwrap_or(0)
+                .max(2);
+            let pass = 1 + group.map(|v| taken[v]).sum::<u8>();
+            let endings = measure.endings();
+            let current = if jumped { passes } else { pass };
+            if !endings.is_empty() && !endings.contains(&current) {
+                b += 1;
+                continue;
+            }
+            order.push(b as u16);
+            if jumped {
+                if measure.has_repeat(Repeat::Fine) {
+                    break;
+                }
+                let coda = (b + 1..bars.len())
+                    .find(|c| bars[*c].has_repeat(Repeat::Coda));
+                if let (true, Some(coda)) =
+                    (measure.has_repeat(Repeat::ToCoda), coda)
+                {
+                    b = coda;
+                    continue;
+                }
+            } else if measure.has_repeat(Repeat::Close) && pass < passes {
+                taken[b] += 1;
+                b = section_start(bars, b);
+                continue;
+            } else if measure.has_repeat(Repeat::DC) {
+                jumped = true;
+                b = 0;
+                continue;
+            } else if measure.has_repeat(Repeat::DS) {
+                jumped = true;
+                b = bars
+                    .iter()
+                    .position(|m| m.has_repeat(Repeat::Segno))
+                    .unwrap_or(0);
+                continue;
+            }
+            b += 1;
+        }
+        order
+    }
+
+    /// Get the starting tempo of a movement in beats per minute (120 if not
+    /// set).
+    pub fn tempo(&self, movement: u16) -> u16 {
+        self.movement
+            .get(movement as usize)
+            .and_then(|mvmt| mvmt.sig(0))
+            .map(|sig| sig.tempo)
+            .filter(|tempo| *tempo != 0)
+            .unwrap_or(120)
+    }
+
+    /// Get the changes of tempo of a movement, timed like its note events.
+    /// The first is at the start (120 quarter notes per minute if not set).
+    pub fn tempo_events(&self, movement: u16) -> Vec<TempoEvent> {
+        let mut events: Vec<TempoEvent> = vec![];
+        let mvmt = match self.movement.get(movement as usize) {
+            Some(mvmt) => mvmt,
+            None => return vec![TempoEvent::default()],
+        };
+        let mut bar_start = 0;
+        for b in self.playback_order(movement) {
+            if let Some(sig) = mvmt.sig(b) {
+                let event = TempoEvent {
+                    start: bar_start,
+                    tempo: if sig.tempo == 0 { 120 } else { sig.tempo },
+                    beat: ticks(sig.beat()),
+                };
+                match events.last() {
+                    Some(last) if last.same_tempo(&event) => {}
+                    _ => events.push(event),
+                }
+            }
+            let pauses = pauses(&mvmt.bar[b as usize], &self.style);
+            bar_start += warp(&pauses, ticks(mvmt.bar_length(b)), false);
+        }
+        if events.is_empty() {
+            events.push(TempoEvent::default());
+        }
+        events
+    }
+
+    /// Get the time in seconds at a time in ticks from the beginning of a
+    /// movement, following its changes of tempo.
+    pub fn seconds(&self, movement: u16, time: u32) -> f32 {
+        let events = self.tempo_events(movement);
+        let mut seconds = 0.0;
+        for (i, event) in events.iter().enumerate() {
+            let end = events.get(i + 1).map_or(time, |e| e.start.min(time));
+            let beats = end.saturating_sub(event.start) as f32
+                / event.beat.max(1) as f32;
+            seconds += beats * 60.0 / f32::from(event.tempo.max(1));
+        }
+        seconds
+    }
+}
+
+/// Get the bars of the endings (voltas) around a bar, or only the bar if it
+/// isn't in an ending.
+fn volta(bars: &[Measure], bar: usize) -> Range<usize> {
+    let in_ending = |b: &usize| !bars[*b].endings().is_empty();
+    if !in_ending(&bar) {
+        return bar..bar + 1;
+    }
+    let start = (0..bar).rev().find(|b| !in_ending(b)).map_or(0, |b| b + 1);
+    let end = (bar..bars.len())
+        .find(|b| !in_ending(b))
+        .unwrap_or(bars.len());
+    start..end
+}
+
+/// A pause in a bar, where every channel holds (at a fermata) or waits (at
+/// a caesura).
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Pause {
+    /// Time in the bar where the pause starts (in ticks)
+    at: u32,
+    /// Length of the held note (0 for a caesura)
+    span: u32,
+    /// Time added by the pause (in ticks)
+    extra: u32,
+}
+
+/// Get the pauses of a bar (the longest of each kind at each time).
+fn pauses(measure: &Measure, style: &Style) -> Vec<Pause> {
+    let mut pauses: Vec<Pause> = vec![];
+    let stretch = u32::from(style.fermata_stretch()).saturating_sub(100);
+    let caesura = u32::from(style.caesura_pause()) * TICKS_PER_WHOLE / 8;
+    for chan in &measure.chan {
+        for v in 0..chan.voice_len() {
+            let mut time = 0;
+            for marking in chan.voice(v).into_iter().flatten() {
+                let at = time;
+                let pause = match marking {
+                    Marking::Note(note) => {
+                        let span = ticks(note.duration);
+                        time += span;
+                        if !note.articulation.contains(&Articulation::Fermata) {
+                            continue;
+                        }
+                        let extra = span * stretch / 100;
+                        Pause { at, span, extra }
+                    }
+                    Marking::CaesuraShort => Pause {
+                        at,
+                        span: 0,
+                        extra: caesura,
+                    },
+                    Marking::CaesuraLong => Pause {
+                        at,
+                        span: 0,
+                        extra: caesura * 2,
+                    },
+                    _ => continue,
+                };
+                let same = |p: &&mut Pause| {
+                    p.at == pause.at && (p.span == 0) == (pause.span == 0)
+                };
+                match pauses.iter_mut().find(same) {
+                    Some(p) if p.extra < pause.extra => *p = pause,
+                    Some(_) => {}
+                    None => pauses.push(pause),
+                }
+            }
+        }
+    }
+    pauses
+}
+
+/// Get a time in a bar (in ticks) after adding the pauses before it.  A note
+/// ending at a caesura isn't held through it.
+fn warp(pauses: &[Pause], time: u32, end: bool) -> u32 {
+    let extra: u32 = pauses
+        .iter()
+        .map(|p| {
+            if p.span == 0 {
+                if time > p.at || (time == p.at && !end) {
+                    p.extra
+                } else {
+                    0
+                }
+            } else if time >= p.at + p.span {
+                p.extra
+            } else if time > p.at {
+                p.extra * (time - p.at) / p.span
+            } else {
+                0
+            }
+        })
+        .sum();
+    time + extra
+}
+
+/// Get the events for playing the note at an index of a voice's markings,
+/// and the grace notes into and out of it.  The grace notes are played
+/// quickly (taking up to half of the note's time), so that they don't
+/// change the rhythm.
+fn play_graces(
+    markings: &[Marking],
+    index: usize,
+    chan: u16,
+    time: Range<u32>,
+    velocity: u8,
+) -> Vec<NoteEvent> {
+    let note = match &markings[index] {
+        Marking::Note(note) => note,
+        _ => return vec![],
+    };
+    let into: Vec<&Note> = markings[..index]
+        .iter()
+        .rev()
+        .map_while(|m| match m {
+            Marking::GraceInto(grace) => Some(grace),
+            _ => None,
+        })
+        .collect();
+    let out_of: Vec<&Note> = markings[index + 1..]
+        .iter()
+        .map_while(|m| match m {
+            Marking::GraceOutOf(grace) => Some(grace),
+            _ => None,
+        })
+        .collect();
+    let graces = (into.len() + out_of.len()) as u32;
+    if graces == 0 {
+        return play_note(note, chan, time, velocity);
+    }
+    let length = time.end - time.start;
+    let each = GRACE_TICKS.min(length / 2 / graces).max(1);
+    let start = time.start + each * into.len() as u32;
+    let end = time.end - each * out_of.len() as u32;
+    let grace = |start, grace: &Note| NoteEvent {
+        chan,
+        start,
+        length: each,
+        keys: grace.pitch.iter().filter_map(|p| p.midi()).collect(),
+        velocity,
+    };
+    let mut events: Vec<NoteEvent> = into
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, g)| grace(time.start + i as u32 * each, g))
+        .collect();
+    events.extend(play_note(note, chan, start..end, velocity));
+    events.extend(
+        out_of
+            .iter()
+            .enumerate()
+            .map(|(i, g)| grace(end + i as u32 * each, g)),
+    );
+    events.retain(|e| !e.keys.is_empty());
+    events
+}
+
+/// Get the events for playing a note, with an ornament expanded into the
+/// notes which are played.
+fn play_note(
+    note: &Note,
+    chan: u16,
+    time: Range<u32>,
+    velocity: u8,
+) -> Vec<NoteEvent> {
+    let (start, length) = (time.start, time.end - time.start);
+    let keys: Vec<u8> = note.pitch.iter().filter_map(|p| p.midi()).collect();
+    if keys.is_empty() {
+        return vec![];
+    }
+    let event = |start, length, keys| NoteEvent {
+        chan,
+        start,
+        length,
+        keys,
+        velocity,
+    };
+    let ornament = note.articulation.iter().find(|a| a.is_ornament());
+    let (upper, lower) = (neighbour(note, true), neighbour(note, false));
+    let (notes, each) = match ornament {
+        Some(Articulation::Trill) => (vec![keys, upper], TRILL_TICKS),
+        Some(Articulation::Tremelo(slashes)) => {
+            (vec![keys], TICKS_PER_WHOLE / (4 << slashes))
+        }
+        Some(Articulation::Turn) => {
+            (vec![upper, keys.clone(), lower, keys], length / 4)
+        }
+        Some(Articulation::TurnInverted) => {
+            (vec![lower, keys.clone(), upper, keys], length / 4)
+        }
+        Some(Articulation::StrumDown) | Some(Articulation::StrumUp) => {
+            let mut keys = keys;
+            keys.sort_unstable();
+            if ornament == Some(&Articulation::StrumUp) {
+                keys.reverse();
+            }
+            // Each pitch starts later, and all end together.
+            return keys
+                .into_iter()
+                .enumerate()
+                .map(|(i, key)| {
+                    let delay = (i as u32 * STRUM_TICKS).min(length - 1);
+                    event(start + delay, length - delay, vec![key])
+                })
+                .collect();
+        }
+        _ => return vec![event(start, length, keys)],
+    };
+    // Cycle through the notes until the end of the note.
+    let each = each.max(1);
+    (0..length)
+        .step_by(each as usize)
+        .zip(notes.iter().cycle())
+        .map(|(t, keys)| event(start + t, each.min(length - t), keys.clone()))
+        .collect()
+}
+
+/// Get the MIDI note numbers of the pitches one step above or below each
+/// pitch of a note.
+fn neighbour(note: &Note, up: bool) -> Vec<u8> {
+    (0..note.pitch.len())
+        .filter_map(|i| {
+            let pitch = note.pitch[i];
+            let step = if up {
+                note.step_up(i, pitch)
+            } else {
+                note.step_down(i, pitch)
+            };
+            step.pitch[i].midi()
+        })
+        .collect()
+}
+
+/// Get the bar that a close repeat jumps back to: an open repeat, or else
+/// the bar after the previous close repeat or endings (or the beginning).
+fn section_start(bars: &[Measure], close: usize) -> usize {
+    let group = volta(bars, close);
+    for b in (0..=close).rev() {
+        if bars[b].has_repeat(Repeat::Open) {
+            return b;
+        }
+        let before = b < group.start;
+        if before
+            && (bars[b].has_repeat(Repeat::Close)
+                || !bars[b].endings().is_empty())
+        {
+            return b + 1;
+        }
+    }
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_score() {
+        let scof = Scof::default();
+        let events = scof.note_events(0);
+        assert_eq!(
+            events[0],
+            NoteEvent {
+                chan: 0,
+                start: 0,
+                length: 480,
+                keys: vec![60],
+                velocity: 80,
+            }
+        );
+        assert_eq!(events[1].keys, vec![57]);
+        assert_eq!(events[1].length, 240);
+        // Second bar starts after a whole note.
+        let second = events.iter().find(|e| e.start >= 1920).unwrap();
+        assert_eq!(second.start, 1920);
+        assert_eq!(second.keys, vec![62]);
+        // Rests are not events.
+        assert_eq!(events.len(), 6 + 5 + 9 + 2 + 2 + 2 + 7 + 1);
+        assert_eq!(scof.tempo(0), 120);
+        assert!(scof.note_events(1).is_empty());
+    }
+
+    #[test]
+    fn tempo_changes() {
+        let text = "sig: 0\n  time: 4/4\n  tempo: 60\n\
+             sig: 0\n  time: 12/8\n  tempo: 60\n\
+             sig: 0\n  time: 2/2\n  tempo: 60\n\
+             bar:\n  chan: 1/1R\n\
+             bar:\n  sig: 1\n  chan: 3/2R\n\
+             bar:\n  sig: 2\n  chan: 1/1R\n";
+        let scof = Scof {
+            movement: vec![crate::Movement::from_muon(text).unwrap()],
+            ..Default::default()
+        };
+        let events = scof.tempo_events(0);
+        assert_eq!(events.len(), 3);
+        assert_eq!((events[1].start, events[1].beat), (1920, 720));
+        // A dotted quarter (and a half note) at 60 BPM.
+        assert_eq!(events[0].quarter_micros(), 1_000_000);
+        assert_eq!(events[1].quarter_micros(), 666_666);
+        assert_eq!(events[2].quarter_micros(), 500_000);
+        // 4 quarter notes, 4 dotted quarters and 2 half notes.
+        assert_eq!(scof.seconds(0, 1920 * 7 / 2), 10.0);
+    }
+
+    /// Get the playback order of bars with repeat markings.
+    fn order(repeats: &[&str]) -> Vec<u16> {
+        let bar = repeats
+            .iter()
+            .map(|r| Measure {
+                sig: None,
+                rehearsal: None,
+                chan: vec![],
+                repeat: r.split_whitespace().map(String::from).collect(),
+            })
+            .collect();
+        let scof = Scof {
+            movement: vec![crate::Movement { sig: vec![], bar }],
+            ..Default::default()
+        };
+        scof.playback_order(0)
+    }
+
+    #[test]
+    fn repeats() {
+        assert_eq!(order(&["", "", ""]), vec![0, 1, 2]);
+        assert_eq!(order(&["", "Close", ""]), vec![0, 1, 0, 1, 2]);
+        assert_eq!(
+            order(&["", "Open", "Close", "Open", "Close"]),
+            vec![0, 1, 2, 1, 2, 3, 4, 3, 4]
+        );
+        // Repeat back to the bar after the previous close repeat.
+        assert_eq!(order(&["Close", "", "Close"]), vec![0, 0, 1, 2, 1, 2]);
+    }
+
+    #[test]
+    fn endings() {
+        assert_eq!(
+            order(&["", "Ending1 Close", "Ending2", ""]),
+            vec![0, 1, 0, 2, 3]
+        );
+        assert_eq!(
+            order(&["", "Ending1 Ending2 Close", "Ending3"]),
+            vec![0, 1, 0, 1, 0, 2]
+        );
+        assert_eq!(
+            order(&["", "Ending1 Close", "Ending2 Close", "Ending3", ""]),
+            vec![0, 1, 0, 2, 0, 3, 4]
+        );
+    }
+
+    #[test]
+    fn jumps() {
+        assert_eq!(order(&["", "Fine", "DC"]), vec![0, 1, 2, 0, 1]);
+        assert_eq!(
+            order(&["", "Segno", "ToCoda", "DS", "Coda"]),
+            vec![0, 1, 2, 3, 1, 2, 4]
+        );
+        // Repeats aren't taken again after a jump, and the last ending is
+        // played.
+        assert_eq!(
+            order(&["", "Ending1 Close", "Ending2 DC"]),
+            vec![0, 1, 0, 2, 0, 2]
+        );
+    }
+
+    #[test]
+    fn repeat_events() {
+        let mvmt = "sig: 0\n  time: 1/4\n  tempo: 120\n\
+            bar:\n  chan: 1/4C4\n  repeat: Close\n\
+            bar:\n  chan: 1/4D4\n";
+        let scof = Scof {
+            movement: vec![crate::Movement::from_muon(mvmt).unwrap()],
+            ..Default::default()
+        };
+        let events = scof.note_events(0);
+        let keys: Vec<(u32, u8)> =
+            events.iter().map(|e| (e.start, e.keys[0])).collect();
+        assert_eq!(keys, vec![(0, 60), (480, 60), (960, 62)]);
+        assert_eq!("Ending2".parse(), Ok(Repeat::Ending(2)));
+        assert_eq!("Ending0".parse::<Repeat>(), Err(()));
+        assert_eq!(Repeat::ToCoda.to_string(), "ToCoda");
+    }
+
+    #[test]
+    fn tie_events() {
+        let mvmt = "sig: 0\n  time: 2/4\n  tempo: 120\n\
+            bar:\n  chan: 1/4C4 1/4D4)\n\
+            bar:\n  chan: 1/4D4 1/4D4\n";
+        let scof = Scof {
+            movement: vec![crate::Movement::from_muon(mvmt).unwrap()],
+            ..Default::default()
+        };
+        let events: Vec<(u32, u32, u8)> = scof
+            .note_events(0)
+            .iter()
+            .map(|e| (e.start, e.length, e.keys[0]))
+            .collect();
+        assert_eq!(events, vec![(0, 480, 60), (480, 960, 62), (1440, 480, 62)]);
+    }
+
+    #[test]
+    fn ornaments() {
+        let events = |text: &str| {
+            let note: Note = text.parse().unwrap();
+            play_note(&note, 0, 0..ticks(note.duration), 80)
+                .iter()
+                .map(|e| (e.start, e.length, e.keys.clone()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(events("1/4C4"), vec![(0, 480, vec![60])]);
+        let trill = events("1/8C4~");
+        assert_eq!(trill.len(), 4);
+        assert_eq!(trill[1], (60, 60, vec![62]));
+        assert_eq!(trill[3], (180, 60, vec![62]));
+        // Two slashes is sixteenth notes.
+        let tremelo = events("1/4E4=2");
+        assert_eq!(tremelo.len(), 4);
+        assert!(tremelo
+            .iter()
+            .all(|(_, length, keys)| { *length == 120 && keys == &vec![64] }));
+        let keys: Vec<u8> =
+            events("1/4C4s").into_iter().map(|e| e.2[0]).collect();
+        assert_eq!(keys, vec![62, 60, 59, 60]);
+        let keys: Vec<u8> =
+            events("1/4C4z").into_iter().map(|e| e.2[0]).collect();
+        assert_eq!(keys, vec![59, 60, 62, 60]);
+        assert_eq!(
+            events("1/4G4C4E4W"),
+            vec![(0, 480, vec![67]), (30, 450, vec![64]), (60, 420, vec![60]),]
+        );
+        let note: Note = "1/4C4=3M~s".parse().unwrap();
+        assert_eq!(note.to_string(), "1/4C4=3M~s");
+        assert!("1/4C4=6".parse::<Note>().is_err());
+    }
+
+    #[test]
+    fn pauses() {
+        let mvmt = "sig: 0\n  time: 2/4\n  tempo: 120\n\
+            bar:\n  chan: 1/4C4$ 1/4D4 //\n  chan: 1/2E4\n\
+            bar:\n  chan: 1/4C4 || 1/4D4\n  chan: 1/2R\n";
+        let mut scof = Scof {
+            movement: vec![crate::Movement::from_muon(mvmt).unwrap()],
+            ..Default::default()
+        };
+        let events = |scof: &Scof| -> Vec<(u32, u32, u8)> {
+            scof.note_events(0)
+                .iter()
+                .map(|e| (e.start, e.length, e.keys[0]))
+                .collect()
+        };
+        // The fermata doubles the first quarter note (in both channels), and
+        // the long caesura waits for a half note.
+        assert_eq!(
+            events(&scof),
+            vec![
+                (0, 960, 60),
+                (0, 1440, 64),
+                (960, 480, 62),
+                (2400, 480, 60),
+                (3360, 480, 62),
+            ]
+        );
+        scof.style.fermata = Some(150);
+        scof.style.caesura = Some(1);
+        assert_eq!(events(&scof)[1], (0, 1200, 64));
+        assert_eq!(events(&scof)[4], (2400, 480, 62));
+    }
+
+    #[test]
+    fn graces() {
+        let mvmt = "sig: 0\n  time: 2/4\n  tempo: 120\n\
+            bar:\n  chan: {1/8D4 1/4C4 }1/16E4 }1/16F4 1/4G4\n";
+        let scof = Scof {
+            movement: vec![crate::Movement::from_muon(mvmt).unwrap()],
+            ..Default::default()
+        };
+        let events: Vec<(u32, u32, u8)> = scof
+            .note_events(0)
+            .iter()
+            .map(|e| (e.start, e.length, e.keys[0]))
+            .collect();
+        // Grace notes are 32nd notes, stolen from the quarter note, without
+        // moving the next note.
+        assert_eq!(
+            events,
+            vec![
+                (0, 60, 62),
+                (60, 300, 60),
+                (360, 60, 64),
+                (420, 60, 65),
+                (480, 480, 67),
+            ]
+        );
+    }
+
+    #[test]
+    fn voices() {
+        let mvmt = "sig: 0\n  time: 2/4\n  tempo: 120\nbar:\n  chan: 1/2E4\
+            \n    voice: 1/4C4 1/4D4\n";
+        let scof = Scof {
+            movement: vec![crate::Movement::from_muon(mvmt).unwrap()],
+            ..Default::default()
+        };
+        let events = scof.note_events(0);
+        let keys: Vec<(u32, u8)> =
+            events.iter().map(|e| (e.start, e.keys[0])).collect();
+        assert_eq!(keys, vec![(0, 64), (0, 60), (480, 62)]);
+        assert!(events.iter().all(|e| e.chan == 0));
+    }
+}
@@ -0,0 +1,103 @@
+//! Rehearsal marks (letters, numbers or other text at the start of a bar)
+
+use crate::Movement;
+
+impl Movement {
+    /// Get the rehearsal mark at the start of a bar.  An automatic mark
+    /// (`*`) follows the previous letter or number, starting at `A`.
+    pub fn rehearsal(&self, bar: u16) -> Option<String> {
+        self.bar.get(bar as usize)?.rehearsal.as_ref()?;
+        let mut mark = None;
+        let mut sequence: Option<String> = None;
+        for measure in self.bar.iter().take(bar as usize + 1) {
+            mark = match measure.rehearsal.as_deref() {
+                Some("*") => Some(next_mark(sequence.as_deref())),
+                Some(text) => Some(text.to_string()),
+                None => continue,
+            };
+            if matches!(mark.as_deref(), Some(mark) if is_sequential(mark)) {
+                sequence = mark.clone();
+            }
+        }
+        mark
+    }
+
+    /// Get the bars which have a rehearsal mark.
+    pub fn rehearsal_bars(&self) -> impl Iterator<Item = u16> + '_ {
+        self.bar
+            .iter()
+            .enumerate()
+            .filter(|(_, measure)| measure.rehearsal.is_some())
+            .map(|(b, _)| b as u16)
+    }
+}
+
+/// Check if a rehearsal mark is part of a sequence (a number, or capital
+/// letters).
+fn is_sequential(mark: &str) -> bool {
+    !mark.is_empty()
+        && (mark.chars().all(|c| c.is_ascii_digit())
+            || mark.chars().all(|c| c.is_ascii_uppercase()))
+}
+
+/// Get the rehearsal mark after one in a sequence: numbers count up, and
+/// letters go from `Z` to `AA`.
+fn next_mark(prev: Option<&str>) -> String {
+    let prev = match prev {
+        Some(prev) => prev,
+        None => return "A".to_string(),
+    };
+    if let Ok(number) = prev.parse::<u32>() {
+        return (number + 1).to_string();
+    }
+    let mut letters: Vec<u8> = prev.bytes().collect();
+    for letter in letters.iter_mut().rev() {
+        if *letter < b'Z' {
+            *letter += 1;
+            return String::from_utf8(letters).unwrap_or_default();
+        }
+        *letter = b'A';
+    }
+    letters.insert(0, b'A');
+    String::from_utf8(letters).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn marks() {
+        assert_eq!(next_mark(None), "A");
+        assert_eq!(next_mark(Some("B")), "C");
+        assert_eq!(next_mark(Some("Z")), "AA");
+        assert_eq!(next_mark(Some("AZ")), "BA");
+        assert_eq!(next_mark(Some("9")), "10");
+        let text = "sig: 0\n  time: 4/4\n  tempo: 120\n\
+            bar:\n  rehearsal: *\n  chan: 1/1R\n\
+            bar:\n  chan: 1/1R\n\
+            bar:\n  rehearsal: Intro\n  chan: 1/1R\n\
+            bar:\n  rehearsal: *\n  chan: 1/1R\n\
+            bar:\n  rehearsal: 12\n  chan: 1/1R\n\
+            bar:\n  rehearsal: *\n  chan: 1/1R\n";
+        let mvmt = Movement::from_muon(text).unwrap();
+        let marks: Vec<Option<String>> =
+            (0..7).map(|bar| mvmt.rehearsal(bar)).collect();
+        let mark = |m: &str| Some(m.to_string());
+        // Other text doesn't interrupt the sequence.
+        assert_eq!(
+            marks,
+            vec![
+                mark("A"),
+                None,
+                mark("Intro"),
+                mark("B"),
+                mark("12"),
+                mark("13"),
+                None
+            ]
+        );
+        let bars: Vec<u16> = mvmt.rehearsal_bars().collect();
+        assert_eq!(bars, vec![0, 2, 3, 4, 5]);
+    }
+}
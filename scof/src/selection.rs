@@ -0,0 +1,287 @@
+//! Range selection of markings
+
+use std::ops::RangeInclusive;
+
+use crate::{Cursor, Marking, Scof};
+
+/// A range of markings within one movement.
+///
+/// The selection spans from an anchor (where it was started) to a head (which
+/// moves as it is extended).  It covers a range of channels, and in each of
+/// those channels every marking from the start position to the end position.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Selection {
+    /// Where the selection was started
+    anchor: Cursor,
+    /// Moving end of the selection
+    head: Cursor,
+}
+
+impl Selection {
+    /// Create a selection of the marking at a cursor.
+    pub fn new(cursor: Cursor) -> Self {
+        Selection {
+            anchor: cursor.clone(),
+            head: cursor,
+        }
+    }
+
+    /// Create a selection from an anchor to a head cursor (in the same
+    /// movement).
+    pub fn span(anchor: Cursor, head: Cursor) -> Self {
+        Selection { anchor, head }
+    }
+
+    /// Get the cursor where the selection was started.
+    pub fn anchor(&self) -> &Cursor {
+        &self.anchor
+    }
+
+    /// Get the cursor at the moving end of the selection.
+    pub fn head(&self) -> &Cursor {
+        &self.head
+    }
+
+    /// Get the movement number of the selection.
+    pub fn movement(&self) -> u16 {
+        self.anchor.movement
+    }
+
+    /// Check if only one marking is selected.
+    pub fn is_single(&self) -> bool {
+        self.anchor == self.head
+    }
+
+    /// Get the range of selected bars.
+    pub fn bars(&self) -> RangeInclusive<u16> {
+        let (a, b) = (self.anchor.bar, self.head.bar);
+        a.min(b)..=a.max(b)
+    }
+
+    /// Get the range of selected channels.
+    pub fn chans(&self) -> RangeInclusive<u16> {
+        let (a, b) = (self.anchor.chan, self.head.chan);
+        a.min(b)..=a.max(b)
+    }
+
+    /// Get the first selected marking in the top channel.
+    pub fn start(&self) -> Cursor {
+        let (a, b) = (&self.anchor, &self.head);
+        let (bar, marking) = (a.bar, a.marking).min((b.bar, b.marking));
+        self.cursor(bar, *self.chans().start(), marking)
+    }
+
+    /// Get the last selected marking in the bottom channel.
+    pub fn end(&self) -> Cursor {
+        let (a, b) = (&self.anchor, &self.head);
+        let (bar, marking) = (a.bar, a.marking).max((b.bar, b.marking));
+        self.cursor(bar, *self.chans().end(), marking)
+    }
+
+    /// Get a cursor in the selected voice.
+    fn cursor(&self, bar: u16, chan: u16, marking: u16) -> Cursor {
+        Cursor {
+            bar,
+            chan,
+            marking,
+            ..self.anchor
+        }
+    }
+
+    /// Check if a cursor is within the selection.
+    pub fn contains(&self, cursor: &Cursor) -> bool {
+        let (start, end) = (self.start(), self.end());
+        cursor.movement == self.movement()
+            && cursor.voice == self.anchor.voice
+            && self.chans().contains(&cursor.chan)
+            && (start.bar, start.marking) <= (cursor.bar, cursor.marking)
+            && (cursor.bar, cursor.marking) <= (end.bar, end.marking)
+    }
+
+    /// Extend the selection to the previous marking.
+    pub fn extend_left(&mut self, scof: &Scof) {
+        self.head.left(scof);
+    }
+
+    /// Extend the selection to the next marking, stopping at the last bar.
+    pub fn extend_right(&mut self, scof: &Scof) {
+        if self.head.right_checked(scof)
+            && self.head.bar + 1 < bar_len(scof, &self.head)
+        {
+            self.head.bar += 1;
+            self.head.marking = 0;
+        }
+    }
+
+    /// Extend the selection to the start of the bar, or to the start of the
+    /// previous bar if already there.
+    pub fn extend_bar_left(&mut self) {
+        if self.head.marking == 0 && self.head.bar > 0 {
+            self.head.bar -= 1;
+        }
+        self.head.marking = 0;
+    }
+
+    /// Extend the selection to the end of the bar, or to the end of the next
+    /// bar if already there.
+    pub fn extend_bar_right(&mut self, scof: &Scof) {
+        if self.head.marking + 1 >= scof.marking_len(&self.head)
+            && self.head.bar + 1 < bar_len(scof, &self.head)
+        {
+            self.head.bar += 1;
+        }
+        self.head.marking = last_marking(scof, &self.head);
+    }
+
+    /// Extend the selection to the channel above.
+    pub fn extend_up(&mut self, scof: &Scof) {
+        if self.head.chan > 0 {
+            self.head.chan -= 1;
+            self.clamp_head(scof);
+        }
+    }
+
+    /// Extend the selection to the channel below.
+    pub fn extend_down(&mut self, scof: &Scof) {
+        if self.head.chan + 1 < scof.chan_len(&self.head) {
+            self.head.chan += 1;
+            self.clamp_head(scof);
+        }
+    }
+
+    /// Keep the head on a marking which exists in its channel.
+    fn clamp_head(&mut self, scof: &Scof) {
+        self.head.marking =
+            self.head.marking.min(last_marking(scof, &self.head));
+    }
+
+    /// Iterate over cursors of the selected markings, one channel at a time.
+    pub fn cursors<'a>(
+        &self,
+        scof: &'a Scof,
+    ) -> impl Iterator<Item = Cursor> + 'a {
+        let selection = self.clone();
+        self.chans()
+            .flat_map(move |chan| selection.chan_cursors(scof, chan))
+    }
+
+    /// Iterate over cursors of the selected markings in one channel.
+    pub fn chan_cursors<'a>(
+        &self,
+        scof: &'a Scof,
+        chan: u16,
+    ) -> impl Iterator<Item = Cursor> + 'a {
+        let (start, end) = (self.start(), self.end());
+        let (start, end) = ((start.bar, start.marking), (end.bar, end.marking));
+        let (movement, voice) = (self.movement(), self.anchor.voice);
+        (start.0..=end.0).flat_map(move |bar| {
+            let cursor = Cursor {
+                movement,
+                bar,
+                chan,
+                marking: 0,
+                voice,
+            };
+            let len = scof.marking_len(&cursor);
+            let first = if bar == start.0 { start.1 } else { 0 };
+            let last = if bar == end.0 {
+                len.min(end.1 + 1)
+            } else {
+                len
+            };
+            (first..last).map(move |marking| Cursor { marking, ..cursor })
+        })
+    }
+
+    /// Iterate over the selected markings, one channel at a time.
+    pub fn markings<'a>(
+        &self,
+        scof: &'a Scof,
+    ) -> impl Iterator<Item = (Cursor, &'a Marking)> + 'a {
+        self.cursors(scof).filter_map(move |cursor| {
+            let marking = scof.marking(&cursor)?;
+            Some((cursor, marking))
+        })
+    }
+}
+
+/// Get the number of bars in the movement at a cursor.
+fn bar_len(scof: &Scof, cursor: &Cursor) -> u16 {
+    scof.movement
+        .get(cursor.movement as usize)
+        .map_or(0, |m| m.bar.len() as u16)
+}
+
+/// Get the index of the last marking in the bar at a cursor.
+fn last_marking(scof: &Scof, cursor: &Cursor) -> u16 {
+    scof.marking_len(cursor).saturating_sub(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extend() {
+        let scof = Scof::default();
+        let mut sel = Selection::new(Cursor::new(0, 0, 0, 4));
+        assert!(sel.is_single());
+        sel.extend_right(&scof);
+        sel.extend_right(&scof);
+        assert_eq!(sel.head(), &Cursor::new(0, 1, 0, 0));
+        sel.extend_left(&scof);
+        sel.extend_left(&scof);
+        sel.extend_left(&scof);
+        assert_eq!(sel.start(), Cursor::new(0, 0, 0, 3));
+        assert_eq!(sel.end(), Cursor::new(0, 0, 0, 4));
+        sel.extend_down(&scof);
+        sel.extend_down(&scof);
+        assert_eq!(sel.chans(), 0..=1);
+        sel.extend_up(&scof);
+        sel.extend_up(&scof);
+        assert_eq!(sel.head().chan, 0);
+    }
+
+    #[test]
+    fn extend_bars() {
+        let scof = Scof::default();
+        let mut sel = Selection::new(Cursor::new(0, 0, 0, 1));
+        sel.extend_bar_right(&scof);
+        assert_eq!(sel.head(), &Cursor::new(0, 0, 0, 5));
+        for _ in 0..4 {
+            sel.extend_bar_right(&scof);
+        }
+        assert_eq!(sel.head(), &Cursor::new(0, 3, 0, 6));
+        assert_eq!(sel.bars(), 0..=3);
+        sel.extend_bar_left();
+        sel.extend_bar_left();
+        assert_eq!(sel.head(), &Cursor::new(0, 2, 0, 0));
+    }
+
+    #[test]
+    fn iterate() {
+        let scof = Scof::default();
+        let mut sel = Selection::new(Cursor::new(0, 0, 0, 4));
+        sel.extend_down(&scof);
+        sel.extend_right(&scof);
+        sel.extend_right(&scof);
+        assert_eq!(sel.head(), &Cursor::new(0, 1, 1, 1));
+        let cursors: Vec<Cursor> = sel.cursors(&scof).collect();
+        assert_eq!(
+            cursors,
+            vec![
+                Cursor::new(0, 0, 0, 4),
+                Cursor::new(0, 0, 0, 5),
+                Cursor::new(0, 1, 0, 0),
+                Cursor::new(0, 1, 0, 1),
+                Cursor::new(0, 0, 1, 4),
+                Cursor::new(0, 1, 1, 0),
+                Cursor::new(0, 1, 1, 1),
+            ]
+        );
+        assert_eq!(sel.markings(&scof).count(), 7);
+        assert!(sel.contains(&Cursor::new(0, 0, 1, 4)));
+        assert!(!sel.contains(&Cursor::new(0, 0, 1, 3)));
+        assert!(!sel.contains(&Cursor::new(0, 1, 0, 2)));
+    }
+}
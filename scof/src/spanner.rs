@@ -0,0 +1,262 @@
+//! Spanners (markings connecting notes)
+
+use crate::{Articulation, Cursor, Marking, Movement, Scof};
+
+/// A slur, tie, glissando or bend, from one note to another.
+///
+/// Slurs, ties and glissandi start at a note marked with `&`, `)` or `-`, and
+/// end at the next note of the same voice (which may be in a later bar).
+/// Slurs on consecutive notes are joined into one.  Bends slide into or out
+/// of one note, so they start and end at the same note.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Spanner {
+    /// Kind of spanner (one of the connective articulations)
+    pub articulation: Articulation,
+    /// Note where the spanner starts
+    pub start: Cursor,
+    /// Note where the spanner ends
+    pub end: Cursor,
+}
+
+impl Spanner {
+    /// Check if the spanner starts, ends or passes through a bar.
+    pub fn crosses(&self, bar: u16) -> bool {
+        self.start.bar <= bar && bar <= self.end.bar
+    }
+}
+
+impl Scof {
+    /// Get the spanners of a movement, in order of their start notes within
+    /// each voice.
+    pub fn spanners(&self, movement: u16) -> Vec<Spanner> {
+        let mut spanners = vec![];
+        let mvmt = match self.movement.get(movement as usize) {
+            Some(mvmt) => mvmt,
+            None => return spanners,
+        };
+        for curs in voices(mvmt, movement) {
+            let notes = voice_notes(self, &curs);
+            voice_spanners(self, &notes, &mut spanners);
+        }
+        spanners
+    }
+
+    /// Get the spanners of a movement which start, end or pass through a
+    /// bar, without looking at the notes of the whole movement.
+    pub fn bar_spanners(&self, movement: u16, bar: u16) -> Vec<Spanner> {
+        let mut spanners = vec![];
+        let mvmt = match self.movement.get(movement as usize) {
+            Some(mvmt) => mvmt,
+            None => return spanners,
+        };
+        for curs in voices(mvmt, movement) {
+            let notes = window_notes(self, &curs, bar);
+            voice_spanners(self, &notes, &mut spanners);
+        }
+        spanners.retain(|s| s.crosses(bar));
+        spanners
+    }
+}
+
+/// Get a cursor at the start of each voice of a movement.
+fn voices(mvmt: &Movement, movement: u16) -> Vec<Cursor> {
+    let mut voices = vec![];
+    let chans = mvmt.bar.iter().map(|m| m.chan.len()).max().unwrap_or(0);
+    for chan in 0..chans as u16 {
+        let len = mvmt
+            .bar
+            .iter()
+            .filter_map(|m| m.chan.get(chan as usize))
+            .map(|c| c.voice_len())
+            .max()
+            .unwrap_or(0);
+        for voice in 0..len {
+            voices.push(Cursor::new(movement, 0, chan, 0).voice(voice));
+        }
+    }
+    voices
+}
+
+/// Get the cursors of the pitched notes of a voice in one bar.
+fn bar_notes(scof: &Scof, curs: &Cursor, bar: u16) -> Vec<Cursor> {
+    let mut notes = vec![];
+    let mut curs = Cursor {
+        bar,
+        marking: 0,
+        ..curs.clone()
+    };
+    while let Some(marking) = scof.marking(&curs) {
+        if let Marking::Note(note) = marking {
+            if !note.pitch.is_empty() {
+                notes.push(curs.clone());
+            }
+        }
+        curs.marking += 1;
+    }
+    notes
+}
+
+/// Get the cursors of the pitched notes of a voice through a movement.
+fn voice_notes(scof: &Scof, curs: &Cursor) -> Vec<Cursor> {
+    let bars = scof.movement[curs.movement as usize].bar.len() as u16;
+    (0..bars)
+        .flat_map(|bar| bar_notes(scof, curs, bar))
+        .collect()
+}
+
+/// Get the cursors of the pitched notes of a voice which can be part of a
+/// spanner crossing a bar: those in the bar, and the slurred notes leading
+/// into and out of it (with the notes before and after them).
+fn window_notes(scof: &Scof, curs: &Cursor, bar: u16) -> Vec<Cursor> {
+    let slurred = |curs: &Cursor| match scof.note(curs) {
+        Some(note) => note.articulation.contains(&Articulation::Slur),
+        None => false,
+    };
+    let mut notes = vec![];
+    'back: for b in (0..bar).rev() {
+        for note in bar_notes(scof, curs, b).into_iter().rev() {
+            let more = slurred(&note);
+            notes.push(note);
+            if !more {
+                break 'back;
+            }
+        }
+    }
+    notes.reverse();
+    notes.extend(bar_notes(scof, curs, bar));
+    let bars = scof.movement[curs.movement as usize].bar.len() as u16;
+    'forward: for b in bar + 1..bars {
+        for note in bar_notes(scof, curs, b) {
+            let more = slurred(&note);
+            notes.push(note);
+            if !more {
+                break 'forward;
+            }
+        }
+    }
+    notes
+}
+
+/// Add the spanners of one voice.
+fn voice_spanners(scof: &Scof, notes: &[Cursor], spanners: &mut Vec<Spanner>) {
+    // Start of the slur continuing from the previous note
+    let mut slur: Option<Cursor> = None;
+    for (i, curs) in notes.iter().enumerate() {
+        let articulation = match scof.note(curs) {
+            Some(note) => &note.articulation,
+            None => continue,
+        };
+        let next = notes.get(i + 1);
+        let slurred = articulation.contains(&Articulation::Slur);
+        if let Some(start) = slur.take() {
+            if !slurred || next.is_none() {
+                spanners.push(Spanner {
+                    articulation: Articulation::Slur,
+                    start,
+                    end: curs.clone(),
+                });
+            } else {
+                slur = Some(start);
+            }
+        } else if slurred && next.is_some() {
+            slur = Some(curs.clone());
+        }
+        for articulation in articulation.iter().filter(|a| a.is_spanner()) {
+            let end = match articulation {
+                Articulation::Slur => continue,
+                Articulation::Tie | Articulation::Glissando => match next {
+                    Some(next) => next.clone(),
+                    None => continue,
+                },
+                _ => curs.clone(),
+            };
+            spanners.push(Spanner {
+                articulation: *articulation,
+                start: curs.clone(),
+                end,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Create a score with two bars, the first with the markings of `chan`.
+    fn score(chan: &str) -> Scof {
+        let text = format!(
+            "sig: 0\n  time: 2/4\n  tempo: 120\n\
+             bar:\n  chan: {}\n\
+             bar:\n  chan: 1/4E4 1/4F4\n",
+            chan
+        );
+        Scof {
+            movement: vec![Movement::from_muon(&text).unwrap()],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn parse() {
+        for text in &["1/4C4&", "1/4C4-", "1/4C4/-", "1/4C4\\-", "1/4C4-/"] {
+            let note = text.parse::<crate::Note>().unwrap();
+            assert_eq!(&note.to_string(), text);
+        }
+        let note = "1/4C--\\.".parse::<crate::Note>().unwrap();
+        assert_eq!(
+            note.articulation,
+            vec![Articulation::BendDownOut, Articulation::Staccato]
+        );
+    }
+
+    #[test]
+    fn spanners() {
+        let scof = score("1/4C4& 1/4D4&");
+        // The slur continues over the barline to the next note.
+        assert_eq!(
+            scof.spanners(0),
+            vec![Spanner {
+                articulation: Articulation::Slur,
+                start: Cursor::new(0, 0, 0, 0),
+                end: Cursor::new(0, 1, 0, 0),
+            }]
+        );
+        assert!(scof.spanners(0)[0].crosses(1));
+        let scof = score("1/4C4-/ 1/4R");
+        let spanners = scof.spanners(0);
+        assert_eq!(spanners.len(), 1);
+        assert_eq!(spanners[0].start, spanners[0].end);
+        let scof = score("1/4C4- 1/4R");
+        assert_eq!(scof.spanners(0)[0].end, Cursor::new(0, 1, 0, 0));
+        let scof = score("1/4C4 1/4E4)");
+        let spanners = scof.spanners(0);
+        assert_eq!(spanners[0].articulation, Articulation::Tie);
+        assert_eq!(spanners[0].end, Cursor::new(0, 1, 0, 0));
+    }
+
+    #[test]
+    fn bar_spanners() {
+        let text = "sig: 0\n  time: 2/4\n  tempo: 120\n\
+                    bar:\n  chan: 1/4C4 1/4D4&\n\
+                    bar:\n  chan: 1/4E4& 1/4F4&\n\
+                    bar:\n  chan: 1/2R\n\
+                    bar:\n  chan: 1/4G4- 1/4A4)\n\
+                    bar:\n  chan: 1/4B4 1/4C5\n";
+        let scof = Scof {
+            movement: vec![Movement::from_muon(text).unwrap()],
+            ..Default::default()
+        };
+        let spanners = scof.spanners(0);
+        for bar in 0..5 {
+            let crossing: Vec<Spanner> = spanners
+                .iter()
+                .filter(|s| s.crosses(bar))
+                .cloned()
+                .collect();
+            assert_eq!(scof.bar_spanners(0, bar), crossing);
+        }
+        // The slur passes through the empty bar.
+        assert_eq!(scof.bar_spanners(0, 2).len(), 1);
+    }
+}
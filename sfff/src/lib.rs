@@ -158,7 +158,41 @@ pub enum Glyph {
     BowBridge = 0x6A,
     BowTailpiece = 0x6B,
 
-    Len = 0x6C,
+    /* Articulations */
+    Staccato = 0x6C,
+    StaccatissimoAbove = 0x6D,
+    StaccatissimoBelow = 0x6E,
+    Tenuto = 0x6F,
+    MarcatoAbove = 0x70,
+    MarcatoBelow = 0x71,
+    Accent = 0x72,
+    /// Stopped (closed mute)
+    Stopped = 0x73,
+    /// Open (no mute)
+    Open = 0x74,
+    Harmonic = 0x75,
+    Pedal = 0x76,
+
+    /* Ornaments */
+    Trill = 0x77,
+    Turn = 0x78,
+    TurnInverted = 0x79,
+    /// Segment of a trill extension line
+    TrillWiggle = 0x7A,
+    /// Segment of an arpeggio wiggle (vertical)
+    ArpeggioWiggle = 0x7B,
+    /// Arrow at the top of an upwards arpeggio
+    ArpeggioUp = 0x7C,
+    /// Arrow at the bottom of a downwards arpeggio
+    ArpeggioDown = 0x7D,
+
+    /* Pauses */
+    Breath = 0x7E,
+    CaesuraShort = 0x7F,
+    CaesuraLong = 0x80,
+    Fermata = 0x81,
+
+    Len = 0x82,
 }
 
 impl From<Glyph> for u16 {
@@ -1098,6 +1132,18 @@ impl SfFontMetadata {
         reader
             .read_to_string(&mut glyph_paths)
             .map_err(|_| ReadError::Prevented)?;
+        match glyph_paths.split('\0').count() {
+            count if count == Glyph::Len as usize => {}
+            // Fonts made before the articulation, ornament and pause glyphs
+            // were added (from `Staccato` on) are loaded with those glyphs
+            // left empty, until they're regenerated.
+            count if count == Glyph::Staccato as usize => {
+                for _ in count..Glyph::Len as usize {
+                    glyph_paths.push('\0');
+                }
+            }
+            _ => return Err(ReadError::WrongGlyphCount),
+        }
 
         let new = Self {
             sffonts_version,
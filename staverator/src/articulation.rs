@@ -0,0 +1,160 @@
+// ScoreFall Ink - Music Composition Software
+//
+// Copyright (C) 2019-2020 Jeron Aldaron Lau <jeronlau@plopgrizzly.com>
+// Copyright (C) 2019-2020 Doug P. Lau
+//
+//     This program is free software: you can redistribute it and/or modify
+//     it under the terms of the GNU General Public License as published by
+//     the Free Software Foundation, either version 3 of the License, or
+//     (at your option) any later version.
+//
+//     This program is distributed in the hope that it will be useful,
+//     but WITHOUT ANY WARRANTY; without even the implied warranty of
+//     MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//     GNU General Public License for more details.
+//
+//     You should have received a copy of the GNU General Public License
+//     along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+/* ************************************************************************** */
+
+//! Placement of articulation symbols.
+//!
+//! Articulations go on the notehead side of a note (opposite the stem), one
+//! stave space away from the notehead.  Combinations are stacked outwards in
+//! the conventional order: staccato, staccatissimo and tenuto closest to the
+//! notehead, then accents, then sound modifiers.  Symbols within the stave
+//! are moved off of the stave lines into spaces.
+
+use crate::{BarElem, Stave};
+use scof::{Articulation, Steps};
+use sfff::Glyph;
+
+/// Get the glyph for an articulation above or below a note (`None` for ones
+/// that aren't drawn as an articulation symbol).
+fn glyph(articulation: Articulation, above: bool) -> Option<Glyph> {
+    use Articulation::*;
+    Some(match articulation {
+        Staccato => Glyph::Staccato,
+        Staccatissimo if above => Glyph::StaccatissimoAbove,
+        Staccatissimo => Glyph::StaccatissimoBelow,
+        Tenuto => Glyph::Tenuto,
+        Marcato if above => Glyph::MarcatoAbove,
+        Marcato => Glyph::MarcatoBelow,
+        Accent => Glyph::Accent,
+        Mute => Glyph::Stopped,
+        Open => Glyph::Open,
+        Harmonic => Glyph::Harmonic,
+        Pedal => Glyph::Pedal,
+        _ => return None,
+    })
+}
+
+/// Get the stacking order of an articulation (lowest closest to the note).
+fn order(articulation: Articulation) -> u8 {
+    use Articulation::*;
+    match articulation {
+        Staccato | Staccatissimo | Tenuto => 0,
+        Accent | Marcato => 1,
+        _ => 2,
+    }
+}
+
+impl BarElem {
+    /// Check if a position (in steps above middle C) is on a stave line.
+    fn is_on_line(&self, steps: Steps) -> bool {
+        let top = self.stave.steps_middle_c;
+        let bottom = top - self.stave.height_steps();
+        (bottom..=top).contains(&steps) && (top - steps).0 % 2 == 0
+    }
+
+    /// Add the articulations of a note (or chord) with noteheads at `steps`.
+    ///
+    /// - `x`: X position of the noteheads.
+    /// - `ofs`: Offset of the stave.
+    /// - `stems`: Stem direction forced by the voice (true is up).
+    pub(crate) fn add_articulations(
+        &mut self,
+        articulations: &[Articulation],
+        x: i32,
+        steps: &[Steps],
+        ofs: Steps,
+        stems: Option<bool>,
+    ) {
+        let (high, low) = match (steps.iter().max(), steps.iter().min()) {
+            (Some(high), Some(low)) => (*high, *low),
+            _ => return,
+        };
+        let y_high = self.y_from_steps(high, ofs);
+        let y_low = self.y_from_steps(low, ofs);
+        let up = self.chord_stems_up(y_high, y_low, ofs, stems);
+        let (note, y_note, dir) = if up {
+            (low, y_low, Steps(-1))
+        } else {
+            (high, y_high, Steps(1))
+        };
+        let mut articulations = articulations.to_vec();
+        articulations.sort_by_key(|a| order(*a));
+        let mut pos = note;
+        for articulation in articulations {
+            let glyph = match glyph(articulation, !up) {
+                Some(glyph) => glyph,
+                None => continue,
+            };
+            pos = pos + dir + dir;
+            if self.is_on_line(pos) {
+                pos = pos + dir;
+            }
+            let y = y_note - ((pos - note) * Stave::STEP).0;
+            self.add_use(glyph, x, y);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::engrave_bar;
+    use crate::Element;
+
+    /// Get the articulation glyphs and their Y positions in a bar.
+    fn articulations(chan: &str) -> Vec<(u16, i32)> {
+        let bar = engrave_bar(chan);
+        let range = Glyph::Staccato as u16..Glyph::Len as u16;
+        bar.elements
+            .iter()
+            .filter_map(|e| match e {
+                Element::Use(u) if range.contains(&u.id) => Some((u.id, u.y)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn placement() {
+        let bar = engrave_bar("1/1R");
+        let y =
+            |steps| bar.y_from_steps(Steps(0), Steps(0)) - steps * Stave::STEP;
+        // Stem up, so below the note; staccato closest, in a space.
+        assert_eq!(
+            articulations("1/1B3>."),
+            vec![
+                (Glyph::Staccato as u16, y(-3)),
+                (Glyph::Accent as u16, y(-5))
+            ]
+        );
+        // Stem down, so above the note, clear of the stave.
+        assert_eq!(
+            articulations("1/1D5_^"),
+            vec![
+                (Glyph::Tenuto as u16, y(10)),
+                (Glyph::MarcatoAbove as u16, y(12))
+            ]
+        );
+        // Chords are marked beyond the outermost notehead, between lines.
+        assert_eq!(
+            articulations("1/1A3C4^"),
+            vec![(Glyph::MarcatoBelow as u16, y(-5))]
+        );
+    }
+}
@@ -55,6 +55,10 @@ pub enum BeamProp {
     Flag,
 }
 
+/// A note that may be beamed: duration, offset in the bar, chord (with its
+/// stave offset) and whether it only gets one beam.
+pub(crate) type BeamNote = (u16, f32, (Vec<Pitch>, Steps), bool);
+
 /// All of the beams in a measure.
 pub(crate) struct Beams {
     // Duration not notated yet in the measure.
@@ -66,14 +70,17 @@ pub(crate) struct Beams {
     // Minimum duration within current beam.
     min_dur: u16,
     // Notes in the beamed group.
-    notes: Vec<(u16, f32, (Vec<Pitch>, Steps), bool)>,
+    notes: Vec<BeamNote>,
     // For iterator.
     queued: Option<Short>,
+    // Stem direction forced by the voice (true is up).
+    stems: Option<bool>,
 }
 
 impl Beams {
-    /// Create an empty instance of beams for the measure.
-    pub fn new() -> Self {
+    /// Create an empty instance of beams for the measure, with an optional
+    /// forced stem direction.
+    pub fn new(stems: Option<bool>) -> Self {
         Beams {
             // Start with 4 beats left (4/4)
             dur: 128,
@@ -87,6 +94,8 @@ impl Beams {
             notes: vec![],
             //
             queued: None,
+            //
+            stems,
         }
     }
 
@@ -186,7 +195,7 @@ impl Iterator for Beams {
                     self.min_dur = dur.min(self.min_dur);
                 }
                 BeamProp::Flag => {
-                    let flag = Short::Flag(dur, width, y);
+                    let flag = Short::Flag(dur, width, y, self.stems);
                     if self.min_dur != 0 {
                         let beam = Beam::new(self);
                         self.queued = Some(flag);
@@ -212,43 +221,33 @@ impl Iterator for Beams {
 /// Short note: A flag or a beam
 pub(crate) enum Short {
     /// Flag
-    Flag(u16, f32, (Vec<Pitch>, Steps)),
+    Flag(u16, f32, (Vec<Pitch>, Steps), Option<bool>),
     /// Beam
     Beam(Beam),
 }
 
 /// A beamed group.
 pub(crate) struct Beam {
-    // Notes in the beamed group.
-    pub(crate) notes: Vec<(u16, f32, (Pitch, Steps), bool)>,
-    // Stem direction (false is down).
-    pub(crate) stems_up: bool,
+    // Notes (chords) in the beamed group.
+    pub(crate) notes: Vec<BeamNote>,
+    // Stem direction forced by the voice (true is up).
+    pub(crate) stems: Option<bool>,
 }
 
 impl Beam {
     /// Create a new beam object.
     pub fn new(beams: &mut Beams) -> Self {
-        // Choose stem direction of beamed group.
-        let mut sum = 0i16;
-        for note_i in 0..beams.notes.len() {
-            let vd = beams.notes[note_i].2 .0[0].visual_distance();
-            match vd.0 {
-                _a if _a > 0 => sum += 1,
-                _a if _a < 0 => sum -= 1,
-                _ => {}
-            }
-        }
-        let stems_up = sum < 0;
         let uses_three_beams = beams.min_dur < 8; // Less than 16th note
 
-        // Select closest notes to the beam.
         let mut notes = vec![];
-        for note in beams.notes.drain(..) {
-            let one_beam = note.3 && uses_three_beams;
-            // FIXME: Choose closest note to beam.
-            notes.push((note.0, note.1, (note.2 .0[0], note.2 .1), one_beam));
+        for mut note in beams.notes.drain(..) {
+            note.3 = note.3 && uses_three_beams;
+            notes.push(note);
         }
 
-        Beam { notes, stems_up }
+        Beam {
+            notes,
+            stems: beams.stems,
+        }
     }
 }
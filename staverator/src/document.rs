@@ -0,0 +1,329 @@
+// ScoreFall Ink - Music Composition Software
+//
+// Copyright (C) 2019-2020 Jeron Aldaron Lau <jeronlau@plopgrizzly.com>
+// Copyright (C) 2019-2020 Doug P. Lau
+//
+//     This program is free software: you can redistribute it and/or modify
+//     it under the terms of the GNU General Public License as published by
+//     the Free Software Foundation, either version 3 of the License, or
+//     (at your option) any later version.
+//
+//     This program is distributed in the hope that it will be useful,
+//     but WITHOUT ANY WARRANTY; without even the implied warranty of
+//     MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//     GNU General Public License for more details.
+//
+//     You should have received a copy of the GNU General Public License
+//     along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+/* ************************************************************************** */
+
+//! Render an entire movement of a score into a standalone SVG document.
+
+use std::fmt::Write;
+use std::ops::Range;
+
+use crate::rehearsal::REHEARSAL_ROOM;
+use crate::signature::TEMPO_ROOM;
+use crate::{BarElem, Element, Group, Stave, STAVE_SPACE};
+use scof::{Cursor, Marking, Pitch, Scof, Steps};
+use sfff::SfFontMetadata;
+
+/// Steps of the top stave line above middle C.
+const TOP_LINE: Steps = Steps(4);
+
+/// Options for rendering a score to an SVG document.
+#[derive(Clone, Debug)]
+pub struct RenderOptions {
+    /// Index of the movement to render.
+    pub movement: u16,
+    /// Margin around the music (in font units).
+    pub margin: i32,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        RenderOptions {
+            movement: 0,
+            margin: STAVE_SPACE,
+        }
+    }
+}
+
+/// Get the steps of the highest and lowest notes of a movement (from middle
+/// C), to leave room for in its bars.
+pub(crate) fn pitch_range(scof: &Scof, movement: u16) -> (Steps, Steps) {
+    let middle_c = "C4".parse::<Pitch>().unwrap().visual_distance();
+    let mut range = (middle_c, middle_c);
+    let bars = scof.movement.get(movement as usize).map(|m| &m.bar[..]);
+    for chan in bars.unwrap_or_default().iter().flat_map(|m| &m.chan) {
+        for voice in 0..chan.voice_len() {
+            for marking in chan.voice(voice).into_iter().flatten() {
+                let note = match marking {
+                    Marking::Note(note)
+                    | Marking::GraceInto(note)
+                    | Marking::GraceOutOf(note) => note,
+                    _ => continue,
+                };
+                for pitch in &note.pitch {
+                    let steps = pitch.visual_distance();
+                    range = (range.0.max(steps), range.1.min(steps));
+                }
+            }
+        }
+    }
+    range
+}
+
+/// Get the steps (from middle C) to leave room for above and below the
+/// staves of a movement: its [`pitch_range`], and the tempo and rehearsal
+/// marks above the first stave.
+pub(crate) fn stave_range(scof: &Scof, movement: u16) -> (Steps, Steps) {
+    let (high, low) = pitch_range(scof, movement);
+    let mvmt = scof.movement.get(movement as usize);
+    let room = if mvmt.and_then(|m| m.rehearsal_bars().next()).is_some() {
+        REHEARSAL_ROOM
+    } else {
+        TEMPO_ROOM
+    };
+    (high.max(TOP_LINE + room), low)
+}
+
+/// Create an empty bar element for the score's staves, with room for notes
+/// from the `high` to the `low` steps of a [`stave_range`].
+pub(crate) fn bar_elem((high, low): (Steps, Steps)) -> BarElem {
+    // Alto clef has 0 steps offset
+    BarElem::new(Stave::new(5, TOP_LINE, Steps(0)), high, low)
+}
+
+/// Engrave bars of a movement (all channels), as a multi-bar rest if there
+/// is more than one.
+///
+/// - `range`: The [`stave_range`] of the movement.
+pub(crate) fn engrave_bars(
+    scof: &Scof,
+    meta: &SfFontMetadata,
+    movement: u16,
+    bars: &Range<u16>,
+    range: (Steps, Steps),
+) -> BarElem {
+    let mut elem = bar_elem(range);
+    add_bars(&mut elem, scof, meta, movement, bars);
+    elem
+}
+
+/// Add bars of a movement to a bar element, as a multi-bar rest if there is
+/// more than one.
+pub(crate) fn add_bars(
+    elem: &mut BarElem,
+    scof: &Scof,
+    meta: &SfFontMetadata,
+    movement: u16,
+    bars: &Range<u16>,
+) {
+    let curs = Cursor::new(movement, bars.start, 0, 0);
+    if bars.len() > 1 {
+        elem.add_multi_rest(meta, scof, &curs, bars.len() as u16);
+    } else {
+        elem.add_bar(meta, scof, &curs);
+    }
+}
+
+/// Get the bars of a movement to engrave together: a run of empty bars for
+/// each multi-bar rest, and single bars otherwise.
+pub(crate) fn bar_units(scof: &Scof, movement: u16) -> Vec<Range<u16>> {
+    let bars = scof
+        .movement
+        .get(movement as usize)
+        .map(|m| m.bar.len() as u16)
+        .unwrap_or(0);
+    let mut runs = scof.multi_rests(movement).into_iter().peekable();
+    let mut units = vec![];
+    let mut bar = 0;
+    while bar < bars {
+        let unit = runs.next_if(|run| run.start == bar).unwrap_or(bar..bar + 1);
+        bar = unit.end;
+        units.push(unit);
+    }
+    units
+}
+
+/// Wrap SVG content with the glyph definitions into an SVG document.
+pub(crate) fn svg_document(
+    width: i32,
+    height: i32,
+    glyph_defs: &str,
+    content: &dyn std::fmt::Display,
+) -> String {
+    let mut svg = String::new();
+    // Write to String should always succeed.
+    let _ = write!(
+        svg,
+        "<svg xmlns='http://www.w3.org/2000/svg' \
+         xmlns:xlink='http://www.w3.org/1999/xlink' \
+         viewBox='0 0 {} {}' width='{}' height='{}'>{}{}</svg>",
+        width, height, width, height, glyph_defs, content
+    );
+    svg
+}
+
+/// Render a movement of the score as an SVG document.
+///
+/// - `glyph_defs`: The `defs` section for the font (see [`modern`]).
+///
+/// [`modern`]: crate::modern
+pub fn render_svg(
+    scof: &Scof,
+    meta: &SfFontMetadata,
+    glyph_defs: &str,
+    options: &RenderOptions,
+) -> String {
+    let chans = scof.chan_len(&Cursor::new(options.movement, 0, 0, 0));
+
+    // Leave room for braces and brackets of stave groups.
+    let groups = if scof.style.group.is_empty() {
+        0
+    } else {
+        2 * STAVE_SPACE
+    };
+    let mut page = Group::new(options.margin + groups, options.margin, None);
+    let range = stave_range(scof, options.movement);
+    let mut offset_x = 0;
+    let mut height = 0;
+    for (i, bars) in bar_units(scof, options.movement).iter().enumerate() {
+        let mut elem = bar_elem(range);
+        if i == 0 {
+            elem.start_system();
+        }
+        add_bars(&mut elem, scof, meta, options.movement, bars);
+        if i == 0 {
+            elem.add_stave_groups(meta, scof, chans);
+        }
+        let mut group = Group::new(offset_x, 0, None);
+        offset_x += elem.width;
+        height = height.max(elem.stave_group_height(chans));
+        for e in elem.elements {
+            group.push(e);
+        }
+        page.push(Element::Group(group));
+    }
+
+    let width = offset_x + groups + options.margin * 2;
+    let height = height + options.margin * 2;
+    svg_document(width, height, glyph_defs, &page)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_score() {
+        let scof = Scof::default();
+        let meta = crate::tests::meta();
+        let svg =
+            render_svg(&scof, &meta, "<defs></defs>", &Default::default());
+
+        assert!(svg.starts_with("<svg xmlns='http://www.w3.org/2000/svg'"));
+        assert!(svg.contains("<defs></defs>"));
+        assert!(svg.ends_with("</svg>"));
+        // One group for the page, and one for each bar.
+        assert_eq!(svg.matches("<g").count(), 5);
+        // Each bar has a stave path for both channels.
+        assert_eq!(svg.matches("<path d='M0 ").count(), 8);
+    }
+
+    #[test]
+    fn stave_groups() {
+        let mut scof = Scof::default();
+        let meta = crate::tests::meta();
+        let plain = render_svg(&scof, &meta, "", &Default::default());
+        scof.style.group.push(scof::StaveGroup {
+            kind: "bracket".to_string(),
+            first: 0,
+            last: 1,
+        });
+        let svg = render_svg(&scof, &meta, "", &Default::default());
+        // Bracket at the start, and barlines joining the staves in each bar.
+        assert_eq!(svg.matches("<path d='M-").count(), 1);
+        assert_eq!(svg.matches("width='16' height='600'").count(), 4);
+        assert_eq!(plain.matches("width='16' height='600'").count(), 0);
+    }
+
+    #[test]
+    fn missing_movement() {
+        let scof = Scof::default();
+        let meta = crate::tests::meta();
+        let options = RenderOptions {
+            movement: 3,
+            ..Default::default()
+        };
+        let svg = render_svg(&scof, &meta, "", &options);
+        assert_eq!(svg.matches("<g").count(), 1);
+    }
+
+    #[test]
+    fn ledger_lines() {
+        let meta = crate::tests::meta();
+        let render = |notes: &str| {
+            let muon = format!(
+                "sig: 0\n  time: 4/4\n  tempo: 120\nbar:\n  chan: {}\n",
+                notes
+            );
+            let scof = Scof {
+                movement: vec![scof::Movement::from_muon(&muon).unwrap()],
+                ..Default::default()
+            };
+            let svg = render_svg(&scof, &meta, "", &Default::default());
+            (pitch_range(&scof, 0), svg)
+        };
+        let height = |svg: &str| -> i32 {
+            let view = svg.split("viewBox='0 0 ").nth(1).unwrap();
+            let height = view.split(&[' ', '\''][..]).nth(1).unwrap();
+            height.parse().unwrap()
+        };
+        let (range, ledger) = render("1/2C6 1/2C2");
+        assert_eq!(range, (Steps(14), Steps(-14)));
+        let (_, plain) = render("1/2C4 1/2C4");
+        // The ledger lines below the stave make the document taller (those
+        // above are within the room left for the tempo marking).
+        assert!(height(&ledger) > height(&plain));
+    }
+
+    /// Get the top of the elements of a bar (text reaches up by its size,
+    /// and glyphs by half a stave space).
+    fn top(elem: &BarElem) -> i32 {
+        elem.elements
+            .iter()
+            .filter_map(|e| match e {
+                Element::Rect(r) => Some(r.y),
+                Element::Use(u) => Some(u.y - STAVE_SPACE / 2),
+                Element::Text(t) => Some(t.y - t.size),
+                _ => None,
+            })
+            .min()
+            .unwrap()
+    }
+
+    #[test]
+    fn tempo_room() {
+        let scof = Scof::default();
+        let meta = crate::tests::meta();
+        let range = stave_range(&scof, 0);
+        let elem = engrave_bars(&scof, &meta, 0, &(0..1), range);
+        // The tempo marking at the start is below the top of the bar.
+        assert!(top(&elem) >= 0);
+    }
+
+    #[test]
+    fn rehearsal_room() {
+        let mut scof = Scof::default();
+        scof.movement[0].bar[0].rehearsal = Some("A".to_string());
+        let meta = crate::tests::meta();
+        let range = stave_range(&scof, 0);
+        let elem = engrave_bars(&scof, &meta, 0, &(0..1), range);
+        // The rehearsal mark's box is below the top of the bar.
+        assert!(elem.elements.iter().any(|e| matches!(e, Element::Rect(_))));
+        assert!(top(&elem) >= 0);
+    }
+}
@@ -0,0 +1,220 @@
+// ScoreFall Ink - Music Composition Software
+//
+// Copyright (C) 2019-2020 Jeron Aldaron Lau <jeronlau@plopgrizzly.com>
+// Copyright (C) 2019-2020 Doug P. Lau
+//
+//     This program is free software: you can redistribute it and/or modify
+//     it under the terms of the GNU General Public License as published by
+//     the Free Software Foundation, either version 3 of the License, or
+//     (at your option) any later version.
+//
+//     This program is distributed in the hope that it will be useful,
+//     but WITHOUT ANY WARRANTY; without even the implied warranty of
+//     MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//     GNU General Public License for more details.
+//
+//     You should have received a copy of the GNU General Public License
+//     along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+/* ************************************************************************** */
+
+//! Dynamics and hairpins, below the stave.
+//!
+//! Dynamics are centered under the note after them.  A hairpin runs from
+//! its `cresc` or `dim` marking to the next dynamic, and like spanners, each
+//! bar draws the part of a hairpin within it.
+
+use crate::{BarElem, Element, Path, Stave};
+use scof::{Cursor, Marking, Scof, Steps};
+use sfff::{Glyph, SfFontMetadata};
+
+/// Width of a dynamic letter glyph.
+const LETTER_WIDTH: i32 = Stave::SPACE * 5 / 4;
+/// Distance from the bottom line of the stave to the baseline of dynamics.
+const DYNAMIC_DROP: i32 = 3 * Stave::SPACE;
+/// Height of a hairpin at its open end.
+const HAIRPIN_HEIGHT: i32 = Stave::SPACE;
+/// Space between a hairpin and a dynamic.
+const HAIRPIN_GAP: i32 = Stave::SPACE / 2;
+
+/// Get the glyphs to compose the text of a dynamic.
+fn glyphs(text: &str) -> Vec<Glyph> {
+    match text {
+        "mp" => return vec![Glyph::MP],
+        "mf" => return vec![Glyph::MF],
+        _ => {}
+    }
+    text.chars()
+        .filter_map(|c| match c {
+            'p' => Some(Glyph::P),
+            'f' => Some(Glyph::F),
+            's' => Some(Glyph::S),
+            'z' => Some(Glyph::Z),
+            'n' => Some(Glyph::N),
+            _ => None,
+        })
+        .collect()
+}
+
+impl BarElem {
+    /// Add the dynamics before the next notes, and remember where hairpins
+    /// start and end.
+    ///
+    /// - `markings`: Markings between notes, with their stave offsets.
+    /// - `x`: X position of the next notes.
+    pub(crate) fn add_dynamics(
+        &mut self,
+        markings: &[(Cursor, Marking, Steps)],
+        x: i32,
+    ) {
+        let center = x + Stave::SPACE / 2;
+        // Right edge of the dynamic on each stave.
+        let mut rights: Vec<(Steps, i32)> = vec![];
+        for (cursor, marking, ofs) in markings {
+            let dynamic = match marking {
+                Marking::Dynamic(dynamic) => dynamic,
+                _ => continue,
+            };
+            let glyphs = glyphs(dynamic.text());
+            let width: i32 = glyphs.iter().map(glyph_width).sum();
+            let y = self.bottom_line(*ofs) + DYNAMIC_DROP;
+            let left = center - width / 2;
+            let mut gx = left;
+            for glyph in glyphs {
+                let w = glyph_width(&glyph);
+                self.add_use(glyph, gx, y);
+                gx += w;
+            }
+            let right = left + width;
+            rights.push((*ofs, right));
+            self.dynamics.push((cursor.clone(), left, right));
+        }
+        for (cursor, marking, ofs) in markings {
+            if let Marking::Cresc | Marking::Dim = marking {
+                let start = rights
+                    .iter()
+                    .filter(|(o, _)| o == ofs)
+                    .map(|(_, right)| *right)
+                    .max()
+                    .unwrap_or(x);
+                self.dynamics.push((cursor.clone(), start, start));
+            }
+        }
+    }
+
+    /// Add the hairpins which start, end or pass through the bar.
+    ///
+    /// - `curs`: Cursor of the measure.
+    /// - `width`: Width of the bar (up to the barline).
+    pub(crate) fn add_hairpins(
+        &mut self,
+        meta: &SfFontMetadata,
+        scof: &Scof,
+        curs: &Cursor,
+        width: i32,
+    ) {
+        if self.multi_rest > 1 {
+            return;
+        }
+        let ymargin = self.stave.height_steps() + Steps(12);
+        let (left, right) = (self.width + Stave::SPACE, self.width + width);
+        let bar = curs.bar_index();
+        for hairpin in scof.hairpins(curs.movement_index()) {
+            if !hairpin.crosses(bar) {
+                continue;
+            }
+            let position = |cursor: &Cursor| {
+                self.dynamics.iter().find(|d| d.0 == *cursor).cloned()
+            };
+            let start = position(&hairpin.start).map(|d| d.2 + HAIRPIN_GAP);
+            let end = position(&hairpin.end).map(|d| d.1 - HAIRPIN_GAP);
+            if start.is_none()
+                && end.is_none()
+                && hairpin.start.bar_index() == bar
+            {
+                continue;
+            }
+            let (x0, x1) = (start.unwrap_or(left), end.unwrap_or(right));
+            if x1 <= x0 {
+                continue;
+            }
+            // Heights at each end: closed at the start of a crescendo (or
+            // end of a decrescendo), and half open where it continues.
+            let full = HAIRPIN_HEIGHT;
+            let (h0, h1) = if hairpin.cresc {
+                (
+                    start.map_or(full / 2, |_| 0),
+                    end.map_or(full / 2, |_| full),
+                )
+            } else {
+                (
+                    start.map_or(full / 2, |_| full),
+                    end.map_or(full / 2, |_| 0),
+                )
+            };
+            let ofs = ymargin * i32::from(hairpin.start.chan_index());
+            let y = self.bottom_line(ofs) + DYNAMIC_DROP - Stave::SPACE / 2;
+            let t = meta.hairpin_thickness;
+            let mut d = String::new();
+            for dir in &[-1, 1] {
+                let (y0, y1) = (y + dir * h0 / 2, y + dir * h1 / 2);
+                d.push_str(&format!(
+                    "M{} {}L{} {}L{} {}L{} {}z",
+                    x0,
+                    y0 - t / 2,
+                    x1,
+                    y1 - t / 2,
+                    x1,
+                    y1 + t / 2,
+                    x0,
+                    y0 + t / 2,
+                ));
+            }
+            self.elements.push(Element::Path(Path::new(None, d)));
+        }
+    }
+}
+
+/// Get the width of a dynamic glyph.
+fn glyph_width(glyph: &Glyph) -> i32 {
+    match glyph {
+        Glyph::MP | Glyph::MF => 2 * LETTER_WIDTH,
+        _ => LETTER_WIDTH,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{engrave_bar, glyph_ids};
+
+    /// Engrave a bar, getting its glyph IDs and the number of paths.
+    fn engrave(chan: &str) -> (Vec<u16>, usize) {
+        let bar = engrave_bar(chan);
+        let paths = bar
+            .elements
+            .iter()
+            .filter(|e| matches!(e, Element::Path(_)))
+            .count();
+        (glyph_ids(&bar), paths)
+    }
+
+    #[test]
+    fn compose() {
+        assert_eq!(glyphs("mf").len(), 1);
+        let ids: Vec<u16> = glyphs("sfz").into_iter().map(u16::from).collect();
+        assert_eq!(ids, [Glyph::S as u16, Glyph::F as u16, Glyph::Z as u16]);
+    }
+
+    #[test]
+    fn hairpins() {
+        let (plain, paths) = engrave("p 1/2C4 f 1/2D4");
+        assert!(plain.contains(&(Glyph::P as u16)));
+        assert!(plain.contains(&(Glyph::F as u16)));
+        let (_, hairpin) = engrave("p cresc 1/2C4 f 1/2D4");
+        assert_eq!(hairpin, paths + 1);
+        // Without a dynamic to end at, it runs to the end of the bar.
+        let (_, hairpin) = engrave("1/2C4 dim 1/2D4");
+        assert_eq!(hairpin, paths + 1);
+    }
+}
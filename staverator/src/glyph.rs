@@ -38,6 +38,23 @@ pub(super) fn rest_duration(duration: u16) -> Glyph {
     }
 }
 
+/// Get the time signature glyph for a digit
+pub(super) fn time_digit(digit: u32) -> Glyph {
+    use Glyph::*;
+    match digit {
+        0 => TimeSig0,
+        1 => TimeSig1,
+        2 => TimeSig2,
+        3 => TimeSig3,
+        4 => TimeSig4,
+        5 => TimeSig5,
+        6 => TimeSig6,
+        7 => TimeSig7,
+        8 => TimeSig8,
+        _ => TimeSig9,
+    }
+}
+
 /// Get the flag glyph for a note with a specific duration
 pub(super) fn flag_duration(duration: u16, up: bool) -> Option<Glyph> {
     use Glyph::*;
@@ -0,0 +1,188 @@
+// ScoreFall Ink - Music Composition Software
+//
+// Copyright (C) 2019-2020 Jeron Aldaron Lau <jeronlau@plopgrizzly.com>
+// Copyright (C) 2019-2020 Doug P. Lau
+//
+//     This program is free software: you can redistribute it and/or modify
+//     it under the terms of the GNU General Public License as published by
+//     the Free Software Foundation, either version 3 of the License, or
+//     (at your option) any later version.
+//
+//     This program is distributed in the hope that it will be useful,
+//     but WITHOUT ANY WARRANTY; without even the implied warranty of
+//     MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//     GNU General Public License for more details.
+//
+//     You should have received a copy of the GNU General Public License
+//     along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+/* ************************************************************************** */
+
+//! Grace notes, engraved cue-sized between the notes of the bar.
+//!
+//! Grace notes don't take any of the bar's duration, so they get extra room
+//! before the next note.  A single eighth grace note into a note is an
+//! acciaccatura, which has a slash through its stem.
+
+use crate::glyph::flag_duration;
+use crate::notehead::{self, Notehead};
+use crate::{BarElem, Element, Path, Stave, Use};
+use scof::{Cursor, Fraction, Marking, Note, Steps};
+use sfff::{Glyph, SfFontMetadata};
+
+/// Scale of grace notes (compared to full-size notes).
+const GRACE_SCALE: f32 = 0.6;
+/// Room to leave for each grace note.
+const GRACE_WIDTH: i32 = Stave::SPACE * 3 / 2;
+
+/// Scale a length to the size of grace notes.
+pub(crate) fn cue(length: i32) -> i32 {
+    (length as f32 * GRACE_SCALE) as i32
+}
+
+impl BarElem {
+    /// Add the grace notes before the next notes, returning the room needed
+    /// for them.
+    ///
+    /// - `markings`: Markings between notes, with their stave offsets.
+    /// - `x`: X position of the first grace note.
+    pub(crate) fn add_graces(
+        &mut self,
+        meta: &SfFontMetadata,
+        markings: &[(Cursor, Marking, Steps)],
+        x: i32,
+    ) -> i32 {
+        // Number of grace notes engraved on each stave.
+        let mut counts: Vec<(Steps, i32)> = vec![];
+        let mut width = 0;
+        for (_, marking, ofs) in markings {
+            let (note, into) = match marking {
+                Marking::GraceInto(note) => (note, true),
+                Marking::GraceOutOf(note) => (note, false),
+                _ => continue,
+            };
+            let slash = into
+                && note.duration == Fraction::new(1, 8)
+                && markings
+                    .iter()
+                    .filter(|(_, m, o)| {
+                        o == ofs && matches!(m, Marking::GraceInto(_))
+                    })
+                    .count()
+                    == 1;
+            let i = match counts.iter().position(|(o, _)| o == ofs) {
+                Some(i) => i,
+                None => {
+                    counts.push((*ofs, 0));
+                    counts.len() - 1
+                }
+            };
+            let gx = x + counts[i].1 * GRACE_WIDTH;
+            counts[i].1 += 1;
+            width = width.max(counts[i].1 * GRACE_WIDTH);
+            self.add_grace(meta, note, gx, *ofs, slash);
+        }
+        width
+    }
+
+    /// Add a cue-sized grace note (or chord), with its stem up.
+    fn add_grace(
+        &mut self,
+        meta: &SfFontMetadata,
+        note: &Note,
+        x: i32,
+        ofs: Steps,
+        slash: bool,
+    ) {
+        if note.pitch.is_empty() {
+            return;
+        }
+        let dur = (u32::from(note.duration.num) * 128
+            / u32::from(note.duration.den).max(1)) as u16;
+        let (mut high, mut low) = (i32::MAX, i32::MIN);
+        for pitch in &note.pitch {
+            let y = self.y_from_steps(pitch.visual_distance(), ofs);
+            self.add_cue(notehead::duration(dur), x, y);
+            high = high.min(y);
+            low = low.max(y);
+        }
+        // Whole notes and longer don't have stems.
+        if dur >= 128 {
+            return;
+        }
+        let [_, right] = notehead::stems(Notehead::Normal, meta, dur);
+        let stem_x = x + cue(right[0]) - meta.stem_thickness;
+        let top = high - cue(Self::STEM_LENGTH);
+        self.add_stem2(meta, stem_x, top, low - top);
+        if let Some(flag) = flag_duration(dur, true) {
+            self.add_cue(flag, stem_x, top);
+        }
+        if slash {
+            let (x0, y0) =
+                (stem_x - cue(Stave::SPACE), top + cue(Stave::SPACE * 2));
+            let (x1, y1) = (stem_x + cue(Stave::SPACE * 3 / 2), top);
+            let t = meta.stem_thickness;
+            let d = format!(
+                "M{} {}L{} {}L{} {}L{} {}z",
+                x0,
+                y0 - t / 2,
+                x1,
+                y1 - t / 2,
+                x1,
+                y1 + t / 2,
+                x0,
+                y0 + t / 2,
+            );
+            self.elements.push(Element::Path(Path::new(None, d)));
+        }
+    }
+
+    /// Add a cue-sized glyph.
+    pub(crate) fn add_cue(&mut self, glyph: Glyph, x: i32, y: i32) {
+        let id = glyph.into();
+        self.elements
+            .push(Element::Use(Use::scaled(x, y, id, GRACE_SCALE)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::engrave_bar;
+
+    /// Engrave a bar, getting its width, the number of cue-sized glyphs and
+    /// the number of paths.
+    fn engrave(chan: &str) -> (i32, usize, usize) {
+        let bar = engrave_bar(chan);
+        // Leave out the note of the tempo marking, above the stave.
+        let top = bar.top_line(Steps(0)) - 4 * Stave::SPACE;
+        let cues = bar
+            .elements
+            .iter()
+            .filter(
+                |e| matches!(e, Element::Use(u) if u.scale < 1.0 && u.y > top),
+            )
+            .count();
+        let paths = bar
+            .elements
+            .iter()
+            .filter(|e| matches!(e, Element::Path(_)))
+            .count();
+        (bar.width, cues, paths)
+    }
+
+    #[test]
+    fn graces() {
+        let (width, cues, paths) = engrave("1/2C5 1/2D5");
+        assert_eq!(cues, 0);
+        // An acciaccatura has a notehead, flag and slash.
+        let (grace, cues, slashed) = engrave("{1/8B4 1/2C5 1/2D5");
+        assert_eq!(cues, 2);
+        assert_eq!(slashed, paths + 1);
+        assert_eq!(grace, width + GRACE_WIDTH);
+        let (graces, cues, unslashed) = engrave("1/2C5 }1/16B4 }1/16A4 1/2D5");
+        assert_eq!(cues, 4);
+        assert_eq!(unslashed, paths);
+        assert_eq!(graces, width + 2 * GRACE_WIDTH);
+    }
+}
@@ -0,0 +1,144 @@
+// ScoreFall Ink - Music Composition Software
+//
+// Copyright (C) 2019-2020 Jeron Aldaron Lau <jeronlau@plopgrizzly.com>
+// Copyright (C) 2019-2020 Doug P. Lau
+//
+//     This program is free software: you can redistribute it and/or modify
+//     it under the terms of the GNU General Public License as published by
+//     the Free Software Foundation, either version 3 of the License, or
+//     (at your option) any later version.
+//
+//     This program is distributed in the hope that it will be useful,
+//     but WITHOUT ANY WARRANTY; without even the implied warranty of
+//     MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//     GNU General Public License for more details.
+//
+//     You should have received a copy of the GNU General Public License
+//     along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+/* ************************************************************************** */
+
+//! Path data for braces, brackets and sub-brackets joining groups of staves.
+//!
+//! Each is drawn to the left of `x`, from the top line of the first stave to
+//! the bottom line of the last stave.
+
+use crate::Stave;
+
+/// Control points for the left edge of a brace, as fractions of its width
+/// (leftwards from `x`) and height (downwards from the top).
+const BRACE: [[(f32, f32); 3]; 4] = [
+    [(0.6, 0.0), (0.5, 0.15), (0.5, 0.3)],
+    [(0.5, 0.45), (0.7, 0.5), (1.0, 0.5)],
+    [(0.7, 0.5), (0.5, 0.55), (0.5, 0.7)],
+    [(0.5, 0.85), (0.6, 1.0), (0.0, 1.0)],
+];
+
+/// Create path data for a brace (grand staff).
+pub(crate) fn brace(x: i32, top: i32, bottom: i32, thickness: i32) -> String {
+    let (width, height) = (Stave::SPACE as f32, (bottom - top) as f32);
+    // The right edge is the left edge moved right, except at the pointed
+    // ends and middle tip.
+    let point = |(fx, fy): (f32, f32), right: bool| {
+        let ofs = if right && fx > 0.0 && fx < 1.0 {
+            thickness
+        } else {
+            0
+        };
+        let px = x - (fx * width) as i32 + ofs;
+        let py = top + (fy * height) as i32;
+        format!("{} {}", px, py)
+    };
+    let mut d = format!("M{}", point((0.0, 0.0), false));
+    for seg in BRACE.iter() {
+        d.push_str(&format!(
+            "C{} {} {}",
+            point(seg[0], false),
+            point(seg[1], false),
+            point(seg[2], false)
+        ));
+    }
+    for (i, seg) in BRACE.iter().enumerate().rev() {
+        let start = if i > 0 { BRACE[i - 1][2] } else { (0.0, 0.0) };
+        d.push_str(&format!(
+            "C{} {} {}",
+            point(seg[1], true),
+            point(seg[0], true),
+            point(start, true)
+        ));
+    }
+    d.push('z');
+    d
+}
+
+/// Create path data for a bracket (section).
+pub(crate) fn bracket(x: i32, top: i32, bottom: i32, thickness: i32) -> String {
+    let space = Stave::SPACE;
+    let (left, right) = (x - thickness, x);
+    let (top, bottom) = (top - space / 2, bottom + space / 2);
+    let (mid, tip) = (left + space / 2, left + space);
+    format!(
+        "M{} {}V{}Q{} {} {} {}Q{} {} {} {}V{}Q{} {} {} {}Q{} {} {} {}z",
+        left,
+        bottom,
+        top,
+        mid,
+        top,
+        tip,
+        top - space / 2,
+        mid,
+        top + thickness,
+        right,
+        top + thickness,
+        bottom - thickness,
+        mid,
+        bottom - thickness,
+        tip,
+        bottom + space / 2,
+        mid,
+        bottom,
+        left,
+        bottom,
+    )
+}
+
+/// Create path data for a sub-bracket (subsection).
+pub(crate) fn subbracket(
+    x: i32,
+    top: i32,
+    bottom: i32,
+    thickness: i32,
+) -> String {
+    let (left, right) = (x - thickness, x);
+    let tick = right + Stave::SPACE / 2;
+    format!(
+        "M{} {}H{}V{}H{}V{}H{}V{}H{}z",
+        left,
+        top,
+        tick,
+        top + thickness,
+        right,
+        bottom - thickness,
+        tick,
+        bottom,
+        left,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths() {
+        let d = brace(-50, 0, 1000, 40);
+        assert!(d.starts_with("M-50 0C-110 0 -100 150 -100 300"));
+        assert_eq!(d.matches('C').count(), 8);
+        assert!(d.ends_with("C-60 150 -70 0 -50 0z"));
+        assert_eq!(
+            subbracket(-100, 0, 1000, 10),
+            "M-110 0H-50V10H-100V990H-50V1000H-110z"
+        );
+        assert!(bracket(-50, 0, 1000, 50).starts_with("M-100 1050V-50Q"));
+    }
+}
@@ -0,0 +1,455 @@
+// ScoreFall Ink - Music Composition Software
+//
+// Copyright (C) 2019-2020 Jeron Aldaron Lau <jeronlau@plopgrizzly.com>
+// Copyright (C) 2019-2020 Doug P. Lau
+//
+//     This program is free software: you can redistribute it and/or modify
+//     it under the terms of the GNU General Public License as published by
+//     the Free Software Foundation, either version 3 of the License, or
+//     (at your option) any later version.
+//
+//     This program is distributed in the hope that it will be useful,
+//     but WITHOUT ANY WARRANTY; without even the implied warranty of
+//     MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//     GNU General Public License for more details.
+//
+//     You should have received a copy of the GNU General Public License
+//     along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+/* ************************************************************************** */
+
+//! Break a movement into systems (lines) and pages.  The rules are described
+//! in `NOTES.md`.
+
+use std::ops::Range;
+
+use crate::document::{
+    add_bars, bar_elem, bar_units, engrave_bars, stave_range, svg_document,
+};
+use crate::raster::{has_text, render_png, RasterOptions};
+use crate::{Element, Group, STAVE_SPACE};
+use scof::{Cursor, Scof};
+use sfff::SfFontMetadata;
+
+/// Maximum number of notes on one line.
+const MAX_NOTES: u16 = 32;
+/// Maximum number of measures on one line.
+const MAX_MEASURES: usize = 9;
+/// Maximum difference in number of measures between sequential lines.
+const MAX_MEASURE_DIFF: usize = 2;
+/// Maximum ratio of the widest measure to the narrowest on a line.
+const MAX_WIDTH_RATIO: i32 = 2;
+/// Maximum barline X difference between lines (fraction of line length).
+const MAX_BARLINE_DIFF: i32 = 5;
+
+/// Page size and margins for laying out a score (in font units).
+#[derive(Clone, Debug)]
+pub struct LayoutOptions {
+    /// Index of the movement to lay out.
+    pub movement: u16,
+    /// Width of a page.
+    pub page_width: i32,
+    /// Height of a page.
+    pub page_height: i32,
+    /// Margin on each side of a page.
+    pub margin: i32,
+    /// Space between systems.
+    pub system_spacing: i32,
+    /// Space reserved at the top of the first page (for the title).
+    pub header: i32,
+}
+
+impl Default for LayoutOptions {
+    fn default() -> Self {
+        // A4 paper with a 1.75mm stave space.
+        LayoutOptions {
+            movement: 0,
+            page_width: 120 * STAVE_SPACE,
+            page_height: 170 * STAVE_SPACE,
+            margin: 8 * STAVE_SPACE,
+            system_spacing: 2 * STAVE_SPACE,
+            header: 0,
+        }
+    }
+}
+
+/// A system (line) of music.
+#[derive(Debug, PartialEq)]
+pub struct System {
+    /// Bars in the system.
+    pub bars: Range<u16>,
+    /// Justified width of each bar (or multi-bar rest).
+    pub widths: Vec<i32>,
+    /// Y position of the system on the page.
+    pub y: i32,
+}
+
+/// A page of music.
+pub struct Page {
+    /// Width of the page.
+    pub width: i32,
+    /// Height of the page.
+    pub height: i32,
+    /// Systems on the page.
+    pub systems: Vec<System>,
+    /// Engraved elements of the page.
+    pub group: Group,
+}
+
+impl Page {
+    /// Render the page as an SVG document.
+    pub fn svg(&self, glyph_defs: &str) -> String {
+        svg_document(self.width, self.height, glyph_defs, &self.group)
+    }
+
+    /// Check if the page has text, which is left out of PNG images.
+    pub fn has_text(&self) -> bool {
+        has_text(&self.group.elements)
+    }
+
+    /// Render the page as a PNG image (without text).
+    pub fn png(&self, glyph_paths: &str, options: &RasterOptions) -> Vec<u8> {
+        let elements = &self.group.elements;
+        render_png(elements, self.width, self.height, glyph_paths, options)
+    }
+}
+
+/// Lay out a movement of the score into pages.
+pub fn layout(
+    scof: &Scof,
+    meta: &SfFontMetadata,
+    options: &LayoutOptions,
+) -> Vec<Page> {
+    let movement = options.movement;
+    let chans = scof.chan_len(&Cursor::new(movement, 0, 0, 0));
+    let units = bar_units(scof, movement);
+    let range = stave_range(scof, movement);
+
+    // Engrave each bar (or multi-bar rest) with natural spacing.
+    let mut natural = vec![];
+    let mut widths = vec![];
+    let mut notes = vec![];
+    for bars in &units {
+        let elem = engrave_bars(scof, meta, movement, bars, range);
+        let cursor = Cursor::new(movement, bars.start, 0, 0);
+        widths.push(elem.width);
+        notes.push(note_count(scof, &cursor));
+        natural.push(elem);
+    }
+
+    // Break into systems and justify.
+    let line_width = options.page_width - 2 * options.margin;
+    let lines = break_lines(&widths, &notes, line_width);
+    let justified = justify(&widths, &lines, line_width);
+
+    // Break systems into pages.
+    let system_height = natural
+        .first()
+        .map(|e| e.stave_group_height(chans))
+        .unwrap_or(0);
+    let bottom = options.page_height - options.margin;
+
+    let mut pages: Vec<Page> = vec![];
+    let mut y = options.margin + options.header;
+    for (line, widths) in lines.into_iter().zip(justified) {
+        let full = y + system_height > bottom
+            && pages.last().map(|p| !p.systems.is_empty()).unwrap_or(false);
+        if pages.is_empty() || full {
+            if !pages.is_empty() {
+                y = options.margin;
+            }
+            pages.push(Page {
+                width: options.page_width,
+                height: options.page_height,
+                systems: vec![],
+                group: Group::new(0, 0, None),
+            });
+        }
+        let page = pages.last_mut().unwrap();
+
+        // Re-engrave each bar with justified spacing.
+        let mut offset_x = options.margin;
+        for (unit, width) in line.clone().zip(widths.iter()) {
+            let mut elem = bar_elem(range);
+            elem.justify(&natural[unit], *width);
+            if unit == line.start {
+                elem.start_system();
+            }
+            add_bars(&mut elem, scof, meta, movement, &units[unit]);
+            if unit == line.start {
+                elem.add_stave_groups(meta, scof, chans);
+            }
+            let mut group = Group::new(offset_x, y, None);
+            offset_x += elem.width;
+            for e in elem.elements {
+                group.push(e);
+            }
+            page.group.push(Element::Group(group));
+        }
+        page.systems.push(System {
+            bars: units[line.start].start..units[line.end - 1].end,
+            widths,
+            y,
+        });
+        y += system_height + options.system_spacing;
+    }
+    pages
+}
+
+/// Count the notes in the bar (the most of any channel).
+fn note_count(scof: &Scof, cursor: &Cursor) -> u16 {
+    (0..scof.chan_len(cursor))
+        .map(|chan| scof.marking_len(&cursor.chan(chan)))
+        .max()
+        .unwrap_or(0)
+}
+
+/// Get the width of a line of bars after widening the narrow bars so that no
+/// bar is less than half the width of the widest.
+fn line_width(widths: &[i32]) -> i32 {
+    let max = widths.iter().cloned().max().unwrap_or(0);
+    widths.iter().map(|w| (*w).max(max / MAX_WIDTH_RATIO)).sum()
+}
+
+/// Check if a line of bars fits within the rules.
+fn fits(widths: &[i32], notes: &[u16], width: i32) -> bool {
+    widths.len() <= MAX_MEASURES
+        && notes.iter().sum::<u16>() <= MAX_NOTES
+        && line_width(widths) <= width
+}
+
+/// Break bars into lines, given the natural width & number of notes of each.
+fn break_lines(widths: &[i32], notes: &[u16], width: i32) -> Vec<Range<usize>> {
+    // Fill each line as much as possible.
+    let mut lines: Vec<Range<usize>> = vec![];
+    let mut start = 0;
+    for end in 1..=widths.len() {
+        if end - start > 1
+            && !fits(&widths[start..end], &notes[start..end], width)
+        {
+            lines.push(start..end - 1);
+            start = end - 1;
+        }
+    }
+    if start < widths.len() {
+        lines.push(start..widths.len());
+    }
+
+    // Balance sequential lines so they don't differ by more than 2 measures.
+    let fits_range = |r: &Range<usize>| {
+        r.len() == 1 || fits(&widths[r.clone()], &notes[r.clone()], width)
+    };
+    for _ in 0..widths.len() * MAX_MEASURES {
+        let mut changed = false;
+        for i in 1..lines.len() {
+            let (prev, next) = (lines[i - 1].clone(), lines[i].clone());
+            let (a, b) = if prev.len() > next.len() + MAX_MEASURE_DIFF {
+                // Move the last bar of the previous line down.
+                (prev.start..prev.end - 1, prev.end - 1..next.end)
+            } else if next.len() > prev.len() + MAX_MEASURE_DIFF {
+                // Move the first bar of the next line up.
+                (prev.start..prev.end + 1, next.start + 1..next.end)
+            } else {
+                continue;
+            };
+            if fits_range(&a) && fits_range(&b) {
+                lines[i - 1] = a;
+                lines[i] = b;
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+    lines
+}
+
+/// Justify the bars in each line to fill the line width.
+fn justify(
+    widths: &[i32],
+    lines: &[Range<usize>],
+    width: i32,
+) -> Vec<Vec<i32>> {
+    let mut justified: Vec<Vec<i32>> = vec![];
+    for (i, line) in lines.iter().enumerate() {
+        let natural = &widths[line.clone()];
+        let max = natural.iter().cloned().max().unwrap_or(0);
+        let mut bars: Vec<i32> = natural
+            .iter()
+            .map(|w| (*w).max(max / MAX_WIDTH_RATIO))
+            .collect();
+        // Don't stretch a last line that is less than half full.
+        let total: i32 = bars.iter().sum();
+        let last = i + 1 == lines.len();
+        if !last || total * 2 >= width {
+            // Distribute the extra space in proportion to the widths.
+            let extra = width - total;
+            let mut given = 0;
+            for bar in bars.iter_mut() {
+                let add = (extra as i64 * *bar as i64 / total as i64) as i32;
+                *bar += add;
+                given += add;
+            }
+            if let Some(bar) = bars.last_mut() {
+                *bar += extra - given;
+            }
+        }
+        // Align barlines with the previous line if it has the same number of
+        // measures.
+        if let Some(prev) = justified.last() {
+            if prev.len() == bars.len() && !last {
+                align_barlines(prev, &mut bars, natural, width);
+            }
+        }
+        justified.push(bars);
+    }
+    justified
+}
+
+/// Move barlines closer to the barlines of the previous line, without making
+/// bars narrower than their natural width.
+fn align_barlines(
+    prev: &[i32],
+    bars: &mut Vec<i32>,
+    natural: &[i32],
+    width: i32,
+) {
+    let limit = width / MAX_BARLINE_DIFF;
+    for _ in 0..8 {
+        let mut diff = 0;
+        let (mut x, mut prev_x) = (0, 0);
+        for (bar, prev_bar) in bars.iter().zip(prev.iter()) {
+            x += bar;
+            prev_x += prev_bar;
+            diff = diff.max((x - prev_x).abs());
+        }
+        if diff <= limit {
+            return;
+        }
+        let average: Vec<i32> = bars
+            .iter()
+            .zip(prev.iter())
+            .map(|(a, b)| (a + b) / 2)
+            .collect();
+        if average.iter().zip(natural.iter()).any(|(a, n)| a < n) {
+            return;
+        }
+        *bars = average;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn measures_per_line() {
+        let widths = [100; 20];
+        let notes = [1; 20];
+        let lines = break_lines(&widths, &notes, 100_000);
+        assert_eq!(lines, vec![0..8, 8..15, 15..20]);
+    }
+
+    #[test]
+    fn notes_per_line() {
+        let widths = [100; 8];
+        let notes = [8; 8];
+        let lines = break_lines(&widths, &notes, 100_000);
+        assert_eq!(lines, vec![0..4, 4..8]);
+    }
+
+    #[test]
+    fn width_ratio() {
+        // The narrow bars need to be widened to 500, so they don't fit.
+        let widths = [1000, 100, 100];
+        let notes = [1; 3];
+        assert_eq!(line_width(&widths), 2000);
+        let lines = break_lines(&widths, &notes, 1500);
+        assert_eq!(lines, vec![0..2, 2..3]);
+        let justified = justify(&widths, &lines, 1500);
+        assert_eq!(justified[0], vec![1000, 500]);
+    }
+
+    #[test]
+    fn justified_width() {
+        let widths = [300, 400, 500, 600];
+        let notes = [1; 4];
+        let lines = break_lines(&widths, &notes, 1000);
+        assert_eq!(lines, vec![0..2, 2..3, 3..4]);
+        let justified = justify(&widths, &lines, 1000);
+        for line in &justified {
+            assert_eq!(line.iter().sum::<i32>(), 1000);
+        }
+    }
+
+    #[test]
+    fn aligned_barlines() {
+        let mut bars = vec![600, 200, 200];
+        align_barlines(&[200, 200, 600], &mut bars, &[100; 3], 1000);
+        assert_eq!(bars, vec![400, 200, 400]);
+    }
+
+    #[test]
+    fn pages() {
+        let scof = Scof::default();
+        let meta = crate::tests::meta();
+        let options = LayoutOptions {
+            page_width: 200 * STAVE_SPACE,
+            page_height: 20 * STAVE_SPACE,
+            ..Default::default()
+        };
+        let pages = layout(&scof, &meta, &options);
+        assert_eq!(pages.len(), 1);
+        assert_eq!(pages[0].systems.len(), 1);
+        assert_eq!(pages[0].systems[0].bars, 0..4);
+        assert_eq!(pages[0].group.elements.len(), 4);
+        let svg = pages[0].svg("");
+        assert!(svg.contains("viewBox='0 0 20000 2000'"));
+        let png = pages[0].png("", &Default::default());
+        assert_eq!(png[16..24], [0, 0, 5, 43, 0, 0, 0, 133]);
+    }
+
+    #[test]
+    fn multi_rests() {
+        let text = "sig: 0\n  time: 4/4\n  tempo: 120\n\
+            bar:\n  chan: 1/1R\nbar:\n  chan: 1/1R\nbar:\n  chan: 1/1R\n\
+            bar:\n  chan: 1/1C4\n";
+        let mut scof = Scof {
+            movement: vec![scof::Movement::from_muon(text).unwrap()],
+            ..Default::default()
+        };
+        let meta = crate::tests::meta();
+        let pages = layout(&scof, &meta, &Default::default());
+        let system = &pages[0].systems[0];
+        assert_eq!(system.bars, 0..4);
+        assert_eq!(system.widths.len(), 2);
+        // H-bar with the number of bars drawn above.
+        let svg = pages[0].svg("");
+        assert_eq!(svg.matches("href='#5c'").count(), 1);
+        assert_eq!(svg.matches("href='#43'").count(), 1);
+        assert_eq!(svg.matches("href='#b'").count(), 0);
+        // Not collapsed for a full score.
+        scof.style.multi_rests = Some(false);
+        let pages = layout(&scof, &meta, &Default::default());
+        assert_eq!(pages[0].systems[0].widths.len(), 4);
+        assert_eq!(pages[0].svg("").matches("href='#5c'").count(), 0);
+    }
+
+    #[test]
+    fn page_breaks() {
+        let scof = Scof::default();
+        let meta = crate::tests::meta();
+        let options = LayoutOptions {
+            page_height: 20 * STAVE_SPACE,
+            ..Default::default()
+        };
+        let pages = layout(&scof, &meta, &options);
+        assert_eq!(pages.len(), 2);
+        assert_eq!(pages[0].systems[0].bars, 0..3);
+        assert_eq!(pages[1].systems[0].bars, 3..4);
+        // Full systems are justified to the line width.
+        let width: i32 = pages[0].systems[0].widths.iter().sum();
+        assert_eq!(width, options.page_width - 2 * options.margin);
+    }
+}
@@ -18,22 +18,42 @@
 
 #![allow(clippy::blacklisted_name)] // bar is a useful musical term
 
+mod articulation;
 mod beaming;
+mod document;
+mod dynamic;
 mod glyph;
+mod grace;
+mod grouping;
+mod layout;
+mod lyric;
 mod notator;
 mod notehead;
+mod ornament;
+mod path_data;
+mod pause;
+mod pdf;
+mod raster;
+mod rehearsal;
+mod repeat;
 mod rhythmic_spacing;
+mod signature;
+mod spanner;
 mod svg;
 
 pub use sfff::{SfFontMetadata, STAVE_SPACE};
-pub use svg::{Element, Group, Path, Rect, Use};
+pub use document::{render_svg, RenderOptions};
+pub use layout::{layout, LayoutOptions, Page, System};
+pub use pdf::{render_pdf, PaperSize, PdfOptions};
+pub use raster::{has_text, render_png, RasterOptions};
+pub use svg::{Element, Group, Path, Rect, Text, Use};
 
 use beaming::{Beam, Beams, Short};
 use notator::Notator;
 use notehead::Notehead;
 use rhythmic_spacing::BarEngraver;
 
-use scof::{Cursor, Scof, Steps};
+use scof::{Cursor, Grouping, Repeat, Scof, Steps};
 use sfff::Glyph;
 use std::fmt;
 use cala::log::{Tag, log};
@@ -44,6 +64,34 @@ const INFO: Tag = Tag::new("Staverator");
 const BAR_WIDTH: i32 = 8 * STAVE_SPACE;
 /// Width of a whole rest (in font units).
 const WHOLE_REST_WIDTH: i32 = 230;
+/// Width of a multi-bar rest H-bar (in font units).
+const MULTI_REST_WIDTH: i32 = 4 * STAVE_SPACE;
+/// Minimum width of a multi-bar rest (in bars).
+const MULTI_REST_BARS: f32 = 1.5;
+/// Width of a time signature digit (in font units).
+const TIME_DIGIT_WIDTH: i32 = 450;
+/// Distance between the beams of a beamed group (in font units).
+const BEAM_SPACING: i32 = 3 * Stave::STEP / 2;
+
+/// A straight line through the stem ends of a beamed group.
+struct BeamLine {
+    /// X position of the first and last stems
+    x: (i32, i32),
+    /// Y position of the first and last stem ends
+    y: (i32, i32),
+}
+
+impl BeamLine {
+    /// Get the Y position of the line at `x`.
+    fn at(&self, x: i32) -> i32 {
+        let ((x0, x1), (y0, y1)) = (self.x, self.y);
+        if x1 == x0 {
+            y0
+        } else {
+            y0 + (y1 - y0) * (x - x0) / (x1 - x0)
+        }
+    }
+}
 
 /// FIXME: REMOVE - Get Bravura font paths
 pub fn bravura() -> Vec<Path> {
@@ -52,14 +100,19 @@ pub fn bravura() -> Vec<Path> {
 
 /// Get Modern font data as SVG defs.
 pub fn modern() -> (sfff::SfFontMetadata, String) {
-    let data: &[u8] = include_bytes!("../modern.sfff");
-    let data = std::io::Cursor::new(data);
-    let (meta, glyphs) = sfff::SfFontMetadata::from_buf_reader(data).unwrap();
+    let (meta, glyphs) = modern_glyphs();
     let glyphs = sfff::generate_defs(&glyphs);
 
     (meta, glyphs)
 }
 
+/// Get Modern font data with the glyph SVG paths (separated by null bytes).
+pub fn modern_glyphs() -> (sfff::SfFontMetadata, String) {
+    let data: &[u8] = include_bytes!("../modern.sfff");
+    let data = std::io::Cursor::new(data);
+    sfff::SfFontMetadata::from_buf_reader(data).unwrap()
+}
+
 /// Stave lines
 pub struct Stave {
     /// Number of lines on stave
@@ -158,6 +211,29 @@ pub struct BarElem {
     pub width: i32,
     /// SVG Elements
     pub elements: Vec<Element>,
+    /// Horizontal scale of the rhythmic spacing (1.0 is natural spacing)
+    stretch: f32,
+    /// Width of the rhythmic spacing, not including the bar margins
+    spacing: i32,
+    /// Number of bars in a multi-bar rest (0 for a single bar)
+    multi_rest: u16,
+    /// Repeat markings of the measure
+    repeat: Vec<Repeat>,
+    /// Positions of the notes engraved in the measure
+    anchors: Vec<spanner::Anchor>,
+    /// Trills to extend to the next note (cursor of the note, start X and Y)
+    trills: Vec<(Cursor, i32, i32)>,
+    /// Fermatas to engrave on every stave (center X, stave offset and Y)
+    fermatas: Vec<(i32, Steps, i32)>,
+    /// Positions of the dynamics and hairpin markings engraved in the
+    /// measure (cursor of the marking, left and right X)
+    dynamics: Vec<(Cursor, i32, i32)>,
+    /// Syllables of lyrics sung in the measure
+    lyrics: Vec<lyric::Lyric>,
+    /// Width of the signature at the start of the measure
+    signature: i32,
+    /// Whether the measure starts a system (for its bar number)
+    system_start: bool,
 }
 
 impl fmt::Display for BarElem {
@@ -172,11 +248,10 @@ impl fmt::Display for BarElem {
 impl BarElem {
     /// Length of stems
     const STEM_LENGTH: i32 = 7 * Stave::STEP;
-    /// Maximum stem length for beamed notes on stave lines.
-    const STEM_LENGTH_LINE: i32 = Self::STEM_LENGTH - (Stave::STEP / 2);
-    /// FIXME: Minimum Shortened Stem Length For Notes On Ledger Lines
-    const _STEM_LENGTH_LEDGER: i32 = 5 * Stave::STEP;
-    /// Minimum Shortened Stem Length For Notes On Stave
+    /// Minimum shortened stem length (in a beamed group) for notes on ledger
+    /// lines
+    const STEM_LENGTH_LEDGER: i32 = 5 * Stave::STEP;
+    /// Minimum shortened stem length (in a beamed group) for notes on stave
     const STEM_LENGTH_SHORT: i32 = 6 * Stave::STEP;
 
     /// Create a new bar element
@@ -185,15 +260,52 @@ impl BarElem {
         let steps_bottom = stave.steps_bottom(low);
         let width = 0;
         let elements = vec![];
+        let stretch = 1.0;
+        let spacing = 0;
+        let multi_rest = 0;
+        let repeat = vec![];
+        let anchors = vec![];
+        let trills = vec![];
+        let fermatas = vec![];
+        let dynamics = vec![];
+        let lyrics = vec![];
+        let signature = 0;
+        let system_start = false;
         Self {
             stave,
             steps_top,
             steps_bottom,
             width,
             elements,
+            stretch,
+            spacing,
+            multi_rest,
+            repeat,
+            anchors,
+            trills,
+            fermatas,
+            dynamics,
+            lyrics,
+            signature,
+            system_start,
         }
     }
 
+    /// Stretch the rhythmic spacing of this measure so that when engraved it
+    /// is `width` wide, given the measure engraved with natural spacing.
+    pub fn justify(&mut self, natural: &BarElem, width: i32) {
+        let margins = 2 * Stave::SPACE;
+        if natural.spacing > 0 {
+            self.stretch =
+                (width - margins).max(0) as f32 / natural.spacing as f32;
+        }
+    }
+
+    /// Set the measure to be the first of a system, so that it's numbered.
+    pub fn start_system(&mut self) {
+        self.system_start = true;
+    }
+
     /// Add markings to this measure.
     ///
     /// - `scof`: The score.
@@ -204,18 +316,86 @@ impl BarElem {
         scof: &Scof,
         cursor: &Cursor,
         curs: &mut Cursor,
+    ) -> Option<(i32, i32, i32, i32)> {
+        self.engrave(meta, scof, Some(cursor), curs)
+    }
+
+    /// Add markings to this measure, without a user's cursor.
+    ///
+    /// - `scof`: The score.
+    /// - `curs`: Cursor of measure.
+    pub fn add_bar(
+        &mut self,
+        meta: &SfFontMetadata,
+        scof: &Scof,
+        curs: &Cursor,
+    ) {
+        self.engrave(meta, scof, None, &mut curs.clone());
+    }
+
+    /// Add a multi-bar rest for `bars` empty bars, starting at a measure.
+    ///
+    /// - `scof`: The score.
+    /// - `curs`: Cursor of the first measure.
+    pub fn add_multi_rest(
+        &mut self,
+        meta: &SfFontMetadata,
+        scof: &Scof,
+        curs: &Cursor,
+        bars: u16,
+    ) {
+        self.multi_rest = bars;
+        self.engrave(meta, scof, None, &mut curs.clone());
+    }
+
+    /// Engrave all channels of the measure, returning the cursor rectangle.
+    fn engrave(
+        &mut self,
+        meta: &SfFontMetadata,
+        scof: &Scof,
+        cursor: Option<&Cursor>,
+        curs: &mut Cursor,
     ) -> Option<(i32, i32, i32, i32)> {
         let reset_cursor = curs.clone();
+        self.anchors.clear();
+        self.trills.clear();
+        self.fermatas.clear();
+        self.dynamics.clear();
+        self.set_lyrics(scof, &reset_cursor);
+        self.repeat = scof
+            .movement
+            .get(curs.movement_index() as usize)
+            .and_then(|mvmt| mvmt.bar.get(curs.bar_index() as usize))
+            .map(|measure| measure.repeats().collect())
+            .unwrap_or_default();
 
-        // Make notators for each stave.
+        self.add_rehearsal(meta, scof, &reset_cursor);
+        let x = self.width + Stave::SPACE + self.repeat_open_width(meta);
+        self.signature = self.add_signatures(meta, scof, &reset_cursor, x);
+
+        // Make notators for each voice of each stave.
         let mut notators = vec![];
-        for chan in 0..scof.movement[0].bar[0].chan.len() as u16 {
+        for chan in 0..scof.chan_len(&reset_cursor) {
             *curs = reset_cursor.chan(chan);
-            notators.push(Notator::new(scof, cursor.clone(), curs.clone()));
+            for voice in 0..scof.voice_len(curs) {
+                notators.push(Notator::new(
+                    scof,
+                    cursor.cloned(),
+                    curs.voice(voice),
+                    chan.into(),
+                ));
+            }
         }
 
         // Engrave the music.
         let (width, rect) = BarEngraver::new(self, &mut notators).engrave(meta);
+        let barline = width - self.repeat_close_width(meta);
+        self.add_group_barlines(meta, scof, barline, scof.chan_len(curs));
+        self.add_navigation(meta, scof, &reset_cursor, width);
+        self.add_spanners(meta, scof, &reset_cursor, barline);
+        self.add_hairpins(meta, scof, &reset_cursor, barline);
+        self.add_trill_lines(self.width + barline);
+        self.add_lyric_lines(meta, self.width + barline);
         self.width += width;
         rect
     }
@@ -231,15 +411,114 @@ impl BarElem {
         ((self.steps_top - self.steps_bottom) * Stave::STEP).0
     }
 
+    /// Get the height of all of the staves for `chans` channels
+    fn stave_group_height(&self, chans: u16) -> i32 {
+        let ymargin = self.stave.height_steps() + Steps(12);
+        let stacked = ymargin * i32::from(chans.max(1) - 1);
+        self.height() + (stacked * Stave::STEP).0
+    }
+
     /// Get the middle of the stave y position
     fn middle(&self) -> i32 {
         let steps = self.stave.steps_middle_c - self.stave.height_steps() / 2;
         self.offset_y(steps)
     }
 
+    /// Get the Y position of the middle line of a stave.
+    fn middle_line(&self, ofs: Steps) -> i32 {
+        self.middle() + (ofs * Stave::STEP).0
+    }
+
+    /// Check if the stems of a chord with noteheads from `y_high` to `y_low`
+    /// point up (true if forced by the voice).  The note furthest from the
+    /// middle line decides, and stems point down if they're as far.
+    fn chord_stems_up(
+        &self,
+        y_high: i32,
+        y_low: i32,
+        ofs: Steps,
+        stems: Option<bool>,
+    ) -> bool {
+        stems.unwrap_or(y_high + y_low > self.middle_line(ofs) * 2)
+    }
+
+    /// Get the Y position of the end of a stem from the notehead nearest to
+    /// it, extended to reach the middle line (for notes on ledger lines).
+    fn stem_end(&self, y: i32, up: bool, ofs: Steps) -> i32 {
+        let middle = self.middle_line(ofs);
+        if up {
+            (y - Self::STEM_LENGTH).min(middle)
+        } else {
+            (y + Self::STEM_LENGTH).max(middle)
+        }
+    }
+
+    /// Get the minimum length of a shortened stem (in a beamed group) from a
+    /// notehead at `y`.
+    fn stem_min(&self, y: i32, ofs: Steps) -> i32 {
+        if self.top_line(ofs) <= y && y <= self.bottom_line(ofs) {
+            Self::STEM_LENGTH_SHORT
+        } else {
+            Self::STEM_LENGTH_LEDGER
+        }
+    }
+
+    /// Add a stem spanning a chord from `y_high` to `y_low`, returning the Y
+    /// position of its end.
+    ///
+    /// - `x`: X position of the (undisplaced) noteheads.
+    fn add_chord_stem(
+        &mut self,
+        meta: &SfFontMetadata,
+        dur: u16,
+        x: i32,
+        (y_high, y_low): (i32, i32),
+        ofs: Steps,
+        up: bool,
+    ) -> i32 {
+        let end = if up {
+            self.stem_end(y_high, true, ofs)
+        } else {
+            self.stem_end(y_low, false, ofs)
+        };
+        self.add_stem_to(meta, dur, x, (y_high, y_low), up, end);
+        end
+    }
+
+    /// Add a stem spanning a chord from `y_high` to `y_low`, ending at `end`.
+    ///
+    /// - `x`: X position of the (undisplaced) noteheads.
+    fn add_stem_to(
+        &mut self,
+        meta: &SfFontMetadata,
+        dur: u16,
+        x: i32,
+        (y_high, y_low): (i32, i32),
+        up: bool,
+        end: i32,
+    ) {
+        let [left, right] = notehead::stems(Notehead::Normal, meta, dur);
+        if up {
+            let x = x + right[0] - meta.stem_thickness;
+            self.add_stem2(meta, x, end, y_low - end);
+        } else {
+            self.add_stem2(meta, x + left[0], y_high, end - y_high);
+        }
+    }
+
+    /// Get the Y positions of the highest and lowest notes of a chord.
+    fn chord_ys(&self, steps: &[Steps], ofs: Steps) -> Option<(i32, i32)> {
+        let (high, low) = (*steps.iter().max()?, *steps.iter().min()?);
+        Some((self.y_from_steps(high, ofs), self.y_from_steps(low, ofs)))
+    }
+
     /// Add a barline to stave
     fn add_barline(&mut self, meta: &SfFontMetadata, x: i32, ofs: Steps) {
-        let width = meta.barline_thickness;
+        self.add_barline_width(x, meta.barline_thickness, ofs);
+    }
+
+    /// Add a barline of a given thickness to stave
+    fn add_barline_width(&mut self, x: i32, width: i32, ofs: Steps) {
         let ofs = (ofs * Stave::STEP).0;
         let y = self.offset_y(self.stave.steps_middle_c) + ofs;
         let y_bottom = self.offset_y(self.stave.steps_stave_bottom()) + ofs;
@@ -248,6 +527,81 @@ impl BarElem {
         self.elements.push(Element::Rect(rect));
     }
 
+    /// Get the Y position of the top line of a channel's stave
+    fn stave_top(&self, chan: u16) -> i32 {
+        let ymargin = self.stave.height_steps() + Steps(12);
+        self.y_from_steps(self.stave.steps_middle_c, ymargin * i32::from(chan))
+    }
+
+    /// Get the Y position of the bottom line of a channel's stave
+    fn stave_bottom(&self, chan: u16) -> i32 {
+        let ymargin = self.stave.height_steps() + Steps(12);
+        let steps = self.stave.steps_stave_bottom();
+        self.y_from_steps(steps, ymargin * i32::from(chan))
+    }
+
+    /// Run barlines through the staves of brace and bracket groups
+    fn add_group_barlines(
+        &mut self,
+        meta: &SfFontMetadata,
+        scof: &Scof,
+        x: i32,
+        chans: u16,
+    ) {
+        for chan in 0..chans.saturating_sub(1) {
+            if scof.style.joins_barlines(chan) {
+                let y = self.stave_bottom(chan);
+                let height = self.stave_top(chan + 1) - y;
+                let width = meta.barline_thickness;
+                let rect = Rect::new(x, y, width, height, None, None, None);
+                self.elements.push(Element::Rect(rect));
+            }
+        }
+    }
+
+    /// Add braces and brackets for stave groups, to the left of the start of
+    /// a system of `chans` channels.
+    pub fn add_stave_groups(
+        &mut self,
+        meta: &SfFontMetadata,
+        scof: &Scof,
+        chans: u16,
+    ) {
+        let x = -Stave::SPACE / 2;
+        for (grouping, group) in scof.style.stave_groups() {
+            if *group.start() >= chans {
+                continue;
+            }
+            let top = self.stave_top(*group.start());
+            let bottom = self.stave_bottom((*group.end()).min(chans - 1));
+            let d = match grouping {
+                Grouping::Brace => {
+                    grouping::brace(x, top, bottom, meta.bracket_thickness)
+                }
+                Grouping::Bracket => {
+                    grouping::bracket(x, top, bottom, meta.bracket_thickness)
+                }
+                Grouping::SubBracket => {
+                    // Sub-brackets are outside of the bracket.
+                    let x = x - meta.bracket_thickness - Stave::SPACE / 4;
+                    let thickness = meta.subbracket_thickness;
+                    grouping::subbracket(x, top, bottom, thickness)
+                }
+            };
+            self.elements.push(Element::Path(Path::new(None, d)));
+        }
+    }
+
+    /// Get the Y position of the top line of a stave.
+    fn top_line(&self, ofs: Steps) -> i32 {
+        self.y_from_steps(self.stave.steps_middle_c, ofs)
+    }
+
+    /// Get the Y position of the bottom line of a stave.
+    fn bottom_line(&self, ofs: Steps) -> i32 {
+        self.top_line(ofs) + (self.stave.height_steps() * Stave::STEP).0
+    }
+
     /// Get Y position from steps and offset
     fn y_from_steps(&self, steps: Steps, ofs: Steps) -> i32 {
         let ofs = (ofs * Stave::STEP).0;
@@ -256,87 +610,147 @@ impl BarElem {
         y + ofs
     }
 
-    /// Add elements for flag and stem.
+    /// Add elements for flag and stem (spanning the chord).
     fn add_flag(
         &mut self,
         meta: &SfFontMetadata,
         dur: u16,
         offset: f32,
-        y: Steps,
+        steps: &[Steps],
         y_offset: Steps,
+        stems: Option<bool>,
     ) {
-        let y = self.y_from_steps(y, y_offset);
-        let flag_glyph = glyph::flag_duration(dur, y > self.middle()).unwrap();
+        let ys = match self.chord_ys(steps, y_offset) {
+            Some(ys) => ys,
+            None => return,
+        };
+        let up = self.chord_stems_up(ys.0, ys.1, y_offset, stems);
+        let flag_glyph = glyph::flag_duration(dur, up).unwrap();
         let x = self.width + ((offset * BAR_WIDTH as f32) as i32);
         let [left, right] = notehead::stems(Notehead::Normal, meta, dur);
-
-        if y > self.middle() {
+        let end = self.add_chord_stem(meta, dur, x, ys, y_offset, up);
+        let ofsx = if up {
             // Right Stem
-            let ofsx = right[0] - meta.stem_thickness;
-            let ofsy = -Self::STEM_LENGTH;
-
-            self.add_use(flag_glyph, x + ofsx, y + ofsy);
-            self.add_stem2(meta, x + ofsx, y + ofsy, Self::STEM_LENGTH);
+            right[0] - meta.stem_thickness
         } else {
             // Left Stem
-            let ofsx = left[0];
-            let ofsy = Self::STEM_LENGTH;
-
-            self.add_use(flag_glyph, x + ofsx, y + ofsy);
-            self.add_stem2(meta, x + ofsx, y, Self::STEM_LENGTH);
+            left[0]
         };
+        self.add_use(flag_glyph, x + ofsx, end);
+    }
+
+    /// Check if the stems of a beamed group point up (true if forced by the
+    /// voice).  The note furthest from the middle line decides, and the stems
+    /// point down when the notes are as far from it either way.
+    fn beam_stems_up(&self, beam: &Beam) -> bool {
+        // Furthest distances above and below the middle line
+        let (above, below) = beam
+            .notes
+            .iter()
+            .flat_map(|(_, _, (pitches, ofs), _)| {
+                let middle = self.middle_line(*ofs);
+                pitches.iter().map(move |p| {
+                    self.y_from_steps(p.visual_distance(), *ofs) - middle
+                })
+            })
+            .fold((0, 0), |(above, below), y| (above.max(-y), below.max(y)));
+        beam.stems.unwrap_or(below > above)
     }
 
-    /// Add beam element.
+    /// Get the line of a beam.  It slants with the notes (by at most a
+    /// space), and moves away from them until every stem is long enough.
+    ///
+    /// - `stems`: X position, Y range, stave offset and beam count of each
+    ///   note.
+    fn beam_line(
+        &self,
+        stems: &[(i32, (i32, i32), Steps, i32)],
+        up: bool,
+    ) -> Option<BeamLine> {
+        let near =
+            |(y_high, y_low): (i32, i32)| if up { y_high } else { y_low };
+        let (first, last) = (stems.first()?, stems.last()?);
+        let y0 = self.stem_end(near(first.1), up, first.2);
+        let y1 = self.stem_end(near(last.1), up, last.2);
+        let y1 = y0 + (y1 - y0).clamp(-Stave::SPACE, Stave::SPACE);
+        let x = (first.0, last.0);
+        let line = BeamLine { x, y: (y0, y1) };
+        let dir = if up { -1 } else { 1 };
+        let short = stems
+            .iter()
+            .map(|(x, ys, ofs, count)| {
+                let y = near(*ys);
+                let beams = (count - 1) * BEAM_SPACING;
+                let end = y + dir * (self.stem_min(y, *ofs) + beams);
+                (end - line.at(*x)) * dir
+            })
+            .max()
+            .unwrap_or(0)
+            .max(0);
+        let y = (y0 + dir * short, y1 + dir * short);
+        Some(BeamLine { x, y })
+    }
+
+    /// Add beam element, bringing the stems of every note to the beam line.
     fn add_beam(&mut self, meta: &SfFontMetadata, beam: Beam) {
         let thickness = Stave::STEP;
+        let stems_up = self.beam_stems_up(&beam);
         let [left, right] = notehead::stems(Notehead::Normal, meta, 32);
-        let (ofsx, ofsy, beamy) = if beam.stems_up {
-            (right[0] - meta.stem_thickness, -Self::STEM_LENGTH, 0)
+        let (ofsx, beamy) = if stems_up {
+            (right[0] - meta.stem_thickness, 0)
         } else {
-            (left[0], 0, Self::STEM_LENGTH - thickness)
+            (left[0], -thickness)
         };
 
-        let mut d = String::new();
         log!(INFO, "ADD_BEAM {} notes", beam.notes.len());
-        let mut old_x = None;
-        for note_i in 0..beam.notes.len() {
-            let (y, y_offset) = beam.notes[note_i].2;
-            let y = self.y_from_steps(y.visual_distance(), y_offset);
-            let x =
-                self.width + ((beam.notes[note_i].1 * BAR_WIDTH as f32) as i32);
-
-            self.add_stem2(meta, x + ofsx, y + ofsy, Self::STEM_LENGTH);
+        let mut stems = vec![];
+        for (dur, offset, (pitches, y_offset), short) in &beam.notes {
+            let steps: Vec<Steps> =
+                pitches.iter().map(|p| p.visual_distance()).collect();
+            let ys = match self.chord_ys(&steps, *y_offset) {
+                Some(ys) => ys,
+                None => continue,
+            };
+            let x = self.width + ((offset * BAR_WIDTH as f32) as i32);
+            let mut count = match dur {
+                1 => 5,       // 128th note beams
+                2..=3 => 4,   // 64th note beams
+                4..=7 => 3,   // 32nd note beams
+                8..=15 => 2,  // 16th note beams
+                16..=31 => 1, // 8th note beams
+                a => panic!("Invalid {}", a),
+            };
+            if *short {
+                count = count.min(1);
+            }
+            stems.push((x, ys, *y_offset, count));
+        }
+        let line = match self.beam_line(&stems, stems_up) {
+            Some(line) => line,
+            None => return,
+        };
 
+        let beam_distance = if stems_up { -1 } else { 1 } * BEAM_SPACING;
+        let mut d = String::new();
+        let mut old_x = None;
+        for (x, ys, _, count) in stems {
+            let y = line.at(x);
+            self.add_stem_to(meta, 32, x, ys, stems_up, y);
             if let Some(old_x) = old_x {
-                let diff: i32 = x - old_x;
-
-                let mut count = match beam.notes[note_i].0 {
-                    1 => 5,       // 128th note beams
-                    2..=3 => 4,   // 64th note beams
-                    4..=7 => 3,   // 32nd note beams
-                    8..=15 => 2,  // 16th note beams
-                    16..=31 => 1, // 8th note beams
-                    a => panic!("Invalid {}", a),
-                };
-
-                if beam.notes[note_i].3 {
-                    count = count.min(1);
-                }
-
-                let beam_distance = if beam.stems_up { -1 } else { 1 }
-                    * (3 * Stave::STEP) / 2;
+                let old_y = line.at(old_x);
+                let sx = ofsx + (meta.stem_thickness / 2);
                 for i in 0..count {
+                    let dy = beamy - i * beam_distance;
                     d.push_str(&format!(
-                        "M{} {}l{} {}l{} {}l{} {}z",
-                        x + ofsx + (meta.stem_thickness / 2),
-                        y + ofsy + beamy - i * beam_distance,
-                        -diff,
-                        0,
+                        "M{} {}L{} {}l{} {}L{} {}z",
+                        x + sx,
+                        y + dy,
+                        old_x + sx,
+                        old_y + dy,
                         0,
                         thickness,
-                        diff,
-                        0
+                        x + sx,
+                        y + dy + thickness,
                     ));
                 }
             }
@@ -349,49 +763,66 @@ impl BarElem {
     fn add_flags_and_beams(&mut self, meta: &SfFontMetadata, beams: Beams) {
         for short in beams {
             match short {
-                Short::Flag(dur, offset, (pitches, y_offset)) => {
-                    let pitch = pitches[0]; // FIXME: Use closest to beam/flag.
-                    self.add_flag(
-                        meta,
-                        dur,
-                        offset,
-                        pitch.visual_distance(),
-                        y_offset,
-                    );
+                Short::Flag(dur, offset, (pitches, y_offset), stems) => {
+                    let steps: Vec<Steps> =
+                        pitches.iter().map(|p| p.visual_distance()).collect();
+                    self.add_flag(meta, dur, offset, &steps, y_offset, stems);
                 }
                 Short::Beam(beam) => self.add_beam(meta, beam),
             }
         }
     }
 
-    /// Add elements for a note
-    fn add_pitch(
+    /// Add elements for a chord: noteheads (with the upper or lower note of
+    /// a second on the other side of the stem), ledger lines and a stem.
+    fn add_chord(
         &mut self,
         meta: &SfFontMetadata,
         dur: u16,
         offset: f32,
-        steps: Steps,
-        y: i32,
+        steps: &[Steps],
+        y_offset: Steps,
+        stems: Option<bool>,
     ) {
+        let ys = match self.chord_ys(steps, y_offset) {
+            Some(ys) => ys,
+            None => return,
+        };
+        let up = self.chord_stems_up(ys.0, ys.1, y_offset, stems);
         let x = self.width + ((offset * BAR_WIDTH as f32) as i32);
-
-        let cp = notehead::duration(dur);
-        self.add_use(cp, x, y);
+        let head_width = notehead::width(Notehead::Normal, meta, dur);
+        let shift = head_width - meta.stem_thickness;
+        for (steps, displaced) in displace_seconds(steps, up) {
+            let x = match (displaced, up) {
+                (false, _) => x,
+                (true, true) => x + shift,
+                (true, false) => x - shift,
+            };
+            let y = self.y_from_steps(steps, y_offset);
+            self.add_pitch(meta, dur, x, steps, y);
+        }
         // Only draw stem if not a whole note or double whole note (breve) or
-        // Shorter than quarter note.
+        // shorter than quarter note (stems of shorter notes are added with
+        // their flags and beams).
         match dur {
             1..=31 | 128..=511 => {}
             _ => {
-                let [left, right] =
-                    notehead::stems(Notehead::Normal, meta, dur);
-                let (ofsx, ofsy) = if y > self.middle() {
-                    (right[0] - meta.stem_thickness, -Self::STEM_LENGTH)
-                } else {
-                    (left[0], 0)
-                };
-                self.add_stem2(meta, x + ofsx, y + ofsy, Self::STEM_LENGTH)
+                self.add_chord_stem(meta, dur, x, ys, y_offset, up);
             }
         }
+    }
+
+    /// Add elements for a notehead and its ledger lines
+    fn add_pitch(
+        &mut self,
+        meta: &SfFontMetadata,
+        dur: u16,
+        x: i32,
+        steps: Steps,
+        y: i32,
+    ) {
+        let cp = notehead::duration(dur);
+        self.add_use(cp, x, y);
 
         // Draw Ledger Lines if below or above stave.
         let head_width = notehead::width(Notehead::Normal, meta, dur);
@@ -430,11 +861,29 @@ impl BarElem {
 
     /// Add `use` element for a whole measure rest
     fn add_measure_rest(&mut self, width: f32, y: Steps) {
+        if self.multi_rest > 1 {
+            return self.add_multi_rest_glyphs(width, y);
+        }
         let x = ((width * BAR_WIDTH as f32) as i32 - WHOLE_REST_WIDTH) / 2;
         let y = self.middle() + ((y - Steps(2)) * Stave::STEP).0;
         self.add_use(Glyph::Rest1, x, y);
     }
 
+    /// Add `use` elements for a multi-bar rest, with the number of bars
+    /// above the stave.
+    fn add_multi_rest_glyphs(&mut self, width: f32, y: Steps) {
+        let center = (width * BAR_WIDTH as f32) as i32 / 2;
+        let y = self.middle() + (y * Stave::STEP).0;
+        self.add_use(Glyph::RestMulti, center - MULTI_REST_WIDTH / 2, y);
+        let digits = self.multi_rest.to_string();
+        let mut x = center - TIME_DIGIT_WIDTH * digits.len() as i32 / 2;
+        for digit in digits.chars().filter_map(|c| c.to_digit(10)) {
+            let glyph = glyph::time_digit(digit);
+            self.add_use(glyph, x, y - 4 * Stave::SPACE);
+            x += TIME_DIGIT_WIDTH;
+        }
+    }
+
     /// Add `use` element for a rest.
     fn add_rest(&mut self, glyph: Glyph, offset: f32, ofs: Steps) {
         let x = self.width + ((offset * BAR_WIDTH as f32) as i32);
@@ -462,35 +911,244 @@ impl BarElem {
         }
         self.width += 1000;
     }
+}
 
-    /// Add time signature
-    pub fn add_times(&mut self, scof: &Scof) {
-        for i in 0..scof.movement[0].bar[0].chan.len() as i32 {
-            let ymargin =
-                (self.stave.height_steps() + Steps(12)).0 * Stave::STEP;
-            // width=421
-            self.add_use(
-                Glyph::TimeSig3,
-                self.width + 50,
-                self.middle() - Stave::SPACE + ymargin * i,
-            );
-            // width=470
-            self.add_use(
-                Glyph::TimeSig4,
-                self.width + 50 - ((470 - 421) / 2),
-                self.middle() + Stave::SPACE + ymargin * i,
-            );
+/// Get the notes of a chord in order from the notehead the stem starts at,
+/// with whether each is displaced to the other side of the stem (the upper
+/// note of a second with the stem up, or the lower with the stem down).
+fn displace_seconds(steps: &[Steps], up: bool) -> Vec<(Steps, bool)> {
+    let mut steps = steps.to_vec();
+    steps.sort();
+    if !up {
+        steps.reverse();
+    }
+    let mut notes: Vec<(Steps, bool)> = vec![];
+    for steps in steps {
+        let displaced = match notes.last() {
+            Some((prev, displaced)) => {
+                !displaced && (steps.0 - prev.0).abs() == 1
+            }
+            None => false,
+        };
+        notes.push((steps, displaced));
+    }
+    notes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Font metadata for testing (the real font files are stored in LFS).
+    pub(crate) fn meta() -> SfFontMetadata {
+        SfFontMetadata {
+            sffonts_version: 0,
+            font_name: "Test".to_string(),
+            stave_line_thickness: 13,
+            stem_thickness: 12,
+            ledger_line_thickness: 16,
+            ledger_line_extension: 40,
+            slur_endpoint_thickness: 10,
+            slur_midpoint_thickness: 22,
+            barline_thickness: 16,
+            thick_barline_thickness: 50,
+            barlines_space: 40,
+            barline_repeatdot_space: 16,
+            bracket_thickness: 50,
+            subbracket_thickness: 16,
+            hairpin_thickness: 16,
+            rehearsal_box_thickness: 16,
+            notehead: [[0, 17], [118, -17]],
+            notehead_x: [[0, 17], [118, -17]],
+            notehead_diamond: [[0, 17], [118, -17]],
+            notehead_triangle: [[0, 17], [118, -17]],
+            notehead_slash: [[0, 17], [200, -17]],
+            notehead_half: [[0, 17], [118, -17]],
+            notehead_half_x: [[0, 17], [118, -17]],
+            notehead_half_diamond: [[0, 17], [118, -17]],
+            notehead_half_triangle: [[0, 17], [118, -17]],
+            notehead_half_slash: [[0, 17], [200, -17]],
+            notehead_whole: [[0, 0], [166, 0]],
+            notehead_whole_x: [[0, 0], [166, 0]],
+            notehead_whole_diamond: [[0, 0], [166, 0]],
+            notehead_whole_triangle: [[0, 0], [166, 0]],
+            notehead_whole_slash: [[0, 0], [250, 0]],
+            notehead_double: [[0, 0], [250, 0]],
+            notehead_double_x: [[0, 0], [250, 0]],
+            notehead_double_diamond: [[0, 0], [250, 0]],
+            notehead_double_triangle: [[0, 0], [250, 0]],
+            notehead_double_slash: [[0, 0], [300, 0]],
         }
+    }
 
-        self.width += 640;
+    /// Engrave a bar of a movement.
+    pub(crate) fn engrave_movement(muon: &str, bar: u16) -> BarElem {
+        let scof = Scof {
+            movement: vec![scof::Movement::from_muon(muon).unwrap()],
+            ..Default::default()
+        };
+        let range = crate::document::stave_range(&scof, 0);
+        let mut elem = crate::document::bar_elem(range);
+        elem.add_bar(&meta(), &scof, &Cursor::new(0, bar, 0, 0));
+        elem
     }
 
-    /// Add clef & time signature.
-    pub fn add_signatures(&mut self, _scof: &Scof) {
-        //self.add_clefs(_scof);
-        //self.add_times(_scof);
+    /// Engrave a 4/4 bar of one channel.
+    pub(crate) fn engrave_bar(chan: &str) -> BarElem {
+        let muon = format!(
+            "sig: 0\n  time: 4/4\n  tempo: 120\nbar:\n  chan: {}\n",
+            chan
+        );
+        engrave_movement(&muon, 0)
     }
-}
 
-#[cfg(test)]
-mod tests {}
+    /// Get the glyph IDs used in a bar.
+    pub(crate) fn glyph_ids(bar: &BarElem) -> Vec<u16> {
+        bar.elements
+            .iter()
+            .filter_map(|e| match e {
+                Element::Use(u) => Some(u.id),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Count the uses of a glyph.
+    pub(crate) fn count(glyphs: &[u16], glyph: Glyph) -> usize {
+        let id = glyph as u16;
+        glyphs.iter().filter(|g| **g == id).count()
+    }
+
+    #[test]
+    fn old_fonts() {
+        // Write and read back a font with `glyphs` (empty) glyph paths.
+        let load = |glyphs: usize| {
+            let mut data = vec![];
+            meta()
+                .write(&mut data, &vec![""; glyphs].join("\0"))
+                .unwrap();
+            SfFontMetadata::from_buf_reader(std::io::Cursor::new(data))
+        };
+        let (_, glyphs) = load(Glyph::Len as usize).unwrap();
+        let defs = sfff::generate_defs(&glyphs);
+        // Fonts without the newer glyphs still load, and get every glyph.
+        let (_, old) = load(Glyph::Staccato as usize).unwrap();
+        assert_eq!(sfff::generate_defs(&old), defs);
+        assert!(load(Glyph::Staccato as usize + 1).is_err());
+    }
+
+    /// Get the Y positions of the stems in an engraved bar.
+    fn stem_ys(chan: &str) -> Vec<i32> {
+        let bar = engrave_bar(chan);
+        // Leave out the stem of the tempo marking, above the stave.
+        let top = bar.top_line(Steps(0));
+        bar.elements
+            .iter()
+            .filter_map(|e| match e {
+                Element::Rect(rect)
+                    if rect.rx.is_some() && rect.y + rect.height > top =>
+                {
+                    Some(rect.y)
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn voice_stems() {
+        let one = stem_ys("1/2C4 1/2C4");
+        assert_eq!(one.len(), 2);
+        assert_eq!(one[0], one[1]);
+        // Upper voice has stems up, lower voice has stems down.
+        let two = stem_ys("1/2C4 1/2C4\n    voice: 1/2C4 1/2C4");
+        assert_eq!(two.len(), 4);
+        let up = two.iter().filter(|y| **y < one[0]).count();
+        assert_eq!(up, 2);
+        assert_eq!(two.iter().filter(|y| **y == one[0]).count(), 2);
+    }
+
+    /// Pairs of Y positions and heights of stems, or of X and Y positions of
+    /// noteheads.
+    type Positions = Vec<(i32, i32)>;
+
+    /// Engrave a bar, getting the stems (Y and height) and the positions of
+    /// half noteheads from top to bottom.
+    fn engrave_stems(chan: &str) -> (Positions, Positions) {
+        let bar = engrave_bar(chan);
+        let middle = bar.middle();
+        let top = bar.top_line(Steps(0));
+        let half = notehead::duration(64) as u16;
+        let mut stems = vec![];
+        let mut heads = vec![];
+        for elem in &bar.elements {
+            match elem {
+                Element::Rect(r) if r.rx.is_some() && r.y + r.height > top => {
+                    stems.push((r.y - middle, r.height))
+                }
+                Element::Use(u) if u.id == half => heads.push((u.x, u.y)),
+                _ => {}
+            }
+        }
+        heads.sort_by_key(|(_, y)| *y);
+        (stems, heads)
+    }
+
+    #[test]
+    fn chord_stems() {
+        let step = Stave::STEP;
+        let length = BarElem::STEM_LENGTH;
+        // One stem spans the chord, down when the notes are as far from the
+        // middle line, otherwise away from the furthest note.
+        let (stems, heads) = engrave_stems("1/2A3C4E4 1/2G3C4");
+        assert_eq!(
+            stems,
+            vec![(-2 * step, 4 * step + length), (-length, 3 * step + length)]
+        );
+        assert_eq!(heads.len(), 5);
+        // The lower note of a second is on the left of a stem down, and the
+        // upper on the right of a stem up.
+        let (_, down) = engrave_stems("1/2C4D4 1/2R");
+        assert!(down[0].0 > down[1].0);
+        let (_, up) = engrave_stems("1/2B3C4E3 1/2R");
+        assert!(up[0].0 > up[1].0);
+        assert_eq!(up[1].0, up[2].0);
+        // Stems of notes on ledger lines reach the middle line.
+        let (ledger, _) = engrave_stems("1/2C6 1/2C2");
+        assert_eq!(ledger, vec![(-14 * step, 14 * step), (0, 14 * step)]);
+        let (flag, _) = engrave_stems("1/8C6 1/8R 1/4R 1/2R");
+        assert_eq!(flag, vec![(-14 * step, 14 * step)]);
+        let (beam, _) = engrave_stems("1/8C6 1/8C6 1/4R 1/2R");
+        assert_eq!(beam.len(), 2);
+        assert!(beam.iter().all(|stem| *stem == (-14 * step, 14 * step)));
+    }
+
+    #[test]
+    fn beam_line() {
+        // Rising notes, with stems down (away from the highest note).
+        let (stems, _) = engrave_stems("1/8C4 1/8E4 1/8G4 1/8B4 1/2R");
+        assert_eq!(stems.len(), 4);
+        let ends: Vec<i32> = stems.iter().map(|(y, h)| y + h).collect();
+        // The stems all reach one line, slanted by at most a space.
+        assert!(ends.windows(2).all(|w| w[0] >= w[1]));
+        assert!(ends[0] - ends[3] <= Stave::SPACE);
+        assert!(ends[0] > ends[3]);
+        // Every stem is long enough, even where the line is shortest.
+        assert!(stems.iter().all(|(_, h)| *h >= BarElem::STEM_LENGTH_SHORT));
+    }
+
+    #[test]
+    fn beam_direction() {
+        // Notes as far above the middle line as below have stems down,
+        // whichever comes first.
+        let below = 7 * Stave::STEP;
+        for chan in &["1/8C5 1/8C3 3/4R", "1/8C3 1/8C5 3/4R"] {
+            let (stems, _) = engrave_stems(chan);
+            assert_eq!(stems.len(), 2);
+            assert!(stems.iter().all(|(y, h)| y + h > below));
+        }
+        // Otherwise the furthest note decides.
+        let (stems, _) = engrave_stems("1/8C5 1/8B2 3/4R");
+        assert!(stems.iter().all(|(y, _)| *y < -below));
+    }
+}
@@ -0,0 +1,274 @@
+// ScoreFall Ink - Music Composition Software
+//
+// Copyright (C) 2019-2020 Jeron Aldaron Lau <jeronlau@plopgrizzly.com>
+// Copyright (C) 2019-2020 Doug P. Lau
+//
+//     This program is free software: you can redistribute it and/or modify
+//     it under the terms of the GNU General Public License as published by
+//     the Free Software Foundation, either version 3 of the License, or
+//     (at your option) any later version.
+//
+//     This program is distributed in the hope that it will be useful,
+//     but WITHOUT ANY WARRANTY; without even the implied warranty of
+//     MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//     GNU General Public License for more details.
+//
+//     You should have received a copy of the GNU General Public License
+//     along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+/* ************************************************************************** */
+
+//! Lyrics, under the stave.
+//!
+//! Each syllable is centered under its note, and notes are spaced further
+//! apart when syllables would collide.  Hyphens join the syllables of a word,
+//! and an extender line follows a syllable held over several notes.
+
+use crate::{BarElem, Element, Rect, Stave, Text};
+use scof::{Cursor, Scof, Steps, Syllable};
+use sfff::SfFontMetadata;
+
+/// Font size of lyrics.
+const LYRIC_SIZE: i32 = 2 * Stave::SPACE;
+/// Distance from the bottom line of the stave to the baseline of the first
+/// verse.
+const LYRIC_DROP: i32 = 9 * Stave::SPACE / 2;
+/// Distance between the baselines of verses.
+const VERSE_SPACING: i32 = 5 * Stave::SPACE / 2;
+/// Space between syllables (and around hyphens).
+const SYLLABLE_GAP: i32 = Stave::SPACE / 2;
+/// Width of a hyphen.
+const HYPHEN_WIDTH: i32 = 2 * Stave::SPACE / 3;
+
+/// A syllable of lyrics in the bar, and where it's engraved.
+#[derive(Clone, Debug)]
+pub(crate) struct Lyric {
+    /// Syllable (with the notes it's sung on)
+    syllable: Syllable,
+    /// Index of the verse
+    verse: usize,
+    /// Left and right X of the engraved text
+    placed: Option<(i32, i32)>,
+}
+
+impl Lyric {
+    /// Check if the lyric is a hyphen carried from the previous bar.
+    fn is_carried(&self) -> bool {
+        self.syllable.hyphen && self.syllable.text.is_empty()
+    }
+
+    /// Check if another lyric is in the same line (channel and verse).
+    fn same_line(&self, other: &Lyric) -> bool {
+        self.verse == other.verse
+            && self.syllable.start.chan_index()
+                == other.syllable.start.chan_index()
+    }
+}
+
+impl BarElem {
+    /// Set the lyrics of all channels of the bar, to engrave with their notes.
+    pub(crate) fn set_lyrics(&mut self, scof: &Scof, curs: &Cursor) {
+        self.lyrics.clear();
+        for chan in 0..scof.chan_len(curs) {
+            let verses = scof.lyrics(&curs.chan(chan));
+            for (verse, syllables) in verses.into_iter().enumerate() {
+                self.lyrics.extend(syllables.into_iter().map(|syllable| {
+                    Lyric {
+                        syllable,
+                        verse,
+                        placed: None,
+                    }
+                }));
+            }
+        }
+    }
+
+    /// Get the Y position of the baseline of a verse.
+    fn lyric_y(&self, lyric: &Lyric) -> i32 {
+        let ymargin = self.stave.height_steps() + Steps(12);
+        let ofs = ymargin * i32::from(lyric.syllable.start.chan_index());
+        self.bottom_line(ofs) + LYRIC_DROP + lyric.verse as i32 * VERSE_SPACING
+    }
+
+    /// Get the text of a lyric, centered at `x`.
+    fn lyric_text(&self, lyric: &Lyric, x: i32) -> Text {
+        let y = self.lyric_y(lyric);
+        let mut text =
+            Text::new(x, y, LYRIC_SIZE, false, &*lyric.syllable.text);
+        text.x -= text.width() / 2;
+        text
+    }
+
+    /// Add the syllables sung on notes, returning how far the notes must move
+    /// right so that the syllables don't collide with the previous ones.
+    ///
+    /// - `notes`: Cursors of the notes.
+    /// - `x`: X position of the notes.
+    pub(crate) fn add_syllables(&mut self, notes: &[Cursor], x: i32) -> i32 {
+        let center = x + Stave::SPACE / 2;
+        let starting: Vec<usize> = (0..self.lyrics.len())
+            .filter(|i| {
+                let lyric = &self.lyrics[*i];
+                lyric.placed.is_none() && notes.contains(&lyric.syllable.start)
+            })
+            .collect();
+        let mut shift = 0;
+        for &i in &starting {
+            let lyric = &self.lyrics[i];
+            if lyric.syllable.text.is_empty() {
+                continue;
+            }
+            let left = self.lyric_text(lyric, center).x;
+            let previous = self.lyrics[..i]
+                .iter()
+                .rev()
+                .filter(|l| l.same_line(lyric))
+                .find_map(|l| match l.placed {
+                    _ if l.is_carried() => Some((self.width, true)),
+                    Some(p) => Some((p.1, l.syllable.hyphen)),
+                    None => None,
+                });
+            if let Some((right, hyphen)) = previous {
+                let mut need = right + SYLLABLE_GAP;
+                if hyphen {
+                    need += HYPHEN_WIDTH + SYLLABLE_GAP;
+                }
+                shift = shift.max(need - left);
+            }
+        }
+        for i in starting {
+            let text = self.lyric_text(&self.lyrics[i], center + shift);
+            let (left, right) = (text.x, text.x + text.width());
+            self.lyrics[i].placed = Some((left, right));
+            if !text.text.is_empty() {
+                self.elements.push(Element::Text(text));
+            }
+        }
+        shift
+    }
+
+    /// Get the X position of the right edge of the rightmost syllable.
+    pub(crate) fn lyrics_right(&self) -> Option<i32> {
+        self.lyrics
+            .iter()
+            .filter_map(|l| l.placed)
+            .map(|p| p.1)
+            .max()
+    }
+
+    /// Add the hyphens and extender lines of the lyrics in the bar.
+    ///
+    /// - `end`: X position of the end of the bar.
+    pub(crate) fn add_lyric_lines(&mut self, meta: &SfFontMetadata, end: i32) {
+        let t = meta.hairpin_thickness;
+        let lyrics = std::mem::take(&mut self.lyrics);
+        for (i, lyric) in lyrics.iter().enumerate() {
+            let right = match lyric.placed {
+                // A hyphen from the previous bar starts at the bar start.
+                Some(_) if lyric.is_carried() => self.width,
+                Some((_, right)) => right,
+                None => continue,
+            };
+            let y = self.lyric_y(lyric);
+            if lyric.syllable.hyphen {
+                // Centered between the syllables (or before the barline).
+                let next = lyrics[i + 1..]
+                    .iter()
+                    .filter(|l| l.same_line(lyric))
+                    .find_map(|l| l.placed)
+                    .map_or(end, |p| p.0);
+                if next - right >= HYPHEN_WIDTH {
+                    let x = (right + next - HYPHEN_WIDTH) / 2;
+                    let y = y - LYRIC_SIZE / 4;
+                    self.add_lyric_line(x, y - t / 2, HYPHEN_WIDTH, t);
+                }
+            } else if lyric.syllable.is_melisma()
+                || lyric.syllable.text.is_empty()
+            {
+                // Held from the start of the bar, or the end of the syllable.
+                let x = if lyric.syllable.text.is_empty() {
+                    self.width
+                } else {
+                    right + SYLLABLE_GAP / 2
+                };
+                let last =
+                    self.anchor(&lyric.syllable.end).map(|a| a.x + a.width);
+                if let Some(last) = last.filter(|last| *last > x) {
+                    self.add_lyric_line(x, y, last - x, t);
+                }
+            }
+        }
+        self.lyrics = lyrics;
+    }
+
+    /// Add a hyphen or extender line.
+    fn add_lyric_line(&mut self, x: i32, y: i32, width: i32, height: i32) {
+        let rect = Rect::new(x, y, width, height, None, None, None);
+        self.elements.push(Element::Rect(rect));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{engrave_bar, engrave_movement};
+
+    /// Engrave a bar, getting its width, lyric texts and number of lines.
+    fn engrave(chan: &str, lyric: &str) -> (i32, Vec<String>, usize) {
+        let bar = engrave_bar(&format!("{}\n{}", chan, lyric.trim_end()));
+        let texts = bar
+            .elements
+            .iter()
+            .filter_map(|e| match e {
+                Element::Text(t) if t.y > bar.bottom_line(Steps(0)) => {
+                    Some(t.text.clone())
+                }
+                _ => None,
+            })
+            .collect();
+        let lines = bar
+            .elements
+            .iter()
+            .filter(|e| matches!(e, Element::Rect(_)))
+            .count();
+        (bar.width, texts, lines)
+    }
+
+    #[test]
+    fn lyrics() {
+        let notes = "1/8C4 1/8D4 1/8E4 1/8F4 1/2G4";
+        let (width, _, lines) = engrave(notes, "");
+        let (short, texts, hyphens) = engrave(notes, "    lyric: a- b c d e\n");
+        assert_eq!(texts, vec!["a", "b", "c", "d", "e"]);
+        assert_eq!(hyphens, lines + 1);
+        assert_eq!(short, width);
+        // Long syllables push the notes apart.
+        let (long, _, _) =
+            engrave(notes, "    lyric: wwwwww wwwwww wwwwww x y\n");
+        assert!(long > width);
+        // A melisma gets an extender line, and verses get their own line.
+        let (_, texts, extender) =
+            engrave(notes, "    lyric: a _ _ b c | d e\n");
+        assert_eq!(texts, vec!["a", "d", "e", "b", "c"]);
+        assert_eq!(extender, lines + 1);
+    }
+
+    #[test]
+    fn carried_hyphen() {
+        // Count the lines in the second bar, after a word ending the first.
+        let lines = |first: &str| {
+            let muon = format!(
+                "sig: 0\n  time: 2/4\n  tempo: 120\n\
+                 bar:\n  chan: 1/2C4\n    lyric: {}\n\
+                 bar:\n  chan: 1/2D4\n    lyric: ing\n",
+                first
+            );
+            let bar = engrave_movement(&muon, 1);
+            bar.elements
+                .iter()
+                .filter(|e| matches!(e, Element::Rect(_)))
+                .count()
+        };
+        assert_eq!(lines("sing-"), lines("sing") + 1);
+    }
+}
@@ -18,7 +18,7 @@
 
 use std::convert::TryInto;
 
-use scof::{Cursor, Marking, Pitch, Scof};
+use scof::{Articulation, Cursor, Marking, Pitch, Scof, Steps};
 
 /// An iterator over durations of notes in a measure.  Should only output
 /// correct notation.  (Turns 3/8 into dotted 1/4 or 1/4 tied to 1/8 depending
@@ -34,28 +34,126 @@ pub(super) struct Notator<'a> {
     scof: &'a Scof,
     //
     pitch: Vec<Pitch>,
+    // Articulations of the current note, until its first part is engraved
+    articulation: Vec<Articulation>,
+    // Cursor of the current note, until its first part is engraved
+    note: Option<Cursor>,
     // User's cursor
-    cursor: Cursor,
+    cursor: Option<Cursor>,
     // Is User's Cursor
     ic: bool,
+    // Index of the stave (channel) being notated
+    stave: usize,
+    // Stem direction forced by the voice (true is up), if there are multiple
+    stems: Option<bool>,
 }
 
 impl<'a> Notator<'a> {
     /// Create a new `Notator`
-    pub(super) fn new(scof: &'a Scof, cursor: Cursor, curs: Cursor) -> Self {
+    ///
+    /// When a channel has more than one voice, even voices have stems up and
+    /// odd voices have stems down.
+    pub(super) fn new(
+        scof: &'a Scof,
+        cursor: Option<Cursor>,
+        curs: Cursor,
+        stave: usize,
+    ) -> Self {
+        let stems = if scof.voice_len(&curs) > 1 {
+            Some(curs.voice_index().is_multiple_of(2))
+        } else {
+            None
+        };
         Notator {
             curs,
             dur: 0,
             check: 128,
             scof,
             pitch: vec![],
+            articulation: vec![],
+            note: None,
             cursor,
             ic: false,
+            stave,
+            stems,
+        }
+    }
+
+    /// Get the index of the stave being notated.
+    pub(super) fn stave(&self) -> usize {
+        self.stave
+    }
+
+    /// Get the stem direction forced by the voice (true is up).
+    pub(super) fn stems(&self) -> Option<bool> {
+        self.stems
+    }
+
+    /// Check if this is the first voice of the channel.
+    pub(super) fn is_first_voice(&self) -> bool {
+        self.curs.voice_index() == 0
+    }
+
+    /// Get the vertical displacement of rests, to keep them clear of the
+    /// other voice.
+    pub(super) fn rest_offset(&self) -> Steps {
+        match self.stems {
+            Some(true) => Steps(-4),
+            Some(false) => Steps(4),
+            None => Steps(0),
+        }
+    }
+
+    /// Take the articulations of the current note (only the first part of a
+    /// tied note has them).
+    pub(super) fn take_articulation(&mut self) -> Vec<Articulation> {
+        std::mem::take(&mut self.articulation)
+    }
+
+    /// Take the cursor of the current note (only the first part of a tied
+    /// note has spanners attached).
+    pub(super) fn take_note(&mut self) -> Option<Cursor> {
+        self.note.take()
+    }
+
+    /// Take the markings between notes (such as breath marks and dynamics)
+    /// before the next note, if the current note is finished.
+    pub(super) fn take_markings(&mut self) -> Vec<(Cursor, Marking)> {
+        let mut markings = vec![];
+        if self.dur != 0 {
+            return markings;
+        }
+        while let Some(marking) = self.scof.marking(&self.curs) {
+            if let Marking::Note(_) = marking {
+                break;
+            }
+            markings.push((self.curs.clone(), marking.clone()));
+            self.curs.right_unchecked();
+        }
+        markings
+    }
+
+    /// Get the cursor of the note about to be engraved, if a note starts
+    /// next (rather than the tied part of a note).
+    pub(super) fn peek_note(&self) -> Option<Cursor> {
+        if self.note.is_some() {
+            return self.note.clone();
+        }
+        if self.dur != 0 {
+            return None;
+        }
+        let mut curs = self.curs.clone();
+        while let Some(marking) = self.scof.marking(&curs) {
+            if let Marking::Note(_) = marking {
+                return Some(curs);
+            }
+            curs.right_unchecked();
         }
+        None
     }
 
     pub(super) fn is_cursor(&self) -> bool {
-        self.curs == self.cursor
+        self.cursor.as_ref() == Some(&self.curs)
     }
 }
 
@@ -72,19 +170,19 @@ impl<'a> Iterator for Notator<'a> {
             self.check /= 2;
         }
         // Get next note/rest, return None if done.
-        match self.scof.marking(&self.curs)? {
-            Marking::Note(note) => {
-                self.ic = self.curs == self.cursor;
-                self.check = 128;
-                // FIXME: Tuplets (test for not divisible by 128)
-                self.dur = ((note.duration.num as u32 * 128)
-                    / note.duration.den as u32)
-                    .try_into()
-                    .unwrap();
-                self.pitch = note.pitch.clone();
-            }
-            _ => unreachable!(),
-        };
+        // Markings between notes are skipped (taken before the note).
+        if let Marking::Note(note) = self.scof.marking(&self.curs)? {
+            self.ic = self.is_cursor();
+            self.check = 128;
+            // FIXME: Tuplets (test for not divisible by 128)
+            self.dur = ((note.duration.num as u32 * 128)
+                / note.duration.den as u32)
+                .try_into()
+                .unwrap();
+            self.pitch = note.pitch.clone();
+            self.articulation = note.articulation.clone();
+            self.note = Some(self.curs.clone());
+        }
         self.curs.right_unchecked();
         <Self as Iterator>::next(self)
     }
@@ -0,0 +1,199 @@
+// ScoreFall Ink - Music Composition Software
+//
+// Copyright (C) 2019-2020 Jeron Aldaron Lau <jeronlau@plopgrizzly.com>
+// Copyright (C) 2019-2020 Doug P. Lau
+//
+//     This program is free software: you can redistribute it and/or modify
+//     it under the terms of the GNU General Public License as published by
+//     the Free Software Foundation, either version 3 of the License, or
+//     (at your option) any later version.
+//
+//     This program is distributed in the hope that it will be useful,
+//     but WITHOUT ANY WARRANTY; without even the implied warranty of
+//     MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//     GNU General Public License for more details.
+//
+//     You should have received a copy of the GNU General Public License
+//     along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+/* ************************************************************************** */
+
+//! Ornaments: trills, turns, tremelos and arpeggios.
+//!
+//! Trills and turns go above the note (and its stem), with a trill extended
+//! by a wavy line to the next note of the voice.  Tremelo slashes go across
+//! the stem, and an arpeggio wiggle runs up the left of the chord.
+
+use crate::notehead::{self, Notehead};
+use crate::spanner::Anchor;
+use crate::{BarElem, Stave};
+use scof::Articulation;
+use sfff::{Glyph, SfFontMetadata};
+
+/// Width of a trill or turn glyph.
+const ORNAMENT_WIDTH: i32 = 3 * Stave::SPACE / 2;
+/// Width of a segment of a trill extension line.
+const TRILL_WIGGLE_WIDTH: i32 = 3 * Stave::SPACE / 4;
+/// Width of the tremelo slashes.
+const TREMELO_WIDTH: i32 = Stave::SPACE;
+/// Height of a segment of an arpeggio wiggle.
+const ARPEGGIO_WIGGLE_HEIGHT: i32 = Stave::SPACE;
+/// Space between an arpeggio wiggle and the noteheads.
+const ARPEGGIO_GAP: i32 = Stave::SPACE;
+
+/// Get the glyph for tremelo slashes.
+fn tremelo(slashes: u8) -> Glyph {
+    match slashes {
+        1 => Glyph::Tremelo1,
+        2 => Glyph::Tremelo2,
+        3 => Glyph::Tremelo3,
+        4 => Glyph::Tremelo4,
+        _ => Glyph::Tremelo5,
+    }
+}
+
+impl BarElem {
+    /// Add the ornaments of an engraved note (or chord).
+    pub(crate) fn add_ornaments(
+        &mut self,
+        meta: &SfFontMetadata,
+        anchor: &Anchor,
+        articulations: &[Articulation],
+        dur: u16,
+    ) {
+        // Has a stem if not a whole note or longer.
+        let stem = dur < 128;
+        for articulation in articulations {
+            match articulation {
+                Articulation::Trill => {
+                    let (x, y) = self.ornament_position(anchor, stem);
+                    self.add_use(Glyph::Trill, x, y);
+                    let start = x + ORNAMENT_WIDTH;
+                    self.trills.push((anchor.cursor.clone(), start, y));
+                }
+                Articulation::Turn | Articulation::TurnInverted => {
+                    let glyph = if *articulation == Articulation::Turn {
+                        Glyph::Turn
+                    } else {
+                        Glyph::TurnInverted
+                    };
+                    let (x, y) = self.ornament_position(anchor, stem);
+                    self.add_use(glyph, x, y);
+                }
+                Articulation::Tremelo(slashes) => {
+                    let (x, y) = if !stem {
+                        (anchor.center(), anchor.high - 2 * Stave::SPACE)
+                    } else if anchor.up {
+                        let [_, right] =
+                            notehead::stems(Notehead::Normal, meta, dur);
+                        let x = anchor.x + right[0] - meta.stem_thickness / 2;
+                        (x, anchor.high - Self::STEM_LENGTH / 2)
+                    } else {
+                        let [left, _] =
+                            notehead::stems(Notehead::Normal, meta, dur);
+                        let x = anchor.x + left[0] + meta.stem_thickness / 2;
+                        (x, anchor.low + Self::STEM_LENGTH / 2)
+                    };
+                    self.add_use(tremelo(*slashes), x - TREMELO_WIDTH / 2, y);
+                }
+                Articulation::Fermata => {
+                    let (_, y) = self.ornament_position(anchor, stem);
+                    self.add_fermata(anchor.center(), anchor.ofs, Some(y));
+                }
+                Articulation::StrumDown | Articulation::StrumUp => {
+                    let up = *articulation == Articulation::StrumDown;
+                    self.add_arpeggio(anchor, up);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Get the position of a trill or turn glyph, above the stave, the note
+    /// and its stem.
+    fn ornament_position(&self, anchor: &Anchor, stem: bool) -> (i32, i32) {
+        let top = self.y_from_steps(self.stave.steps_middle_c, anchor.ofs);
+        let mut y = (anchor.high - 2 * Stave::SPACE).min(top - Stave::SPACE);
+        if stem && anchor.up {
+            y = y.min(anchor.high - Self::STEM_LENGTH - Stave::SPACE);
+        }
+        (anchor.center() - ORNAMENT_WIDTH / 2, y)
+    }
+
+    /// Add an arpeggio wiggle to the left of a chord, with an arrow pointing
+    /// up or down.
+    fn add_arpeggio(&mut self, anchor: &Anchor, up: bool) {
+        let x = anchor.x - ARPEGGIO_GAP;
+        let top = anchor.high - Stave::STEP;
+        let mut y = anchor.low + Stave::STEP;
+        if !up {
+            self.add_use(Glyph::ArpeggioDown, x, y);
+        }
+        while y > top {
+            self.add_use(Glyph::ArpeggioWiggle, x, y);
+            y -= ARPEGGIO_WIGGLE_HEIGHT;
+        }
+        if up {
+            self.add_use(Glyph::ArpeggioUp, x, y);
+        }
+    }
+
+    /// Add the extension lines of trills in the bar, each to the next note
+    /// of its voice (or the end of the bar).
+    ///
+    /// - `end`: X position of the end of the bar.
+    pub(crate) fn add_trill_lines(&mut self, end: i32) {
+        for (cursor, start, y) in std::mem::take(&mut self.trills) {
+            let next = self
+                .anchors
+                .iter()
+                .filter(|a| {
+                    a.cursor.chan_index() == cursor.chan_index()
+                        && a.cursor.voice_index() == cursor.voice_index()
+                        && a.cursor.marking_index() > cursor.marking_index()
+                })
+                .map(|a| a.x)
+                .min();
+            let end = next.unwrap_or(end) - Stave::SPACE / 2;
+            let mut x = start;
+            while x + TRILL_WIGGLE_WIDTH <= end {
+                self.add_use(Glyph::TrillWiggle, x, y);
+                x += TRILL_WIGGLE_WIDTH;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{count, engrave_bar, glyph_ids};
+
+    /// Get the glyph IDs used in a bar.
+    fn glyphs(chan: &str) -> Vec<u16> {
+        glyph_ids(&engrave_bar(chan))
+    }
+
+    #[test]
+    fn trills() {
+        let bar = glyphs("1/2C4~ 1/2D4s");
+        assert_eq!(count(&bar, Glyph::Trill), 1);
+        assert_eq!(count(&bar, Glyph::Turn), 1);
+        // The extension line runs to the next note.
+        assert!(count(&bar, Glyph::TrillWiggle) > 2);
+        let short = glyphs("1/8C4~ 1/8D4 3/4R");
+        assert!(
+            count(&short, Glyph::TrillWiggle) < count(&bar, Glyph::TrillWiggle)
+        );
+    }
+
+    #[test]
+    fn tremelos_and_arpeggios() {
+        let bar = glyphs("1/4C4=3 1/4D4=1 1/2C4E4G4M");
+        assert_eq!(count(&bar, Glyph::Tremelo3), 1);
+        assert_eq!(count(&bar, Glyph::Tremelo1), 1);
+        assert_eq!(count(&bar, Glyph::ArpeggioWiggle), 3);
+        assert_eq!(count(&bar, Glyph::ArpeggioUp), 1);
+        assert_eq!(count(&bar, Glyph::ArpeggioDown), 0);
+    }
+}
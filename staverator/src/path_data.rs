@@ -0,0 +1,326 @@
+// ScoreFall Ink - Music Composition Software
+//
+// Copyright (C) 2019-2020 Jeron Aldaron Lau <jeronlau@plopgrizzly.com>
+// Copyright (C) 2019-2020 Doug P. Lau
+//
+//     This program is free software: you can redistribute it and/or modify
+//     it under the terms of the GNU General Public License as published by
+//     the Free Software Foundation, either version 3 of the License, or
+//     (at your option) any later version.
+//
+//     This program is distributed in the hope that it will be useful,
+//     but WITHOUT ANY WARRANTY; without even the implied warranty of
+//     MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//     GNU General Public License for more details.
+//
+//     You should have received a copy of the GNU General Public License
+//     along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+/* ************************************************************************** */
+
+//! Parse SVG path data (the `d` attribute) into absolute path operations, for
+//! the backends that don't understand SVG.
+
+/// An absolute path operation.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(crate) enum PathOp {
+    /// Start a new subpath
+    Move(f32, f32),
+    /// Straight line
+    Line(f32, f32),
+    /// Cubic bézier curve (2 control points and end point)
+    Cubic(f32, f32, f32, f32, f32, f32),
+    /// Close the subpath
+    Close,
+}
+
+impl PathOp {
+    /// Scale the operation about the origin.
+    pub(crate) fn scale(self, s: f32) -> Self {
+        use PathOp::*;
+        match self {
+            Move(x, y) => Move(x * s, y * s),
+            Line(x, y) => Line(x * s, y * s),
+            Cubic(ax, ay, bx, by, x, y) => {
+                Cubic(ax * s, ay * s, bx * s, by * s, x * s, y * s)
+            }
+            Close => Close,
+        }
+    }
+}
+
+/// Tokenizer for numbers in path data.
+struct Numbers<'a> {
+    d: &'a [u8],
+    i: usize,
+}
+
+impl<'a> Numbers<'a> {
+    /// Skip whitespace and commas
+    fn skip(&mut self) {
+        while let Some(c) = self.d.get(self.i) {
+            if c.is_ascii_whitespace() || *c == b',' {
+                self.i += 1;
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// Get the next command letter, if there is one.
+    fn command(&mut self) -> Option<u8> {
+        self.skip();
+        let c = *self.d.get(self.i)?;
+        if c.is_ascii_alphabetic() && c != b'e' && c != b'E' {
+            self.i += 1;
+            Some(c)
+        } else {
+            None
+        }
+    }
+
+    /// Check if there is another number before the next command.
+    fn has_number(&mut self) -> bool {
+        self.skip();
+        match self.d.get(self.i) {
+            Some(c) => c.is_ascii_digit() || b"+-.".contains(c),
+            None => false,
+        }
+    }
+
+    /// Get the next number.
+    fn number(&mut self) -> Option<f32> {
+        self.skip();
+        let start = self.i;
+        let mut dot = false;
+        let mut exp = false;
+        while let Some(c) = self.d.get(self.i) {
+            let first = self.i == start;
+            match c {
+                b'+' | b'-' if first => {}
+                b'+' | b'-' if matches!(self.d[self.i - 1], b'e' | b'E') => {}
+                b'.' if !dot && !exp => dot = true,
+                b'e' | b'E' if !exp && !first => exp = true,
+                b'0'..=b'9' => {}
+                _ => break,
+            }
+            self.i += 1;
+        }
+        std::str::from_utf8(&self.d[start..self.i])
+            .ok()?
+            .parse()
+            .ok()
+    }
+
+    /// Get the next pair of numbers.
+    fn pair(&mut self) -> Option<(f32, f32)> {
+        Some((self.number()?, self.number()?))
+    }
+}
+
+/// Parse SVG path data into absolute path operations.  Parsing stops at the
+/// first error.
+pub(crate) fn parse(d: &str) -> Vec<PathOp> {
+    let mut ops = vec![];
+    let mut nums = Numbers {
+        d: d.as_bytes(),
+        i: 0,
+    };
+    // Current point, subpath start, and last control point.
+    let (mut x, mut y) = (0.0, 0.0);
+    let (mut sx, mut sy) = (0.0, 0.0);
+    let mut ctrl: Option<(f32, f32)> = None;
+    let mut quad: Option<(f32, f32)> = None;
+
+    'commands: while let Some(cmd) = nums.command() {
+        let rel = cmd.is_ascii_lowercase();
+        let upper = cmd.to_ascii_uppercase();
+        let (ox, oy) = if rel { (x, y) } else { (0.0, 0.0) };
+        let mut first = true;
+        loop {
+            let (mut next_ctrl, mut next_quad) = (None, None);
+            match upper {
+                b'Z' => {
+                    ops.push(PathOp::Close);
+                    x = sx;
+                    y = sy;
+                    ctrl = None;
+                    quad = None;
+                    continue 'commands;
+                }
+                b'M' if first => {
+                    let (px, py) = match nums.pair() {
+                        Some(p) => p,
+                        None => break 'commands,
+                    };
+                    x = ox + px;
+                    y = oy + py;
+                    sx = x;
+                    sy = y;
+                    ops.push(PathOp::Move(x, y));
+                }
+                b'M' | b'L' => {
+                    let (px, py) = match nums.pair() {
+                        Some(p) => p,
+                        None => break 'commands,
+                    };
+                    x = if rel { x + px } else { px };
+                    y = if rel { y + py } else { py };
+                    ops.push(PathOp::Line(x, y));
+                }
+                b'H' => {
+                    let px = match nums.number() {
+                        Some(p) => p,
+                        None => break 'commands,
+                    };
+                    x = if rel { x + px } else { px };
+                    ops.push(PathOp::Line(x, y));
+                }
+                b'V' => {
+                    let py = match nums.number() {
+                        Some(p) => p,
+                        None => break 'commands,
+                    };
+                    y = if rel { y + py } else { py };
+                    ops.push(PathOp::Line(x, y));
+                }
+                b'C' | b'S' => {
+                    let (ax, ay) = if upper == b'S' {
+                        // Reflect last control point.
+                        match ctrl {
+                            Some((cx, cy)) => (2.0 * x - cx, 2.0 * y - cy),
+                            None => (x, y),
+                        }
+                    } else {
+                        match nums.pair() {
+                            Some((px, py)) if rel => (x + px, y + py),
+                            Some(p) => p,
+                            None => break 'commands,
+                        }
+                    };
+                    let (b, e) = match (nums.pair(), nums.pair()) {
+                        (Some(b), Some(e)) => (b, e),
+                        _ => break 'commands,
+                    };
+                    let (ox, oy) = if rel { (x, y) } else { (0.0, 0.0) };
+                    let (bx, by) = (ox + b.0, oy + b.1);
+                    x = ox + e.0;
+                    y = oy + e.1;
+                    ops.push(PathOp::Cubic(ax, ay, bx, by, x, y));
+                    next_ctrl = Some((bx, by));
+                }
+                b'Q' | b'T' => {
+                    let (qx, qy) = if upper == b'T' {
+                        match quad {
+                            Some((cx, cy)) => (2.0 * x - cx, 2.0 * y - cy),
+                            None => (x, y),
+                        }
+                    } else {
+                        match nums.pair() {
+                            Some((px, py)) if rel => (x + px, y + py),
+                            Some(p) => p,
+                            None => break 'commands,
+                        }
+                    };
+                    let (ex, ey) = match nums.pair() {
+                        Some((px, py)) if rel => (x + px, y + py),
+                        Some(p) => p,
+                        None => break 'commands,
+                    };
+                    // Convert quadratic to cubic.
+                    ops.push(PathOp::Cubic(
+                        x + 2.0 / 3.0 * (qx - x),
+                        y + 2.0 / 3.0 * (qy - y),
+                        ex + 2.0 / 3.0 * (qx - ex),
+                        ey + 2.0 / 3.0 * (qy - ey),
+                        ex,
+                        ey,
+                    ));
+                    x = ex;
+                    y = ey;
+                    next_quad = Some((qx, qy));
+                }
+                _ => break 'commands, // Arcs are not used by fonts.
+            }
+            ctrl = next_ctrl;
+            quad = next_quad;
+            first = false;
+            if !nums.has_number() {
+                break;
+            }
+        }
+    }
+    ops
+}
+
+/// Get the bounding box of path operations (including control points) as
+/// `[x_min, y_min, x_max, y_max]`.
+pub(crate) fn bounds(ops: &[PathOp]) -> [f32; 4] {
+    let mut b = [f32::MAX, f32::MAX, f32::MIN, f32::MIN];
+    let mut add = |x: f32, y: f32| {
+        b[0] = b[0].min(x);
+        b[1] = b[1].min(y);
+        b[2] = b[2].max(x);
+        b[3] = b[3].max(y);
+    };
+    for op in ops {
+        match *op {
+            PathOp::Move(x, y) | PathOp::Line(x, y) => add(x, y),
+            PathOp::Cubic(ax, ay, bx, by, x, y) => {
+                add(ax, ay);
+                add(bx, by);
+                add(x, y);
+            }
+            PathOp::Close => {}
+        }
+    }
+    if ops.is_empty() {
+        [0.0; 4]
+    } else {
+        b
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use PathOp::*;
+
+    #[test]
+    fn rect() {
+        assert_eq!(
+            parse("M10 20h30v-5h-30v5z"),
+            vec![
+                Move(10.0, 20.0),
+                Line(40.0, 20.0),
+                Line(40.0, 15.0),
+                Line(10.0, 15.0),
+                Line(10.0, 20.0),
+                Close,
+            ]
+        );
+    }
+
+    #[test]
+    fn relative_curves() {
+        assert_eq!(
+            parse("M 470 -943 c -61 0 -133 96 -133 252 l 2 -2 z m 1 1"),
+            vec![
+                Move(470.0, -943.0),
+                Cubic(409.0, -943.0, 337.0, -847.0, 337.0, -691.0),
+                Line(339.0, -693.0),
+                Close,
+                Move(471.0, -942.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn implicit_lines() {
+        assert_eq!(
+            parse("M0,0 10,0 10-10Z"),
+            vec![Move(0.0, 0.0), Line(10.0, 0.0), Line(10.0, -10.0), Close]
+        );
+        assert_eq!(bounds(&parse("M0,0 10,0 10-10Z")), [0.0, -10.0, 10.0, 0.0]);
+    }
+}
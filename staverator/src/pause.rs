@@ -0,0 +1,138 @@
+// ScoreFall Ink - Music Composition Software
+//
+// Copyright (C) 2019-2020 Jeron Aldaron Lau <jeronlau@plopgrizzly.com>
+// Copyright (C) 2019-2020 Doug P. Lau
+//
+//     This program is free software: you can redistribute it and/or modify
+//     it under the terms of the GNU General Public License as published by
+//     the Free Software Foundation, either version 3 of the License, or
+//     (at your option) any later version.
+//
+//     This program is distributed in the hope that it will be useful,
+//     but WITHOUT ANY WARRANTY; without even the implied warranty of
+//     MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//     GNU General Public License for more details.
+//
+//     You should have received a copy of the GNU General Public License
+//     along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+/* ************************************************************************** */
+
+//! Pauses: fermatas, breath marks and caesuras.
+//!
+//! A breath mark goes above the stave it's written in, while caesuras and
+//! fermatas apply to every stave, so they're engraved on all of them.
+
+use crate::{BarElem, Stave};
+use scof::{Cursor, Marking, Steps};
+use sfff::Glyph;
+
+/// Width of a fermata glyph.
+const FERMATA_WIDTH: i32 = 2 * Stave::SPACE;
+/// Room to leave for a breath mark.
+const BREATH_WIDTH: i32 = Stave::SPACE;
+/// Room to leave for a caesura.
+const CAESURA_WIDTH: i32 = 2 * Stave::SPACE;
+
+impl BarElem {
+    /// Add the breath marks and caesuras before the next notes, returning
+    /// the room needed for them.
+    ///
+    /// - `markings`: Markings between notes, with their stave offsets.
+    /// - `staves`: Number of staves.
+    pub(crate) fn add_pauses(
+        &mut self,
+        markings: &[(Cursor, Marking, Steps)],
+        x: i32,
+        staves: i32,
+        ymargin: Steps,
+    ) -> i32 {
+        let mut width = 0;
+        let mut breaths: Vec<Steps> = vec![];
+        let mut long = None;
+        for (_, marking, ofs) in markings {
+            match marking {
+                Marking::Breath if !breaths.contains(ofs) => breaths.push(*ofs),
+                Marking::CaesuraShort => long = long.or(Some(false)),
+                Marking::CaesuraLong => long = Some(true),
+                _ => {}
+            }
+        }
+        for ofs in breaths {
+            let y = self.top_line(ofs) - Stave::SPACE;
+            self.add_use(Glyph::Breath, x, y);
+            width = BREATH_WIDTH;
+        }
+        if let Some(long) = long {
+            for i in 0..staves {
+                let y = self.top_line(ymargin * i);
+                let glyph = if long {
+                    Glyph::CaesuraLong
+                } else {
+                    Glyph::CaesuraShort
+                };
+                self.add_use(glyph, x, y);
+            }
+            width = CAESURA_WIDTH;
+        }
+        width
+    }
+
+    /// Add a fermata over a note or rest (to be engraved on every stave).
+    ///
+    /// - `x`: X position of the center of the note.
+    /// - `y`: Y position of the fermata, or `None` for above the stave.
+    pub(crate) fn add_fermata(&mut self, x: i32, ofs: Steps, y: Option<i32>) {
+        let top = self.top_line(ofs) - Stave::SPACE;
+        let y = y.map_or(top, |y| y.min(top));
+        self.fermatas.push((x, ofs, y));
+    }
+
+    /// Engrave the fermatas of the bar on every stave.
+    ///
+    /// - `staves`: Number of staves.
+    pub(crate) fn add_fermatas(&mut self, staves: i32, ymargin: Steps) {
+        let fermatas = std::mem::take(&mut self.fermatas);
+        let mut xs: Vec<i32> = fermatas.iter().map(|f| f.0).collect();
+        xs.sort_unstable();
+        xs.dedup_by(|x, first| *x - *first < Stave::SPACE);
+        for x in xs {
+            for i in 0..staves {
+                let ofs = ymargin * i;
+                let y = fermatas
+                    .iter()
+                    .filter(|f| f.1 == ofs && (f.0 - x).abs() < Stave::SPACE)
+                    .map(|f| f.2)
+                    .min()
+                    .unwrap_or_else(|| self.top_line(ofs) - Stave::SPACE);
+                self.add_use(Glyph::Fermata, x - FERMATA_WIDTH / 2, y);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::{count, engrave_bar, glyph_ids};
+    use sfff::Glyph;
+
+    /// Engrave a bar, getting its width and glyph IDs.
+    fn engrave(chan: &str) -> (i32, Vec<u16>) {
+        let bar = engrave_bar(chan);
+        (bar.width, glyph_ids(&bar))
+    }
+
+    #[test]
+    fn pauses() {
+        let (width, plain) = engrave("1/2C4 1/2D4");
+        let (breath, glyphs) = engrave("1/2C4 ` 1/2D4");
+        assert_eq!(count(&plain, Glyph::Breath), 0);
+        assert_eq!(count(&glyphs, Glyph::Breath), 1);
+        assert!(breath > width);
+        let (caesura, glyphs) = engrave("1/2C4 // 1/2D4");
+        assert_eq!(count(&glyphs, Glyph::CaesuraLong), 1);
+        assert!(caesura > breath);
+        let (_, glyphs) = engrave("1/2C4$ 1/2R$");
+        assert_eq!(count(&glyphs, Glyph::Fermata), 2);
+    }
+}
@@ -0,0 +1,523 @@
+// ScoreFall Ink - Music Composition Software
+//
+// Copyright (C) 2019-2020 Jeron Aldaron Lau <jeronlau@plopgrizzly.com>
+// Copyright (C) 2019-2020 Doug P. Lau
+//
+//     This program is free software: you can redistribute it and/or modify
+//     it under the terms of the GNU General Public License as published by
+//     the Free Software Foundation, either version 3 of the License, or
+//     (at your option) any later version.
+//
+//     This program is distributed in the hope that it will be useful,
+//     but WITHOUT ANY WARRANTY; without even the implied warranty of
+//     MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//     GNU General Public License for more details.
+//
+//     You should have received a copy of the GNU General Public License
+//     along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+/* ************************************************************************** */
+
+//! Render a score into a multi-page PDF document.  Glyphs are embedded once as
+//! form XObjects, and used by each page's content stream.
+
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+use crate::layout::{layout, LayoutOptions};
+use crate::path_data::{self, PathOp};
+use crate::{Element, STAVE_SPACE};
+use scof::Scof;
+use sfff::SfFontMetadata;
+
+/// Size of the title block on the first page (in stave spaces).
+const TITLE_HEIGHT: i32 = 16;
+
+/// Paper size (in points, 1/72 inch).
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PaperSize {
+    /// Width of the paper
+    pub width: f32,
+    /// Height of the paper
+    pub height: f32,
+}
+
+impl PaperSize {
+    /// ISO A4 paper
+    pub const A4: PaperSize = PaperSize {
+        width: 595.28,
+        height: 841.89,
+    };
+    /// US Letter paper
+    pub const LETTER: PaperSize = PaperSize {
+        width: 612.0,
+        height: 792.0,
+    };
+}
+
+/// Options for rendering a score to a PDF document.
+#[derive(Clone, Debug)]
+pub struct PdfOptions {
+    /// Index of the movement to render.
+    pub movement: u16,
+    /// Paper size.
+    pub paper: PaperSize,
+    /// Margin on each side of the page (in points).
+    pub margin: f32,
+    /// Size of one stave space (in points).
+    pub stave_space: f32,
+}
+
+impl Default for PdfOptions {
+    fn default() -> Self {
+        PdfOptions {
+            movement: 0,
+            paper: PaperSize::A4,
+            margin: 36.0,
+            stave_space: 4.96, // 1.75mm
+        }
+    }
+}
+
+/// Render a movement of the score as a PDF document.
+///
+/// - `glyph_paths`: The SVG paths of the font's glyphs, separated by null
+///   bytes (see [`modern_glyphs`]).
+///
+/// [`modern_glyphs`]: crate::modern_glyphs
+pub fn render_pdf(
+    scof: &Scof,
+    meta: &SfFontMetadata,
+    glyph_paths: &str,
+    options: &PdfOptions,
+) -> Vec<u8> {
+    // Points per font unit.
+    let scale = options.stave_space / STAVE_SPACE as f32;
+    let layout_options = LayoutOptions {
+        movement: options.movement,
+        page_width: (options.paper.width / scale) as i32,
+        page_height: (options.paper.height / scale) as i32,
+        margin: (options.margin / scale) as i32,
+        header: TITLE_HEIGHT * STAVE_SPACE,
+        ..Default::default()
+    };
+    let pages = layout(scof, meta, &layout_options);
+
+    // Find glyphs that are used.
+    let mut used = BTreeSet::new();
+    for page in &pages {
+        for elem in &page.group.elements {
+            used_glyphs(elem, &mut used);
+        }
+    }
+    let glyphs: Vec<&str> = glyph_paths.split('\0').collect();
+
+    let mut pdf = PdfWriter::new();
+    // Object IDs
+    let catalog = 1;
+    let page_tree = 2;
+    let font = 3;
+    let font_bold = 4;
+    let font_italic = 5;
+    let first_glyph = 6;
+    let first_page = first_glyph + used.len();
+
+    pdf.object(
+        catalog,
+        &format!("<< /Type /Catalog /Pages {} 0 R >>", page_tree),
+    );
+    let kids: Vec<String> = (0..pages.len())
+        .map(|i| format!("{} 0 R", first_page + 2 * i))
+        .collect();
+    pdf.object(
+        page_tree,
+        &format!(
+            "<< /Type /Pages /Kids [{}] /Count {} >>",
+            kids.join(" "),
+            pages.len()
+        ),
+    );
+    pdf.object(font, &font_dict("Times-Roman"));
+    pdf.object(font_bold, &font_dict("Times-Bold"));
+    pdf.object(font_italic, &font_dict("Times-Italic"));
+
+    // Glyphs as form XObjects.
+    let mut xobjects = String::new();
+    for (i, id) in used.iter().enumerate() {
+        let ops = path_data::parse(glyphs.get(*id as usize).unwrap_or(&""));
+        let [x0, y0, x1, y1] = path_data::bounds(&ops);
+        let mut stream = String::new();
+        fill_path(&mut stream, &ops);
+        let dict = format!(
+            "/Type /XObject /Subtype /Form /BBox [{} {} {} {}]",
+            x0.floor(),
+            y0.floor(),
+            x1.ceil(),
+            y1.ceil()
+        );
+        pdf.stream(first_glyph + i, &dict, stream.as_bytes());
+        let _ = write!(xobjects, " /G{:x} {} 0 R", id, first_glyph + i);
+    }
+    let resources = format!(
+        "<< /Font << /F1 {} 0 R /F2 {} 0 R /F3 {} 0 R >> \
+         /XObject <<{} >> >>",
+        font, font_bold, font_italic, xobjects
+    );
+
+    // Pages
+    let (width, height) = (options.paper.width, options.paper.height);
+    for (i, page) in pages.iter().enumerate() {
+        let mut stream = String::new();
+        // Flip the Y axis, and scale font units to points.
+        let _ = writeln!(stream, "q {} 0 0 {} 0 {} cm", scale, -scale, height);
+        for elem in &page.group.elements {
+            element(&mut stream, elem);
+        }
+        stream.push_str("Q\n");
+        if i == 0 {
+            title_block(&mut stream, scof, options);
+        } else {
+            let number = (i + 1).to_string();
+            text(
+                &mut stream,
+                1,
+                10.0,
+                width / 2.0,
+                options.margin / 2.0,
+                &number,
+                0.5,
+            );
+        }
+
+        let page_id = first_page + 2 * i;
+        pdf.object(
+            page_id,
+            &format!(
+                "<< /Type /Page /Parent {} 0 R /MediaBox [0 0 {} {}] \
+                 /Resources {} /Contents {} 0 R >>",
+                page_tree,
+                width,
+                height,
+                resources,
+                page_id + 1
+            ),
+        );
+        pdf.stream(page_id + 1, "", stream.as_bytes());
+    }
+
+    pdf.finish(catalog)
+}
+
+/// Get the dictionary for one of the standard fonts.
+fn font_dict(name: &str) -> String {
+    format!(
+        "<< /Type /Font /Subtype /Type1 /BaseFont /{} \
+         /Encoding /WinAnsiEncoding >>",
+        name
+    )
+}
+
+/// Find glyph IDs used by an element.
+fn used_glyphs(elem: &Element, used: &mut BTreeSet<u16>) {
+    match elem {
+        Element::Use(u) => {
+            used.insert(u.id);
+        }
+        Element::Group(g) => {
+            for e in &g.elements {
+                used_glyphs(e, used);
+            }
+        }
+        Element::Rect(_) | Element::Path(_) | Element::Text(_) => {}
+    }
+}
+
+/// Write path operations, filling the path (nonzero winding rule).
+fn fill_path(out: &mut String, ops: &[PathOp]) {
+    for op in ops {
+        let _ = match *op {
+            PathOp::Move(x, y) => writeln!(out, "{} {} m", x, y),
+            PathOp::Line(x, y) => writeln!(out, "{} {} l", x, y),
+            PathOp::Cubic(ax, ay, bx, by, x, y) => {
+                writeln!(out, "{} {} {} {} {} {} c", ax, ay, bx, by, x, y)
+            }
+            PathOp::Close => writeln!(out, "h"),
+        };
+    }
+    if !ops.is_empty() {
+        out.push_str("f\n");
+    }
+}
+
+/// Write the drawing operations for an element.
+fn element(out: &mut String, elem: &Element) {
+    let _ = match elem {
+        Element::Rect(r) => {
+            if let Some([red, green, blue]) = r.fill_rgb() {
+                writeln!(
+                    out,
+                    "q {} {} {} rg {} {} {} {} re f Q",
+                    f32::from(red) / 255.0,
+                    f32::from(green) / 255.0,
+                    f32::from(blue) / 255.0,
+                    r.x,
+                    r.y,
+                    r.width,
+                    r.height
+                )
+            } else {
+                writeln!(out, "{} {} {} {} re f", r.x, r.y, r.width, r.height)
+            }
+        }
+        Element::Use(u) => writeln!(
+            out,
+            "q {} 0 0 {} {} {} cm /G{:x} Do Q",
+            u.scale, u.scale, u.x, u.y, u.id
+        ),
+        Element::Path(p) => {
+            fill_path(out, &path_data::parse(&p.d));
+            Ok(())
+        }
+        Element::Text(t) => {
+            // Flip the text back up, within the flipped page.
+            let font = if t.italic { 3 } else { 1 };
+            let _ = write!(
+                out,
+                "BT /F{} {} Tf 1 0 0 -1 {} {} Tm (",
+                font, t.size, t.x, t.y
+            );
+            escape(out, &t.text);
+            writeln!(out, ") Tj ET")
+        }
+        Element::Group(g) => {
+            let _ = writeln!(out, "q 1 0 0 1 {} {} cm", g.x, g.y);
+            for e in &g.elements {
+                element(out, e);
+            }
+            writeln!(out, "Q")
+        }
+    };
+}
+
+/// Write the title, subtitle and credits at the top of the first page.
+fn title_block(out: &mut String, scof: &Scof, options: &PdfOptions) {
+    let meta = &scof.meta;
+    let left = options.margin;
+    let right = options.paper.width - options.margin;
+    let center = options.paper.width / 2.0;
+    let mut y = options.paper.height - options.margin - 24.0;
+
+    text(out, 2, 24.0, center, y, &scof.title, 0.5);
+    if let Some(part) = &meta.part {
+        text(out, 1, 14.0, left, y, part, 0.0);
+    }
+    if let Some(subtitle) = &meta.subtitle {
+        y -= 20.0;
+        text(out, 1, 14.0, center, y, subtitle, 0.5);
+    }
+    y -= 24.0;
+    text(out, 1, 11.0, right, y, &meta.composer, 1.0);
+    if let Some(lyricist) = &meta.lyricist {
+        text(
+            out,
+            1,
+            11.0,
+            left,
+            y,
+            &format!("Words by {}", lyricist),
+            0.0,
+        );
+    }
+    for arranger in &meta.arranger {
+        y -= 13.0;
+        let credit = match &arranger.ensemble {
+            Some(ensemble) => {
+                format!("Arranged for {} by {}", ensemble, arranger.name)
+            }
+            None => format!("Arranged by {}", arranger.name),
+        };
+        text(out, 1, 11.0, right, y, &credit, 1.0);
+    }
+    if let Some(translator) = &meta.translator {
+        y -= 13.0;
+        let credit = format!("Translated by {}", translator);
+        text(out, 1, 11.0, left, y, &credit, 0.0);
+    }
+    // Licenses at the bottom of the first page.
+    let mut y = options.margin / 2.0;
+    for license in meta.licenses.iter().rev() {
+        text(out, 1, 8.0, center, y, license, 0.5);
+        y += 10.0;
+    }
+}
+
+/// Write a line of text.  `align` is 0.0 for left, 0.5 for center, and 1.0
+/// for right aligned text.
+fn text(
+    out: &mut String,
+    font: u8,
+    size: f32,
+    x: f32,
+    y: f32,
+    string: &str,
+    align: f32,
+) {
+    // Estimate the width (standard fonts aren't embedded, so no metrics).
+    let width = string.chars().count() as f32 * size * 0.5;
+    let x = x - width * align;
+    let _ = write!(out, "BT /F{} {} Tf {} {} Td (", font, size, x, y);
+    escape(out, string);
+    out.push_str(") Tj ET\n");
+}
+
+/// Write a string escaped for a PDF string literal (WinAnsi encoding).
+fn escape(out: &mut String, string: &str) {
+    for c in string.chars() {
+        match c {
+            '(' | ')' | '\\' => {
+                out.push('\\');
+                out.push(c);
+            }
+            ' '..='~' => out.push(c),
+            '\u{A0}'..='\u{FF}' => {
+                let _ = write!(out, "\\{:03o}", c as u32);
+            }
+            _ => out.push('?'),
+        }
+    }
+}
+
+/// Writes the objects of a PDF file, keeping track of the cross-reference
+/// table.
+struct PdfWriter {
+    out: Vec<u8>,
+    offsets: Vec<usize>,
+}
+
+impl PdfWriter {
+    /// Start a new PDF file.
+    fn new() -> Self {
+        let mut out = b"%PDF-1.4\n".to_vec();
+        // Binary comment so that the file is treated as binary.
+        out.extend_from_slice(b"%\xE2\xE3\xCF\xD3\n");
+        PdfWriter {
+            out,
+            offsets: vec![],
+        }
+    }
+
+    /// Record the offset of object `id`.
+    fn start(&mut self, id: usize) {
+        if self.offsets.len() < id {
+            self.offsets.resize(id, 0);
+        }
+        self.offsets[id - 1] = self.out.len();
+        self.out
+            .extend_from_slice(format!("{} 0 obj\n", id).as_bytes());
+    }
+
+    /// Write an object.
+    fn object(&mut self, id: usize, body: &str) {
+        self.start(id);
+        self.out.extend_from_slice(body.as_bytes());
+        self.out.extend_from_slice(b"\nendobj\n");
+    }
+
+    /// Write a stream object, with extra dictionary entries `dict`.
+    fn stream(&mut self, id: usize, dict: &str, data: &[u8]) {
+        self.start(id);
+        let header = format!("<< {} /Length {} >>\nstream\n", dict, data.len());
+        self.out.extend_from_slice(header.as_bytes());
+        self.out.extend_from_slice(data);
+        self.out.extend_from_slice(b"\nendstream\nendobj\n");
+    }
+
+    /// Write the cross-reference table and trailer.
+    fn finish(mut self, root: usize) -> Vec<u8> {
+        let xref = self.out.len();
+        let mut table = format!(
+            "xref\n0 {}\n0000000000 65535 f \n",
+            self.offsets.len() + 1
+        );
+        for offset in &self.offsets {
+            let _ = writeln!(table, "{:010} 00000 n ", offset);
+        }
+        let _ = write!(
+            table,
+            "trailer\n<< /Size {} /Root {} 0 R >>\nstartxref\n{}\n%%EOF\n",
+            self.offsets.len() + 1,
+            root,
+            xref
+        );
+        self.out.extend_from_slice(table.as_bytes());
+        self.out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Glyph paths where every glyph is a square.
+    fn glyphs() -> String {
+        vec!["M0 0h100v-100h-100z"; sfff::Glyph::Len as usize].join("\0")
+    }
+
+    #[test]
+    fn document() {
+        let scof = Scof::default();
+        let meta = crate::tests::meta();
+        let pdf = render_pdf(&scof, &meta, &glyphs(), &Default::default());
+        let text = String::from_utf8_lossy(&pdf);
+
+        assert!(pdf.starts_with(b"%PDF-1.4\n"));
+        assert!(pdf.ends_with(b"%%EOF\n"));
+        assert!(text.contains("/Count 1"));
+        assert!(text.contains("(Untitled Score) Tj"));
+        assert!(text.contains("(Anonymous) Tj"));
+        let part = scof.extract_part(&[1]);
+        let pdf = render_pdf(&part, &meta, &glyphs(), &Default::default());
+        assert!(String::from_utf8_lossy(&pdf).contains("(Channel 2) Tj"));
+        // Glyphs are drawn through XObjects.
+        assert!(text.contains("/Subtype /Form /BBox [0 -100 100 0]"));
+        assert!(text.contains("/G1 Do"));
+
+        // Every cross-reference points at its object.
+        let xref = text.rfind("xref\n").unwrap();
+        for (i, line) in text[xref..].lines().skip(3).enumerate() {
+            if line.starts_with("trailer") {
+                break;
+            }
+            let offset: usize = line[..10].parse().unwrap();
+            let obj = format!("{} 0 obj", i + 1);
+            assert_eq!(&text[offset..offset + obj.len()], obj);
+        }
+    }
+
+    #[test]
+    fn pagination() {
+        let scof = Scof::default();
+        let meta = crate::tests::meta();
+        let options = PdfOptions {
+            paper: PaperSize {
+                width: 612.0,
+                height: 160.0,
+            },
+            ..Default::default()
+        };
+        let pdf = render_pdf(&scof, &meta, &glyphs(), &options);
+        let text = String::from_utf8_lossy(&pdf);
+
+        assert!(text.contains("/Count 2"));
+        assert!(text.contains("/MediaBox [0 0 612 160]"));
+        // Page number on the second page.
+        assert!(text.contains("(2) Tj"));
+    }
+
+    #[test]
+    fn escaped_text() {
+        let mut out = String::new();
+        text(&mut out, 1, 10.0, 0.0, 0.0, "(© ♪)", 0.0);
+        assert_eq!(out, "BT /F1 10 Tf 0 0 Td (\\(\\251 ?\\)) Tj ET\n");
+    }
+}
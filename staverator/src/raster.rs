@@ -0,0 +1,624 @@
+// ScoreFall Ink - Music Composition Software
+//
+// Copyright (C) 2019-2020 Jeron Aldaron Lau <jeronlau@plopgrizzly.com>
+// Copyright (C) 2019-2020 Doug P. Lau
+//
+//     This program is free software: you can redistribute it and/or modify
+//     it under the terms of the GNU General Public License as published by
+//     the Free Software Foundation, either version 3 of the License, or
+//     (at your option) any later version.
+//
+//     This program is distributed in the hope that it will be useful,
+//     but WITHOUT ANY WARRANTY; without even the implied warranty of
+//     MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//     GNU General Public License for more details.
+//
+//     You should have received a copy of the GNU General Public License
+//     along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+/* ************************************************************************** */
+
+//! Software rasterizer for rendering elements into PNG images.  Shapes are
+//! anti-aliased by computing the exact area covered in each pixel.
+
+use std::collections::HashMap;
+
+use crate::path_data::{self, PathOp};
+use crate::{Element, STAVE_SPACE};
+
+/// Options for rendering elements to a PNG image.
+#[derive(Clone, Debug)]
+pub struct RasterOptions {
+    /// Resolution (pixels per inch).
+    pub dpi: f32,
+    /// Size of one stave space (in points, 1/72 inch).
+    pub stave_space: f32,
+}
+
+impl Default for RasterOptions {
+    fn default() -> Self {
+        RasterOptions {
+            dpi: 96.0,
+            stave_space: 4.96, // 1.75mm
+        }
+    }
+}
+
+impl RasterOptions {
+    /// Get the number of pixels per font unit.
+    fn scale(&self) -> f32 {
+        self.stave_space / STAVE_SPACE as f32 * self.dpi / 72.0
+    }
+}
+
+/// Render elements as a PNG image on a white background.  Text is left out,
+/// as there is no text font (see [`has_text`]).
+///
+/// - `width`, `height`: Size of the image (in font units).
+/// - `glyph_paths`: The SVG paths of the font's glyphs, separated by null
+///   bytes (see [`modern_glyphs`]).
+///
+/// [`modern_glyphs`]: crate::modern_glyphs
+pub fn render_png(
+    elements: &[Element],
+    width: i32,
+    height: i32,
+    glyph_paths: &str,
+    options: &RasterOptions,
+) -> Vec<u8> {
+    let scale = options.scale();
+    let mut raster = Raster::new(
+        (width as f32 * scale).ceil() as usize,
+        (height as f32 * scale).ceil() as usize,
+        scale,
+        glyph_paths,
+    );
+    for elem in elements {
+        raster.element(elem, 0.0, 0.0);
+    }
+    raster.png()
+}
+
+/// Check if any of the elements (or their groups) is text, which is left out
+/// of PNG images.
+pub fn has_text(elements: &[Element]) -> bool {
+    elements.iter().any(|elem| match elem {
+        Element::Text(_) => true,
+        Element::Group(g) => has_text(&g.elements),
+        _ => false,
+    })
+}
+
+/// An RGB image being rendered.
+struct Raster<'a> {
+    width: usize,
+    height: usize,
+    /// Pixels per font unit
+    scale: f32,
+    /// RGB pixel data
+    pixels: Vec<u8>,
+    /// Glyph path data
+    glyphs: Vec<&'a str>,
+    /// Parsed glyphs
+    cache: HashMap<u16, Vec<PathOp>>,
+}
+
+impl<'a> Raster<'a> {
+    /// Create a new white raster.
+    fn new(width: usize, height: usize, scale: f32, glyphs: &'a str) -> Self {
+        Raster {
+            width,
+            height,
+            scale,
+            pixels: vec![0xFF; width * height * 3],
+            glyphs: glyphs.split('\0').collect(),
+            cache: HashMap::new(),
+        }
+    }
+
+    /// Draw an element, offset by (`x`, `y`) font units.
+    fn element(&mut self, elem: &Element, x: f32, y: f32) {
+        match elem {
+            Element::Rect(r) => {
+                let (x0, y0) = (x + r.x as f32, y + r.y as f32);
+                let (x1, y1) = (x0 + r.width as f32, y0 + r.height as f32);
+                let ops = [
+                    PathOp::Move(x0, y0),
+                    PathOp::Line(x1, y0),
+                    PathOp::Line(x1, y1),
+                    PathOp::Line(x0, y1),
+                    PathOp::Close,
+                ];
+                self.fill(&ops, 0.0, 0.0, r.fill_rgb().unwrap_or([0; 3]));
+            }
+            Element::Use(u) => {
+                let glyphs = &self.glyphs;
+                let ops = self
+                    .cache
+                    .entry(u.id)
+                    .or_insert_with(|| {
+                        path_data::parse(
+                            glyphs.get(u.id as usize).unwrap_or(&""),
+                        )
+                    })
+                    .clone();
+                let ops: Vec<PathOp> =
+                    ops.into_iter().map(|op| op.scale(u.scale)).collect();
+                self.fill(&ops, x + u.x as f32, y + u.y as f32, [0; 3]);
+            }
+            Element::Path(p) => {
+                self.fill(&path_data::parse(&p.d), x, y, [0; 3]);
+            }
+            Element::Group(g) => {
+                for e in &g.elements {
+                    self.element(e, x + g.x as f32, y + g.y as f32);
+                }
+            }
+            // Text isn't rasterized (there is no text font, see `has_text`).
+            Element::Text(_) => {}
+        }
+    }
+
+    /// Fill a path offset by (`x`, `y`) font units with a color.
+    fn fill(&mut self, ops: &[PathOp], x: f32, y: f32, rgb: [u8; 3]) {
+        let lines = self.flatten(ops, x, y);
+        if lines.is_empty() {
+            return;
+        }
+        // Find the bounding box of the lines, clipped to the raster.
+        let mut b = [f32::MAX, f32::MAX, f32::MIN, f32::MIN];
+        for &(px, py, _, _) in &lines {
+            b = [b[0].min(px), b[1].min(py), b[2].max(px), b[3].max(py)];
+        }
+        let x0 = (b[0].floor().max(0.0) as usize).min(self.width);
+        let y0 = (b[1].floor().max(0.0) as usize).min(self.height);
+        let x1 = (b[2].ceil().max(0.0) as usize).min(self.width);
+        let y1 = (b[3].ceil().max(0.0) as usize).min(self.height);
+        if x0 >= x1 || y0 >= y1 {
+            return;
+        }
+
+        let mut cover = Coverage::new(x1 - x0, y1 - y0);
+        let (ox, oy) = (x0 as f32, y0 as f32);
+        for &(ax, ay, bx, by) in &lines {
+            cover.line(ax - ox, ay - oy, bx - ox, by - oy);
+        }
+        cover.accumulate(|cx, cy, alpha| {
+            let i = ((y0 + cy) * self.width + x0 + cx) * 3;
+            for (c, v) in self.pixels[i..i + 3].iter_mut().zip(rgb.iter()) {
+                let blend =
+                    f32::from(*c) + (f32::from(*v) - f32::from(*c)) * alpha;
+                *c = blend.round() as u8;
+            }
+        });
+    }
+
+    /// Flatten path operations into lines (in pixels), closing each subpath.
+    fn flatten(
+        &self,
+        ops: &[PathOp],
+        x: f32,
+        y: f32,
+    ) -> Vec<(f32, f32, f32, f32)> {
+        let s = self.scale;
+        let tr = |px: f32, py: f32| ((x + px) * s, (y + py) * s);
+        let mut lines = vec![];
+        let (mut start, mut pen) = ((0.0, 0.0), (0.0, 0.0));
+        for op in ops {
+            match *op {
+                PathOp::Move(px, py) => {
+                    if pen != start {
+                        lines.push((pen.0, pen.1, start.0, start.1));
+                    }
+                    start = tr(px, py);
+                    pen = start;
+                }
+                PathOp::Line(px, py) => {
+                    let p = tr(px, py);
+                    lines.push((pen.0, pen.1, p.0, p.1));
+                    pen = p;
+                }
+                PathOp::Cubic(ax, ay, bx, by, px, py) => {
+                    let (a, b, p) = (tr(ax, ay), tr(bx, by), tr(px, py));
+                    let len = dist(pen, a) + dist(a, b) + dist(b, p);
+                    let n = (len.sqrt() * 2.0).ceil().clamp(1.0, 64.0);
+                    let mut prev = pen;
+                    for i in 1..=n as usize {
+                        let t = i as f32 / n;
+                        let u = 1.0 - t;
+                        let (c0, c1, c2, c3) = (
+                            u * u * u,
+                            3.0 * u * u * t,
+                            3.0 * u * t * t,
+                            t * t * t,
+                        );
+                        let q = (
+                            c0 * pen.0 + c1 * a.0 + c2 * b.0 + c3 * p.0,
+                            c0 * pen.1 + c1 * a.1 + c2 * b.1 + c3 * p.1,
+                        );
+                        lines.push((prev.0, prev.1, q.0, q.1));
+                        prev = q;
+                    }
+                    pen = p;
+                }
+                PathOp::Close => {
+                    if pen != start {
+                        lines.push((pen.0, pen.1, start.0, start.1));
+                    }
+                    pen = start;
+                }
+            }
+        }
+        if pen != start {
+            lines.push((pen.0, pen.1, start.0, start.1));
+        }
+        lines
+    }
+
+    /// Encode the raster as a PNG image.
+    fn png(&self) -> Vec<u8> {
+        // Each row uses the "Up" filter, so blank areas become zeros.
+        let stride = self.width * 3;
+        let mut data = Vec::with_capacity((stride + 1) * self.height);
+        for row in 0..self.height {
+            let line = &self.pixels[row * stride..(row + 1) * stride];
+            if row == 0 {
+                data.push(0);
+                data.extend_from_slice(line);
+            } else {
+                let above = &self.pixels[(row - 1) * stride..row * stride];
+                data.push(2);
+                data.extend(
+                    line.iter().zip(above).map(|(c, a)| c.wrapping_sub(*a)),
+                );
+            }
+        }
+
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        let mut ihdr = vec![];
+        ihdr.extend_from_slice(&(self.width as u32).to_be_bytes());
+        ihdr.extend_from_slice(&(self.height as u32).to_be_bytes());
+        // 8-bit RGB, no interlace
+        ihdr.extend_from_slice(&[8, 2, 0, 0, 0]);
+        chunk(&mut png, b"IHDR", &ihdr);
+        chunk(&mut png, b"IDAT", &zlib(&data));
+        chunk(&mut png, b"IEND", &[]);
+        png
+    }
+}
+
+/// Distance between two points.
+fn dist(a: (f32, f32), b: (f32, f32)) -> f32 {
+    ((b.0 - a.0).powi(2) + (b.1 - a.1).powi(2)).sqrt()
+}
+
+/// Signed area accumulation buffer for anti-aliased filling.  Each line adds
+/// the area it covers to the cells it crosses; a running sum then gives the
+/// coverage of every pixel.
+struct Coverage {
+    width: usize,
+    height: usize,
+    area: Vec<f32>,
+}
+
+impl Coverage {
+    /// Create an empty coverage buffer.
+    fn new(width: usize, height: usize) -> Self {
+        Coverage {
+            width,
+            height,
+            area: vec![0.0; width * height + 2],
+        }
+    }
+
+    /// Add a line to the buffer.
+    fn line(&mut self, x0: f32, y0: f32, x1: f32, y1: f32) {
+        if (y0 - y1).abs() <= f32::EPSILON {
+            return;
+        }
+        // Lines left of the buffer cover the first column, and lines right of
+        // the buffer cover nothing.
+        let w = self.width as f32;
+        let (x0, x1) = (x0.max(0.0).min(w), x1.max(0.0).min(w));
+        let (dir, (x0, y0), (x1, y1)) = if y0 < y1 {
+            (1.0, (x0, y0), (x1, y1))
+        } else {
+            (-1.0, (x1, y1), (x0, y0))
+        };
+        let dxdy = (x1 - x0) / (y1 - y0);
+        let mut x = x0;
+        if y0 < 0.0 {
+            x -= y0 * dxdy;
+        }
+        let end = (y1.ceil().max(0.0) as usize).min(self.height);
+        for row in (y0.max(0.0) as usize)..end {
+            let start = row * self.width;
+            let dy = ((row + 1) as f32).min(y1) - (row as f32).max(y0);
+            let xnext = x + dxdy * dy;
+            let d = dy * dir;
+            let (xa, xb) = if x < xnext { (x, xnext) } else { (xnext, x) };
+            let xa_floor = xa.floor();
+            let xai = xa_floor as usize;
+            let xb_ceil = xb.ceil();
+            let xbi = xb_ceil as usize;
+            if xbi <= xai + 1 {
+                // Line within a single column.
+                let xmf = 0.5 * (x + xnext) - xa_floor;
+                self.area[start + xai] += d - d * xmf;
+                self.area[start + xai + 1] += d * xmf;
+            } else {
+                let s = (xb - xa).recip();
+                let xaf = xa - xa_floor;
+                let a0 = 0.5 * s * (1.0 - xaf) * (1.0 - xaf);
+                let xbf = xb - xb_ceil + 1.0;
+                let am = 0.5 * s * xbf * xbf;
+                self.area[start + xai] += d * a0;
+                if xbi == xai + 2 {
+                    self.area[start + xai + 1] += d * (1.0 - a0 - am);
+                } else {
+                    let a1 = s * (1.5 - xaf);
+                    self.area[start + xai + 1] += d * (a1 - a0);
+                    for xi in xai + 2..xbi - 1 {
+                        self.area[start + xi] += d * s;
+                    }
+                    let a2 = a1 + (xbi - xai - 3) as f32 * s;
+                    self.area[start + xbi - 1] += d * (1.0 - a2 - am);
+                }
+                self.area[start + xbi] += d * am;
+            }
+            x = xnext;
+        }
+    }
+
+    /// Call `f` with the coverage (0.0 to 1.0) of each covered pixel.
+    fn accumulate<F: FnMut(usize, usize, f32)>(&self, mut f: F) {
+        let mut acc = 0.0;
+        for (i, a) in self.area[..self.width * self.height].iter().enumerate() {
+            acc += a;
+            let alpha = acc.abs().min(1.0);
+            if alpha > 1.0 / 512.0 {
+                f(i % self.width, i / self.width, alpha);
+            }
+        }
+    }
+}
+
+/// Write a PNG chunk.
+fn chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+/// Calculate the CRC-32 of data.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc ^= u32::from(*byte);
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+/// Base lengths of deflate length codes 257 to 285.
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59,
+    67, 83, 99, 115, 131, 163, 195, 227, 258,
+];
+
+/// Extra bits of deflate length codes 257 to 285.
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5,
+    5, 5, 5, 0,
+];
+
+/// Writes bits, least significant first.
+struct BitWriter {
+    out: Vec<u8>,
+    bits: u32,
+    count: u32,
+}
+
+impl BitWriter {
+    /// Write `count` bits of `value`.
+    fn bits(&mut self, value: u32, count: u32) {
+        self.bits |= value << self.count;
+        self.count += count;
+        while self.count >= 8 {
+            self.out.push(self.bits as u8);
+            self.bits >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    /// Write a Huffman code (most significant bit first).
+    fn code(&mut self, code: u32, count: u32) {
+        let reversed = code.reverse_bits() >> (32 - count);
+        self.bits(reversed, count);
+    }
+
+    /// Write a symbol using the fixed Huffman codes.
+    fn symbol(&mut self, sym: u16) {
+        let sym = u32::from(sym);
+        match sym {
+            0..=143 => self.code(0x30 + sym, 8),
+            144..=255 => self.code(0x190 + sym - 144, 9),
+            256..=279 => self.code(sym - 256, 7),
+            _ => self.code(0xC0 + sym - 280, 8),
+        }
+    }
+
+    /// Flush remaining bits.
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.out.push(self.bits as u8);
+        }
+        self.out
+    }
+}
+
+/// Compress data into a zlib stream.  Runs of repeated bytes are encoded as
+/// matches at distance 1 with the fixed Huffman codes, which works well for
+/// images that are mostly blank.
+fn zlib(data: &[u8]) -> Vec<u8> {
+    let mut w = BitWriter {
+        out: vec![0x78, 0x01],
+        bits: 0,
+        count: 0,
+    };
+    // Final block, fixed Huffman codes
+    w.bits(0b011, 3);
+    let mut i = 0;
+    while i < data.len() {
+        let mut run = 0;
+        if i > 0 {
+            let prev = data[i - 1];
+            while run < 258 && i + run < data.len() && data[i + run] == prev {
+                run += 1;
+            }
+        }
+        if run >= 3 {
+            let code = LENGTH_BASE.iter().rposition(|b| *b as usize <= run);
+            let code = code.unwrap_or(0);
+            w.symbol(257 + code as u16);
+            let extra = LENGTH_EXTRA[code];
+            w.bits((run - LENGTH_BASE[code] as usize) as u32, u32::from(extra));
+            // Distance code 0 (distance 1), no extra bits
+            w.code(0, 5);
+            i += run;
+        } else {
+            w.symbol(u16::from(data[i]));
+            i += 1;
+        }
+    }
+    w.symbol(256);
+    let mut out = w.finish();
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+/// Calculate the Adler-32 checksum of data.
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for byte in data {
+        a = (a + u32::from(*byte)) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Group, Rect, Use};
+
+    /// Get the RGB pixel at (x, y).
+    fn pixel(raster: &Raster, x: usize, y: usize) -> [u8; 3] {
+        let i = (y * raster.width + x) * 3;
+        [raster.pixels[i], raster.pixels[i + 1], raster.pixels[i + 2]]
+    }
+
+    #[test]
+    fn rect_coverage() {
+        let mut raster = Raster::new(8, 8, 0.5, "");
+        // Covers pixels 1 to 3 fully, and half of pixel 4.
+        let rect = Rect::new(2, 2, 7, 4, None, None, None);
+        raster.element(&Element::Rect(rect), 0.0, 0.0);
+        assert_eq!(pixel(&raster, 0, 1), [255; 3]);
+        assert_eq!(pixel(&raster, 1, 1), [0; 3]);
+        assert_eq!(pixel(&raster, 3, 2), [0; 3]);
+        assert_eq!(pixel(&raster, 4, 1), [128; 3]);
+        assert_eq!(pixel(&raster, 5, 1), [255; 3]);
+        assert_eq!(pixel(&raster, 1, 3), [255; 3]);
+    }
+
+    #[test]
+    fn fill_and_groups() {
+        let mut raster = Raster::new(8, 8, 1.0, "M0 0h2v2h-2z");
+        let rect = Rect::new(0, 0, 2, 2, None, None, Some(0xFF9AF0));
+        let mut group = Group::new(4, 4, None);
+        group.push(Element::Use(Use::new(1, 1, 0)));
+        raster.element(&Element::Rect(rect), 0.0, 0.0);
+        raster.element(&Element::Group(group), 0.0, 0.0);
+        assert_eq!(pixel(&raster, 1, 1), [0xFF, 0x9A, 0xF0]);
+        assert_eq!(pixel(&raster, 4, 4), [255; 3]);
+        assert_eq!(pixel(&raster, 5, 5), [0; 3]);
+        assert_eq!(pixel(&raster, 6, 6), [0; 3]);
+        assert_eq!(pixel(&raster, 7, 7), [255; 3]);
+    }
+
+    #[test]
+    fn text() {
+        let mut group = Group::new(4, 4, None);
+        assert!(!has_text(&[Element::Group(Group::new(0, 0, None))]));
+        group.push(Element::Text(crate::Text::new(0, 0, 12, false, "Fine")));
+        assert!(has_text(&[Element::Group(group)]));
+    }
+
+    #[test]
+    fn clipped_curve() {
+        // A circle mostly outside of the raster.
+        let mut raster = Raster::new(4, 4, 1.0, "");
+        let path = crate::Path::new(
+            None,
+            "M-4 2C-4-8 8-8 8 2C8 12-4 12-4 2Z".to_string(),
+        );
+        raster.element(&Element::Path(path), 0.0, 0.0);
+        for y in 0..4 {
+            for x in 0..4 {
+                assert_eq!(pixel(&raster, x, y), [0; 3]);
+            }
+        }
+    }
+
+    #[test]
+    fn checksums() {
+        assert_eq!(crc32(b"IEND"), 0xAE42_6082);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+    }
+
+    #[test]
+    fn compression() {
+        // Checked against zlib's inflate.
+        assert_eq!(
+            zlib(b"aaaaab"),
+            [
+                0x78, 0x01, 0x4B, 0x04, 0x81, 0x24, 0x00, 0x07, 0xFC, 0x02,
+                0x48
+            ]
+        );
+    }
+
+    #[test]
+    fn png() {
+        let scof = scof::Scof::default();
+        let meta = crate::tests::meta();
+        let range = crate::document::pitch_range(&scof, 0);
+        let bar =
+            crate::document::engrave_bars(&scof, &meta, 0, &(0..1), range);
+        let glyphs = vec!["M0 0h100v-100h-100z"; sfff::Glyph::Len as usize];
+        let options = RasterOptions::default();
+        let png = render_png(
+            &bar.elements,
+            bar.width,
+            1000,
+            &glyphs.join("\0"),
+            &options,
+        );
+        assert!(png.starts_with(b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR"));
+        assert!(png.ends_with(b"IEND\xAE\x42\x60\x82"));
+        // 96 DPI, 4.96 points per stave space
+        let width = (bar.width as f32 * options.scale()).ceil() as u32;
+        assert_eq!(png[16..20], width.to_be_bytes());
+        assert_eq!(png[20..24], 67u32.to_be_bytes());
+    }
+}
@@ -0,0 +1,160 @@
+// ScoreFall Ink - Music Composition Software
+//
+// Copyright (C) 2019-2020 Jeron Aldaron Lau <jeronlau@plopgrizzly.com>
+// Copyright (C) 2019-2020 Doug P. Lau
+//
+//     This program is free software: you can redistribute it and/or modify
+//     it under the terms of the GNU General Public License as published by
+//     the Free Software Foundation, either version 3 of the License, or
+//     (at your option) any later version.
+//
+//     This program is distributed in the hope that it will be useful,
+//     but WITHOUT ANY WARRANTY; without even the implied warranty of
+//     MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//     GNU General Public License for more details.
+//
+//     You should have received a copy of the GNU General Public License
+//     along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+/* ************************************************************************** */
+
+//! Rehearsal marks and bar numbers, above the first stave.
+//!
+//! Rehearsal marks are boxed, above any tempo marking at the start of the
+//! bar.  Bar numbers are small italic text, just above the stave.
+
+use crate::{BarElem, Element, Rect, Stave, Text};
+use scof::{Cursor, Scof, Steps};
+use sfff::SfFontMetadata;
+
+/// Font size of rehearsal marks.
+const REHEARSAL_SIZE: i32 = 5 * Stave::SPACE / 2;
+/// Height of the baseline of rehearsal marks above the first stave.
+const REHEARSAL_HEIGHT: i32 = 7 * Stave::SPACE;
+/// Room for rehearsal marks above the first stave (up to the top of the
+/// box).
+pub(crate) const REHEARSAL_ROOM: Steps = Steps(20);
+/// Space between a rehearsal mark and its box.
+const REHEARSAL_PADDING: i32 = Stave::SPACE / 2;
+/// Font size of bar numbers.
+const NUMBER_SIZE: i32 = 3 * Stave::SPACE / 2;
+/// Height of the baseline of bar numbers above the first stave.
+const NUMBER_HEIGHT: i32 = 3 * Stave::SPACE / 2;
+
+impl BarElem {
+    /// Add the rehearsal mark and bar number at the start of a bar.
+    ///
+    /// - `curs`: Cursor of the measure.
+    pub(crate) fn add_rehearsal(
+        &mut self,
+        meta: &SfFontMetadata,
+        scof: &Scof,
+        curs: &Cursor,
+    ) {
+        let bar = curs.bar_index();
+        let top = self.top_line(Steps(0));
+        if scof.style.numbers_bar(bar, self.system_start) {
+            let y = top - NUMBER_HEIGHT;
+            let text = (bar + 1).to_string();
+            let text = Text::new(self.width, y, NUMBER_SIZE, true, text);
+            self.elements.push(Element::Text(text));
+        }
+        let mark = scof
+            .movement
+            .get(curs.movement_index() as usize)
+            .and_then(|mvmt| mvmt.rehearsal(bar));
+        if let Some(mark) = mark {
+            let pad = REHEARSAL_PADDING;
+            let y = top - REHEARSAL_HEIGHT;
+            let text =
+                Text::new(self.width + pad, y, REHEARSAL_SIZE, false, mark);
+            // Capital letters and digits are about 3/4 of the font size.
+            let (width, height) =
+                (text.width() + 2 * pad, REHEARSAL_SIZE * 3 / 4 + 2 * pad);
+            self.add_box(self.width, y + pad - height, width, height, meta);
+            self.elements.push(Element::Text(text));
+        }
+    }
+
+    /// Add the outline of a box, with the thickness of rehearsal mark boxes.
+    fn add_box(
+        &mut self,
+        x: i32,
+        y: i32,
+        width: i32,
+        height: i32,
+        meta: &SfFontMetadata,
+    ) {
+        let t = meta.rehearsal_box_thickness;
+        let sides = [
+            (x, y, width, t),
+            (x, y + height - t, width, t),
+            (x, y, t, height),
+            (x + width - t, y, t, height),
+        ];
+        for (x, y, width, height) in sides.iter().cloned() {
+            let rect = Rect::new(x, y, width, height, None, None, None);
+            self.elements.push(Element::Rect(rect));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use scof::Movement;
+
+    /// Engrave a bar, getting its text and number of rectangles.
+    fn engrave(
+        scof: &Scof,
+        bar: u16,
+        system_start: bool,
+    ) -> (Vec<String>, usize) {
+        let mut elem =
+            crate::document::bar_elem(crate::document::pitch_range(scof, 0));
+        if system_start {
+            elem.start_system();
+        }
+        elem.add_bar(&crate::tests::meta(), scof, &Cursor::new(0, bar, 0, 0));
+        let texts = elem
+            .elements
+            .iter()
+            .filter_map(|e| match e {
+                Element::Text(t) if t.size != 2 * Stave::SPACE => {
+                    Some(t.text.clone())
+                }
+                _ => None,
+            })
+            .collect();
+        let rects = elem
+            .elements
+            .iter()
+            .filter(|e| matches!(e, Element::Rect(_)))
+            .count();
+        (texts, rects)
+    }
+
+    #[test]
+    fn rehearsal_marks() {
+        let muon = "sig: 0\n  time: 4/4\n  tempo: 120\n\
+            bar:\n  chan: 1/1R\n\
+            bar:\n  rehearsal: *\n  chan: 1/1R\n\
+            bar:\n  chan: 1/1R\n";
+        let mut scof = Scof {
+            movement: vec![Movement::from_muon(muon).unwrap()],
+            ..Default::default()
+        };
+        let (texts, _) = engrave(&scof, 0, true);
+        // The first bar isn't numbered.
+        assert!(texts.is_empty());
+        let (texts, rects) = engrave(&scof, 1, false);
+        assert_eq!(texts, vec!["A"]);
+        let (texts, plain) = engrave(&scof, 2, true);
+        assert_eq!(texts, vec!["3"]);
+        assert_eq!(rects, plain + 4);
+        scof.style.bar_numbers = Some(2);
+        let numbers: Vec<Vec<String>> =
+            (0..3).map(|bar| engrave(&scof, bar, true).0).collect();
+        assert_eq!(numbers, vec![vec![], vec!["2", "A"], vec![]]);
+    }
+}
@@ -0,0 +1,236 @@
+// ScoreFall Ink - Music Composition Software
+//
+// Copyright (C) 2019-2020 Jeron Aldaron Lau <jeronlau@plopgrizzly.com>
+// Copyright (C) 2019-2020 Doug P. Lau
+//
+//     This program is free software: you can redistribute it and/or modify
+//     it under the terms of the GNU General Public License as published by
+//     the Free Software Foundation, either version 3 of the License, or
+//     (at your option) any later version.
+//
+//     This program is distributed in the hope that it will be useful,
+//     but WITHOUT ANY WARRANTY; without even the implied warranty of
+//     MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//     GNU General Public License for more details.
+//
+//     You should have received a copy of the GNU General Public License
+//     along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+/* ************************************************************************** */
+
+//! Repeat barlines, volta brackets and navigation markings (jumps).
+//!
+//! The open repeat barline is drawn at the start of a bar, and the close
+//! repeat barline after its end barline, so the thick barline is always on the
+//! outside of the repeated section.
+
+use crate::{BarElem, Element, Rect, Stave, Text};
+use scof::{Cursor, Repeat, Scof, Steps};
+use sfff::{Glyph, SfFontMetadata};
+
+/// Width of a repeat dot (in font units).
+const REPEAT_DOT_WIDTH: i32 = 50;
+/// Height of volta brackets above the top line of the first stave.
+const VOLTA_HEIGHT: i32 = 4 * Stave::SPACE;
+/// Length of the hooks at the ends of a volta bracket.
+const VOLTA_HOOK: i32 = 2 * Stave::SPACE;
+/// Font size of volta numbers and jump text.
+const TEXT_SIZE: i32 = 2 * Stave::SPACE;
+/// Width of a segno or coda sign (in font units).
+const SIGN_WIDTH: i32 = 2 * Stave::SPACE;
+
+impl BarElem {
+    /// Get the width of the open repeat barlines and dots at the start of the
+    /// bar.
+    pub(crate) fn repeat_open_width(&self, meta: &SfFontMetadata) -> i32 {
+        if self.repeat.contains(&Repeat::Open) {
+            meta.thick_barline_thickness
+                + meta.barlines_space
+                + meta.barline_thickness
+                + meta.barline_repeatdot_space
+                + REPEAT_DOT_WIDTH
+        } else {
+            0
+        }
+    }
+
+    /// Get the width of the close repeat dots before the end barline.
+    pub(crate) fn repeat_dots_width(&self, meta: &SfFontMetadata) -> i32 {
+        if self.repeat.contains(&Repeat::Close) {
+            meta.barline_repeatdot_space + REPEAT_DOT_WIDTH
+        } else {
+            0
+        }
+    }
+
+    /// Get the width of the thick close repeat barline after the end barline.
+    pub(crate) fn repeat_close_width(&self, meta: &SfFontMetadata) -> i32 {
+        if self.repeat.contains(&Repeat::Close) {
+            meta.barlines_space + meta.thick_barline_thickness
+        } else {
+            0
+        }
+    }
+
+    /// Add repeat barlines and dots to one stave, given the X position of
+    /// the end barline.
+    pub(crate) fn add_repeat_barlines(
+        &mut self,
+        meta: &SfFontMetadata,
+        x: i32,
+        ofs: Steps,
+    ) {
+        if self.repeat.contains(&Repeat::Open) {
+            let thick = meta.thick_barline_thickness;
+            let thin = thick + meta.barlines_space;
+            self.add_barline_width(0, thick, ofs);
+            self.add_barline_width(thin, meta.barline_thickness, ofs);
+            let dots = thin + meta.barline_thickness;
+            self.add_repeat_dots(dots + meta.barline_repeatdot_space, ofs);
+        }
+        if self.repeat.contains(&Repeat::Close) {
+            let dots = x - meta.barline_repeatdot_space - REPEAT_DOT_WIDTH;
+            self.add_repeat_dots(dots, ofs);
+            let thick = x + meta.barline_thickness + meta.barlines_space;
+            self.add_barline_width(thick, meta.thick_barline_thickness, ofs);
+        }
+    }
+
+    /// Add the two repeat dots, in the spaces around the middle line.
+    fn add_repeat_dots(&mut self, x: i32, ofs: Steps) {
+        let y = self.middle() + (ofs * Stave::STEP).0;
+        self.add_use(Glyph::RepeatUpDot, x, y - Stave::STEP);
+        self.add_use(Glyph::RepeatDownDot, x, y + Stave::STEP);
+    }
+
+    /// Add volta brackets, signs and jump text above the first stave of a
+    /// bar `width` wide.
+    pub(crate) fn add_navigation(
+        &mut self,
+        meta: &SfFontMetadata,
+        scof: &Scof,
+        curs: &Cursor,
+        width: i32,
+    ) {
+        let bars = match scof.movement.get(curs.movement_index() as usize) {
+            Some(mvmt) => &mvmt.bar,
+            None => return,
+        };
+        let bar = curs.bar_index() as usize;
+        let top = self.stave_top(0);
+        // Volta bracket, with a number where it starts.
+        let endings = bars[bar].endings();
+        if !endings.is_empty() {
+            let y = top - VOLTA_HEIGHT;
+            let thickness = meta.barline_thickness;
+            let first = bar == 0 || bars[bar - 1].endings() != endings;
+            if first {
+                self.add_rect(0, y, thickness, VOLTA_HOOK);
+                let numbers: Vec<String> =
+                    endings.iter().map(|n| n.to_string()).collect();
+                let number = format!("{}.", numbers.join(", "));
+                self.add_text(Stave::SPACE / 2, y + TEXT_SIZE, number);
+            }
+            self.add_rect(0, y, width, thickness);
+            if self.repeat.contains(&Repeat::Close) {
+                self.add_rect(width - thickness, y, thickness, VOLTA_HOOK);
+            }
+        }
+        // Signs at the start of the bar.
+        let sign = top - 2 * Stave::SPACE;
+        if self.repeat.contains(&Repeat::Segno) {
+            self.add_use(Glyph::Segno, 0, sign);
+        }
+        if self.repeat.contains(&Repeat::Coda) {
+            self.add_use(Glyph::Coda, 0, sign);
+        }
+        // Jump text at the end of the bar.
+        let has = |repeat| bars.iter().any(|m| m.has_repeat(repeat));
+        let target = if has(Repeat::ToCoda) {
+            " al Coda"
+        } else if has(Repeat::Fine) {
+            " al Fine"
+        } else {
+            ""
+        };
+        let mut jumps = vec![];
+        if self.repeat.contains(&Repeat::Fine) {
+            jumps.push("Fine".to_string());
+        }
+        if self.repeat.contains(&Repeat::DC) {
+            jumps.push(format!("D.C.{}", target));
+        }
+        if self.repeat.contains(&Repeat::DS) {
+            jumps.push(format!("D.S.{}", target));
+        }
+        let mut y = top - Stave::SPACE;
+        if self.repeat.contains(&Repeat::ToCoda) {
+            self.add_use(Glyph::Coda, width - SIGN_WIDTH, sign);
+            self.add_jump(width - SIGN_WIDTH, y, "To Coda");
+            y -= TEXT_SIZE;
+        }
+        for jump in jumps {
+            self.add_jump(width, y, &jump);
+            y -= TEXT_SIZE;
+        }
+    }
+
+    /// Add a filled rectangle.
+    fn add_rect(&mut self, x: i32, y: i32, width: i32, height: i32) {
+        let rect = Rect::new(x, y, width, height, None, None, None);
+        self.elements.push(Element::Rect(rect));
+    }
+
+    /// Add upright text with its baseline at `y`.
+    fn add_text(&mut self, x: i32, y: i32, text: String) {
+        let text = Text::new(x, y, TEXT_SIZE, false, text);
+        self.elements.push(Element::Text(text));
+    }
+
+    /// Add italic jump text ending at `x`, with its baseline at `y`.
+    fn add_jump(&mut self, x: i32, y: i32, jump: &str) {
+        let mut text = Text::new(x, y, TEXT_SIZE, true, jump);
+        text.x -= text.width();
+        self.elements.push(Element::Text(text));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::document::{engrave_bars, pitch_range};
+    use scof::Scof;
+
+    #[test]
+    fn repeat_barlines() {
+        let meta = crate::tests::meta();
+        let mut scof = Scof::default();
+        let range = pitch_range(&scof, 0);
+        let plain = engrave_bars(&scof, &meta, 0, &(0..1), range);
+        scof.movement[0].bar[0].repeat =
+            vec!["Open".into(), "Ending1".into(), "Close".into()];
+        let bar = engrave_bars(&scof, &meta, 0, &(0..1), range);
+        // Thick, space, thin, space, dot at the start; dot, space before the
+        // end barline; space, thick after it.
+        let extra = (50 + 40 + 16 + 16 + 50) + (16 + 50) + (40 + 50);
+        assert!((bar.width - plain.width - extra).abs() <= 1);
+        let svg = bar.to_string();
+        assert_eq!(svg.matches("width='50'").count(), 4);
+        assert_eq!(svg.matches("href='#5e'").count(), 4);
+        assert_eq!(svg.matches("href='#5f'").count(), 4);
+        assert!(svg.contains(">1.</text>"));
+    }
+
+    #[test]
+    fn jumps() {
+        let meta = crate::tests::meta();
+        let mut scof = Scof::default();
+        scof.movement[0].bar[1].repeat = vec!["Segno".into(), "Fine".into()];
+        scof.movement[0].bar[3].repeat = vec!["DS".into()];
+        let range = pitch_range(&scof, 0);
+        let svg = engrave_bars(&scof, &meta, 0, &(1..2), range).to_string();
+        assert!(svg.contains("href='#5b'"));
+        assert!(svg.contains("font-style='italic'>Fine</text>"));
+        let svg = engrave_bars(&scof, &meta, 0, &(3..4), range).to_string();
+        assert!(svg.contains(">D.S. al Fine</text>"));
+    }
+}
@@ -24,8 +24,11 @@
 use std::collections::VecDeque;
 use std::convert::TryInto;
 
-use crate::{BarElem, Beams, Element, Notator, Stave, BAR_WIDTH};
-use scof::Steps;
+use crate::notehead::{self, Notehead};
+use crate::{
+    BarElem, Beams, Element, Notator, Stave, BAR_WIDTH, MULTI_REST_BARS,
+};
+use scof::{Cursor, Marking, Steps};
 use sfff::SfFontMetadata;
 
 /// Engraver for a single bar of music (multiple staves)
@@ -55,10 +58,10 @@ impl<'a, 'b, 'c> BarEngraver<'a, 'b, 'c> {
         // Add each stave
         let mut beams = vec![];
         let mut pq = VecDeque::new();
-        for i in 0..notators.len() {
+        for (i, notator) in notators.iter().enumerate() {
             // 128 128ths remaining.
             pq.push_back((128, i));
-            beams.push(Beams::new());
+            beams.push(Beams::new(notator.stems()));
         }
         // Beginning of bar margin
         let width = Stave::SPACE as f32 / BAR_WIDTH as f32;
@@ -82,22 +85,58 @@ impl<'a, 'b, 'c> BarEngraver<'a, 'b, 'c> {
         meta: &SfFontMetadata,
     ) -> (i32, Option<(i32, i32, i32, i32)>) {
         let ymargin = self.bar.stave.height_steps() + Steps(12);
+        let staves: i32 = self
+            .notators
+            .iter()
+            .map(|n| n.stave() + 1)
+            .max()
+            .unwrap_or(0)
+            .try_into()
+            .unwrap();
         let mut cursor_rect = None;
         let mut rests = vec![];
         self.cursor = None;
+        // Leave room for an open repeat barline.
+        self.width +=
+            self.bar.repeat_open_width(meta) as f32 / BAR_WIDTH as f32;
+        // Leave room for a signature change.
+        self.width += self.bar.signature as f32 / BAR_WIDTH as f32;
+        // A multi-bar rest replaces the music of every stave.
+        if self.bar.multi_rest > 1 {
+            rests.extend(self.pq.drain(..).map(|(_, stave_i)| stave_i));
+        }
+        // Markings at the start of the bar.
+        let all: Vec<usize> = self.pq.iter().map(|(_, i)| *i).collect();
+        let markings = self.take_markings(&all, ymargin);
+        self.add_markings(meta, &markings, staves, ymargin);
+        self.add_syllables(&all);
         // Empty the priority queue.
         while let Some((mut time, stave_i)) = self.pq.pop_front() {
+            let mut markings = self.take_markings(&[stave_i], ymargin);
             let (pitches, dur, ic) =
                 if let Some(a) = self.notators[stave_i].next() {
                     a
                 } else {
-                    rests.push((stave_i, self.notators[stave_i].is_cursor()));
+                    rests.push(stave_i);
                     continue;
                 };
             // Increment width
             if time < self.all {
-                self.width += get_spacing(self.all - time) / 7.0;
+                self.width +=
+                    get_spacing(self.all - time) / 7.0 * self.bar.stretch;
                 self.all = time;
+                // Add markings of every stave that reached this time.
+                let now: Vec<usize> = self
+                    .pq
+                    .iter()
+                    .filter(|(t, _)| *t == time)
+                    .map(|(_, i)| *i)
+                    .collect();
+                markings.extend(self.take_markings(&now, ymargin));
+                self.add_markings(meta, &markings, staves, ymargin);
+                let mut now = now;
+                now.push(stave_i);
+                self.add_syllables(&now);
             }
             // Render cursor
             if ic {
@@ -110,6 +149,7 @@ impl<'a, 'b, 'c> BarEngraver<'a, 'b, 'c> {
                     }
                 }
             } else if let Some((x, stave_j)) = self.cursor {
+                // Cursor ends at the next marking of the same voice.
                 if stave_i == stave_j {
                     self.cursor = None;
                     let e = if x == 0.0 { 0 } else { -meta.barline_thickness };
@@ -124,32 +164,58 @@ impl<'a, 'b, 'c> BarEngraver<'a, 'b, 'c> {
                     ));
                 }
             }
+            // Only the first part of a tied note has articulations.
+            let articulation = self.notators[stave_i].take_articulation();
+            let note = self.notators[stave_i].take_note();
+            // Offset Y, so that the note appears on the correct stave.
+            let notator = &self.notators[stave_i];
+            let y_offset = ymargin * notator.stave() as i32;
+            let stems = notator.stems();
             // Render pitch or rest.
             if pitches.is_empty() {
-                // Add rest
+                if articulation.contains(&scof::Articulation::Fermata) {
+                    let x = self.bar.width
+                        + (self.width * BAR_WIDTH as f32) as i32
+                        + Stave::SPACE / 2;
+                    self.bar.add_fermata(x, y_offset, None);
+                }
+                // Add rest, displaced away from other voices.
                 self.bar.add_rest(
                     crate::glyph::rest_duration(dur),
                     self.width,
-                    ymargin * stave_i as i32,
+                    y_offset + notator.rest_offset(),
                 );
                 // Advance beaming
                 self.beams[stave_i].advance(dur, self.width, None);
             } else {
-                // Offset Y, so that the note appears on the correct stave.
-                let y_offset = ymargin * stave_i as i32;
                 // Add chord
-                for pitch in &pitches {
-                    let y = self
+                let steps: Vec<Steps> =
+                    pitches.iter().map(|p| p.visual_distance()).collect();
+                self.bar
+                    .add_chord(meta, dur, self.width, &steps, y_offset, stems);
+                let x = self.bar.width + (self.width * BAR_WIDTH as f32) as i32;
+                self.bar.add_articulations(
+                    &articulation,
+                    x,
+                    &steps,
+                    y_offset,
+                    stems,
+                );
+                // Remember where the note is for attaching spanners, and add
+                // its ornaments.
+                if let Some(cursor) = note {
+                    let width = notehead::width(Notehead::Normal, meta, dur);
+                    let anchor = self
                         .bar
-                        .y_from_steps(pitch.visual_distance(), y_offset);
-
-                    self.bar.add_pitch(
-                        meta,
-                        dur,
-                        self.width,
-                        pitch.visual_distance(),
-                        y,
-                    );
+                        .add_anchor(cursor, x, width, &steps, y_offset, stems);
+                    if let Some(anchor) = anchor {
+                        self.bar.add_ornaments(
+                            meta,
+                            &anchor,
+                            &articulation,
+                            dur,
+                        );
+                    }
                 }
                 // Advance beaming (using closest note to the beam)
                 self.beams[stave_i].advance(
@@ -181,13 +247,38 @@ impl<'a, 'b, 'c> BarEngraver<'a, 'b, 'c> {
             self.bar.add_flags_and_beams(meta, beam);
         }
         // Add the rest of the width.
-        self.width += get_spacing(self.all) / 7.0;
+        self.width += get_spacing(self.all) / 7.0 * self.bar.stretch;
+        // Markings at the end of the bar.
+        let all: Vec<usize> = (0..self.notators.len()).collect();
+        let markings = self.take_markings(&all, ymargin);
+        self.add_markings(meta, &markings, staves, ymargin);
+        self.bar.add_fermatas(staves, ymargin);
+        // Leave room for the last syllables of lyrics.
+        let x = self.bar.width + (self.width * BAR_WIDTH as f32) as i32;
+        if let Some(right) = self.bar.lyrics_right() {
+            let overhang = (right - x).max(0);
+            self.width += overhang as f32 / BAR_WIDTH as f32;
+        }
+        // Remember the rhythmic spacing width (without margins).
+        let margin = Stave::SPACE as f32 / BAR_WIDTH as f32;
+        self.bar.spacing = (BAR_WIDTH as f32 * (self.width - margin)) as i32;
         // End of bar margin
         self.width += Stave::SPACE as f32 / BAR_WIDTH as f32;
-        // Draw measure rests
-        for (rest_stave, rest_ic) in rests {
-            self.bar
-                .add_measure_rest(self.width, ymargin * rest_stave as i32);
+        // Leave room for close repeat dots.
+        self.width +=
+            self.bar.repeat_dots_width(meta) as f32 / BAR_WIDTH as f32;
+        // Leave room for the H-bar of a multi-bar rest.
+        if self.bar.multi_rest > 1 {
+            self.width = self.width.max(MULTI_REST_BARS);
+        }
+        // Draw measure rests (only one for each stave).
+        for stave_i in rests {
+            let notator = &self.notators[stave_i];
+            let rest_ic = notator.is_cursor();
+            if notator.is_first_voice() {
+                let y_offset = ymargin * notator.stave() as i32;
+                self.bar.add_measure_rest(self.width, y_offset);
+            }
             if rest_ic {
                 cursor_rect = Some((
                     meta.barline_thickness,                 // X
@@ -213,15 +304,71 @@ impl<'a, 'b, 'c> BarEngraver<'a, 'b, 'c> {
         }
         // Calculate physical bar width.
         let bar_width = ((BAR_WIDTH as f32 * self.width) as i32).max(BAR_WIDTH);
-        // Draw barlines
-        for i in 0..self.notators.len().try_into().unwrap() {
+        // Draw barlines (the stave continues under a close repeat barline).
+        let close = self.bar.repeat_close_width(meta);
+        for i in 0..staves {
             let y = self.bar.offset_y(self.bar.stave.steps_middle_c);
-            let path = self.bar.stave.path(meta, y, bar_width, ymargin * i);
+            let path =
+                self.bar.stave.path(meta, y, bar_width + close, ymargin * i);
             self.bar.elements.push(Element::Path(path));
             self.bar.add_barline(meta, bar_width, ymargin * i);
+            self.bar.add_repeat_barlines(meta, bar_width, ymargin * i);
         }
         // Return calculated physical bar width.
-        (bar_width, cursor_rect)
+        (bar_width + close, cursor_rect)
+    }
+
+    /// Take the markings between notes before the next notes of some
+    /// notators, with their stave offsets.
+    fn take_markings(
+        &mut self,
+        notators: &[usize],
+        ymargin: Steps,
+    ) -> Vec<(Cursor, Marking, Steps)> {
+        let mut markings = vec![];
+        for &i in notators {
+            let ofs = ymargin * self.notators[i].stave() as i32;
+            let taken = self.notators[i].take_markings();
+            markings.extend(taken.into_iter().map(|(c, m)| (c, m, ofs)));
+        }
+        markings
+    }
+
+    /// Engrave the lyrics of the notes starting now on some notators, moving
+    /// the notes right if the syllables need more room.
+    fn add_syllables(&mut self, notators: &[usize]) {
+        let notes: Vec<Cursor> = notators
+            .iter()
+            .filter_map(|i| self.notators[*i].peek_note())
+            .collect();
+        if notes.is_empty() {
+            return;
+        }
+        let x = self.bar.width + (self.width * BAR_WIDTH as f32) as i32;
+        let shift = self.bar.add_syllables(&notes, x);
+        self.width += shift as f32 / BAR_WIDTH as f32;
+    }
+
+    /// Engrave markings between notes: breath marks, caesuras and grace
+    /// notes (leaving room for them), then dynamics under the next notes.
+    fn add_markings(
+        &mut self,
+        meta: &SfFontMetadata,
+        markings: &[(Cursor, Marking, Steps)],
+        staves: i32,
+        ymargin: Steps,
+    ) {
+        if markings.is_empty() {
+            return;
+        }
+        let x = self.bar.width + (self.width * BAR_WIDTH as f32) as i32;
+        let width = self.bar.add_pauses(markings, x, staves, ymargin);
+        self.width += width as f32 / BAR_WIDTH as f32;
+        let x = self.bar.width + (self.width * BAR_WIDTH as f32) as i32;
+        let width = self.bar.add_graces(meta, markings, x);
+        self.width += width as f32 / BAR_WIDTH as f32;
+        let x = self.bar.width + (self.width * BAR_WIDTH as f32) as i32;
+        self.bar.add_dynamics(markings, x);
     }
 }
 
@@ -0,0 +1,281 @@
+// ScoreFall Ink - Music Composition Software
+//
+// Copyright (C) 2019-2020 Jeron Aldaron Lau <jeronlau@plopgrizzly.com>
+// Copyright (C) 2019-2020 Doug P. Lau
+//
+//     This program is free software: you can redistribute it and/or modify
+//     it under the terms of the GNU General Public License as published by
+//     the Free Software Foundation, either version 3 of the License, or
+//     (at your option) any later version.
+//
+//     This program is distributed in the hope that it will be useful,
+//     but WITHOUT ANY WARRANTY; without even the implied warranty of
+//     MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//     GNU General Public License for more details.
+//
+//     You should have received a copy of the GNU General Public License
+//     along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+/* ************************************************************************** */
+
+//! Signatures, at the start of the bars that change them.
+//!
+//! Time signatures are engraved on every stave, and tempo and swing markings
+//! above the first stave.  Only the parts of a signature that change from the
+//! previous bar are engraved.
+
+use crate::glyph::{flag_duration, time_digit};
+use crate::grace::cue;
+use crate::notehead::{self, Notehead};
+use crate::{BarElem, Element, Stave, Text, TIME_DIGIT_WIDTH};
+use scof::{Cursor, Scof, Sig, Steps};
+use sfff::{Glyph, SfFontMetadata};
+
+/// Font size of tempo and swing markings.
+const TEMPO_SIZE: i32 = 2 * Stave::SPACE;
+/// Height of the baseline of tempo markings above the first stave.
+const TEMPO_HEIGHT: i32 = 4 * Stave::SPACE;
+/// Room for tempo markings above the first stave (up to the top of the
+/// note's stem).
+pub(crate) const TEMPO_ROOM: Steps = Steps(14);
+/// Swing equation when the style doesn't have one (eighths played as
+/// triplets).
+const SWING_TEXT: &str = "1/8 1/8 = 1/6 1/12";
+/// Text of a change back to straight eighths.
+const STRAIGHT_TEXT: &str = "Straight";
+
+/// Check if a signature swings eighth notes.
+fn swung(sig: &Sig) -> bool {
+    matches!(sig.swing, Some(swing) if swing != 50)
+}
+
+impl BarElem {
+    /// Add the signature a bar changes to, returning the room needed for it.
+    ///
+    /// - `curs`: Cursor of the measure.
+    /// - `x`: X position of the signature.
+    pub(crate) fn add_signatures(
+        &mut self,
+        meta: &SfFontMetadata,
+        scof: &Scof,
+        curs: &Cursor,
+        x: i32,
+    ) -> i32 {
+        let mvmt = match scof.movement.get(curs.movement_index() as usize) {
+            Some(mvmt) => mvmt,
+            None => return 0,
+        };
+        let bar = curs.bar_index();
+        let index = match mvmt.sig_change(bar) {
+            Some(index) => index,
+            None => return 0,
+        };
+        let sig = &mvmt.sig[index];
+        let prev_index = bar.checked_sub(1).and_then(|b| mvmt.sig_index(b));
+        let prev = prev_index.and_then(|i| mvmt.sig.get(i));
+        let style = scof.style.sig.get(index);
+        let symbol = matches!(style, Some(s) if s.time_symbol);
+        let prev_style = prev_index.and_then(|i| scof.style.sig.get(i));
+        let prev_symbol = matches!(prev_style, Some(s) if s.time_symbol);
+        let mut width = 0;
+        if prev.map(|p| (&p.time, prev_symbol)) != Some((&sig.time, symbol)) {
+            let ymargin = self.stave.height_steps() + Steps(12);
+            for chan in 0..scof.chan_len(curs) {
+                let ofs = ymargin * i32::from(chan);
+                width = self.add_time(&sig.time, symbol, x, ofs);
+            }
+            if width > 0 {
+                width += Stave::SPACE;
+            }
+        }
+        let y = self.top_line(Steps(0)) - TEMPO_HEIGHT;
+        let mut text_x = x;
+        let tempo = style.and_then(|s| s.tempo.clone());
+        if tempo.is_some() || prev.map(|p| p.tempo) != Some(sig.tempo) {
+            text_x = match tempo {
+                Some(tempo) => self.add_tempo_text(x, y, tempo),
+                None => self.add_tempo(meta, x, y, sig),
+            } + TEMPO_SIZE;
+        }
+        let swing =
+            style.and_then(|s| s.swing_text.clone()).or_else(|| {
+                match (matches!(prev, Some(p) if swung(p)), swung(sig)) {
+                    (false, true) => Some(SWING_TEXT.to_string()),
+                    (true, false) => Some(STRAIGHT_TEXT.to_string()),
+                    _ => None,
+                }
+            });
+        if let Some(swing) = swing {
+            self.add_tempo_text(text_x, y, swing);
+        }
+        width
+    }
+
+    /// Add a time signature, returning its width.  The `symbol` is used for
+    /// common (4/4) and cut (2/2) time.
+    fn add_time(
+        &mut self,
+        time: &str,
+        symbol: bool,
+        x: i32,
+        ofs: Steps,
+    ) -> i32 {
+        let middle = self.middle() + (ofs * Stave::STEP).0;
+        let glyph = match time {
+            "4/4" => Some(Glyph::TimeSigCommon),
+            "2/2" => Some(Glyph::TimeSigCut),
+            _ => None,
+        };
+        if let Some(glyph) = glyph.filter(|_| symbol) {
+            self.add_use(glyph, x, middle);
+            return TIME_DIGIT_WIDTH;
+        }
+        let mut parts = time.splitn(2, '/');
+        let (num, den) = match (parts.next(), parts.next()) {
+            (Some(num), Some(den)) => (num.trim(), den.trim()),
+            _ => return 0,
+        };
+        let width = num.len().max(den.len()) as i32 * TIME_DIGIT_WIDTH;
+        self.add_time_digits(num, x + width / 2, middle - Stave::SPACE);
+        self.add_time_digits(den, x + width / 2, middle + Stave::SPACE);
+        width
+    }
+
+    /// Add the digits of a time signature number, centered at `x`.
+    fn add_time_digits(&mut self, number: &str, x: i32, y: i32) {
+        let mut x = x - number.len() as i32 * TIME_DIGIT_WIDTH / 2;
+        for digit in number.chars().filter_map(|c| c.to_digit(10)) {
+            self.add_use(time_digit(digit), x, y);
+            x += TIME_DIGIT_WIDTH;
+        }
+    }
+
+    /// Add a "beat = BPM" tempo marking, with the beat of the time signature
+    /// (a dotted note in compound meters), returning the X position of its
+    /// end.
+    fn add_tempo(
+        &mut self,
+        meta: &SfFontMetadata,
+        x: i32,
+        y: i32,
+        sig: &Sig,
+    ) -> i32 {
+        // Duration of the beat (a quarter note is 32), and if it's dotted.
+        let beat = sig.beat();
+        let dotted = beat.num == 3;
+        let dur = (128 / beat.den.max(1)) * if dotted { 2 } else { 1 };
+        let head = y - Stave::SPACE / 2;
+        self.add_cue(notehead::duration(dur), x, head);
+        let [_, right] = notehead::stems(Notehead::Normal, meta, dur);
+        let stem_x = x + cue(right[0]) - meta.stem_thickness;
+        let mut x = stem_x + meta.stem_thickness + Stave::SPACE / 2;
+        if dur < 128 {
+            let top = head - cue(Self::STEM_LENGTH);
+            self.add_stem2(meta, stem_x, top, head - top);
+            if let Some(flag) = flag_duration(dur, true) {
+                self.add_cue(flag, stem_x, top);
+            }
+        }
+        if dotted {
+            self.add_cue(Glyph::Staccato, x, head);
+            x += Stave::SPACE;
+        }
+        self.add_tempo_text(x, y, format!("= {}", sig.tempo))
+    }
+
+    /// Add tempo or swing text, returning the X position of its end.
+    fn add_tempo_text(&mut self, x: i32, y: i32, text: String) -> i32 {
+        let text = Text::new(x, y, TEMPO_SIZE, false, text);
+        let end = text.x + text.width();
+        self.elements.push(Element::Text(text));
+        end
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use scof::{Movement, SigStyle};
+
+    /// Engrave the bars of a movement, getting the full-size glyphs and the
+    /// text of each bar.
+    fn engrave(scof: &Scof) -> Vec<(Vec<u16>, Vec<String>)> {
+        let meta = crate::tests::meta();
+        (0..scof.movement[0].bar.len() as u16)
+            .map(|b| {
+                let mut bar = crate::document::bar_elem(
+                    crate::document::pitch_range(scof, 0),
+                );
+                bar.add_bar(&meta, scof, &Cursor::new(0, b, 0, 0));
+                let mut glyphs = vec![];
+                let mut texts = vec![];
+                for elem in &bar.elements {
+                    match elem {
+                        Element::Use(u) if u.scale == 1.0 => glyphs.push(u.id),
+                        Element::Text(t) => texts.push(t.text.clone()),
+                        _ => {}
+                    }
+                }
+                (glyphs, texts)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn signatures() {
+        let muon = "sig: 0\n  time: 12/8\n  tempo: 90\n\
+            sig: 0\n  time: 4/4\n  tempo: 90\n  swing: 67\n\
+            sig: 0\n  time: 4/4\n  tempo: 100\n\
+            bar:\n  chan: 1/1R 1/2R\n\
+            bar:\n  sig: 1\n  chan: 1/1R\n\
+            bar:\n  chan: 1/1R\n\
+            bar:\n  sig: 2\n  chan: 1/1R\n";
+        let style = |time_symbol, tempo: Option<&str>| SigStyle {
+            tempo: tempo.map(str::to_string),
+            time_symbol,
+            swing_text: None,
+        };
+        let mut scof = Scof {
+            movement: vec![Movement::from_muon(muon).unwrap()],
+            ..Default::default()
+        };
+        scof.style.sig = vec![
+            style(false, None),
+            style(true, None),
+            style(false, Some("Faster")),
+        ];
+        let bars = engrave(&scof);
+        let digits: Vec<u16> =
+            [1, 2, 8].iter().map(|d| time_digit(*d) as u16).collect();
+        let time = |glyphs: &Vec<u16>| {
+            glyphs
+                .iter()
+                .filter(|g| {
+                    (Glyph::TimeSig0 as u16..=Glyph::TimeSigCut as u16)
+                        .contains(g)
+                })
+                .copied()
+                .collect::<Vec<u16>>()
+        };
+        assert_eq!(time(&bars[0].0), digits);
+        assert_eq!(bars[0].1, vec!["= 90"]);
+        // Dotted quarter beats in 12/8 time.
+        let mut bar =
+            crate::document::bar_elem(crate::document::pitch_range(&scof, 0));
+        bar.add_bar(&crate::tests::meta(), &scof, &Cursor::new(0, 0, 0, 0));
+        let dot = Glyph::Staccato as u16;
+        assert!(bar
+            .elements
+            .iter()
+            .any(|e| matches!(e, Element::Use(u) if u.id == dot)));
+        // Common time, and the tempo doesn't change.
+        assert_eq!(time(&bars[1].0), vec![Glyph::TimeSigCommon as u16]);
+        assert_eq!(bars[1].1, vec![SWING_TEXT]);
+        assert!(time(&bars[2].0).is_empty());
+        assert!(bars[2].1.is_empty());
+        // The same time, but without the symbol, back to straight eighths.
+        let four = time_digit(4) as u16;
+        assert_eq!(time(&bars[3].0), vec![four, four]);
+        assert_eq!(bars[3].1, vec!["Faster", STRAIGHT_TEXT]);
+    }
+}
@@ -0,0 +1,435 @@
+// ScoreFall Ink - Music Composition Software
+//
+// Copyright (C) 2019-2020 Jeron Aldaron Lau <jeronlau@plopgrizzly.com>
+// Copyright (C) 2019-2020 Doug P. Lau
+//
+//     This program is free software: you can redistribute it and/or modify
+//     it under the terms of the GNU General Public License as published by
+//     the Free Software Foundation, either version 3 of the License, or
+//     (at your option) any later version.
+//
+//     This program is distributed in the hope that it will be useful,
+//     but WITHOUT ANY WARRANTY; without even the implied warranty of
+//     MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//     GNU General Public License for more details.
+//
+//     You should have received a copy of the GNU General Public License
+//     along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+/* ************************************************************************** */
+
+//! Slurs, ties, glissandi and bends, connecting notes.
+//!
+//! Each bar draws the part of a spanner within it.  A spanner continuing
+//! over a barline is left open at the bar edge, where it is joined by the
+//! part in the next bar (or system).
+
+use crate::{BarElem, Element, Path, Stave};
+use scof::{Articulation, Cursor, Scof, Steps};
+use sfff::SfFontMetadata;
+
+/// Height of the arc of a slur, above its ends.
+const SLUR_HEIGHT: i32 = Stave::SPACE * 3 / 2;
+/// Height of the arc of a tie, above its ends.
+const TIE_HEIGHT: i32 = Stave::SPACE * 3 / 4;
+/// Distance between a slur end and the notehead.
+const SLUR_GAP: i32 = Stave::SPACE;
+/// Space between a glissando or bend line and a notehead.
+const LINE_GAP: i32 = Stave::STEP / 2;
+/// Width of a bend line.
+const BEND_WIDTH: i32 = 2 * Stave::SPACE;
+/// Length of one wave of a wavy glissando.
+const WAVE_LENGTH: i32 = Stave::SPACE;
+/// Height of a wave of a wavy glissando, above and below the line.
+const WAVE_HEIGHT: i32 = Stave::SPACE / 4;
+
+/// Position of an engraved note, for attaching spanners.
+#[derive(Clone, Debug)]
+pub(crate) struct Anchor {
+    /// Cursor of the note marking
+    pub cursor: Cursor,
+    /// X position of the noteheads
+    pub x: i32,
+    /// Width of the noteheads
+    pub width: i32,
+    /// Y position of the highest notehead
+    pub high: i32,
+    /// Y position of the lowest notehead
+    pub low: i32,
+    /// Whether the stems point up
+    pub up: bool,
+    /// Offset of the stave
+    pub ofs: Steps,
+}
+
+impl Anchor {
+    /// Get the X position of the center of the noteheads.
+    pub(crate) fn center(&self) -> i32 {
+        self.x + self.width / 2
+    }
+
+    /// Get the Y position of a slur end at the note.
+    fn slur_y(&self, above: bool) -> i32 {
+        if above {
+            self.high - SLUR_GAP
+        } else {
+            self.low + SLUR_GAP
+        }
+    }
+}
+
+/// An end of a spanner within a bar: at a note, or open at the bar edge.
+type End = (i32, i32, bool);
+
+impl BarElem {
+    /// Remember the position of an engraved note (or chord) with noteheads
+    /// at `steps`, for attaching spanners and ornaments.
+    ///
+    /// - `x`: X position of the noteheads.
+    /// - `width`: Width of the noteheads.
+    /// - `ofs`: Offset of the stave.
+    /// - `stems`: Stem direction forced by the voice (true is up).
+    pub(crate) fn add_anchor(
+        &mut self,
+        cursor: Cursor,
+        x: i32,
+        width: i32,
+        steps: &[Steps],
+        ofs: Steps,
+        stems: Option<bool>,
+    ) -> Option<Anchor> {
+        let (high, low) = (*steps.iter().max()?, *steps.iter().min()?);
+        let high = self.y_from_steps(high, ofs);
+        let low = self.y_from_steps(low, ofs);
+        let up = self.chord_stems_up(high, low, ofs, stems);
+        let anchor = Anchor {
+            cursor,
+            x,
+            width,
+            high,
+            low,
+            up,
+            ofs,
+        };
+        self.anchors.push(anchor.clone());
+        Some(anchor)
+    }
+
+    /// Get the anchor of a note engraved in this bar.
+    pub(crate) fn anchor(&self, cursor: &Cursor) -> Option<&Anchor> {
+        self.anchors.iter().find(|a| &a.cursor == cursor)
+    }
+
+    /// Add the spanners which start, end or pass through the bar.
+    ///
+    /// - `curs`: Cursor of the measure.
+    /// - `width`: Width of the bar (up to the barline).
+    pub(crate) fn add_spanners(
+        &mut self,
+        meta: &SfFontMetadata,
+        scof: &Scof,
+        curs: &Cursor,
+        width: i32,
+    ) {
+        if self.multi_rest > 1 {
+            return;
+        }
+        let wavy = scof.style.wavy_glissando.unwrap_or(false);
+        let edges = (self.width, self.width + width);
+        let bar = curs.bar_index();
+        for spanner in scof.bar_spanners(curs.movement_index(), bar) {
+            let start = self.anchor(&spanner.start).cloned();
+            let end = self.anchor(&spanner.end).cloned();
+            // Stave lines, for a spanner passing through with no notes here
+            let lines = if spanner.start.bar_index() < bar
+                && bar < spanner.end.bar_index()
+            {
+                let ymargin = self.stave.height_steps() + Steps(12);
+                let ofs = ymargin * i32::from(spanner.start.chan_index());
+                Some((self.top_line(ofs), self.bottom_line(ofs)))
+            } else {
+                None
+            };
+            let d = match spanner.articulation {
+                Articulation::Slur => {
+                    slur(meta, start, end, edges, lines, SLUR_HEIGHT)
+                }
+                Articulation::Tie => {
+                    slur(meta, start, end, edges, lines, TIE_HEIGHT)
+                }
+                Articulation::Glissando => {
+                    glissando(meta, start, end, edges, lines, wavy)
+                }
+                articulation => {
+                    start.and_then(|s| bend(meta, articulation, &s))
+                }
+            };
+            if let Some(d) = d {
+                self.elements.push(Element::Path(Path::new(None, d)));
+            }
+        }
+    }
+}
+
+/// Create path data for the part of a slur (or tie) within a bar, with an
+/// arc `height` above its ends.  With no notes in the bar, it's drawn from
+/// edge to edge above the stave `lines` (top and bottom).
+fn slur(
+    meta: &SfFontMetadata,
+    start: Option<Anchor>,
+    end: Option<Anchor>,
+    (left, right): (i32, i32),
+    lines: Option<(i32, i32)>,
+    height: i32,
+) -> Option<String> {
+    // Above the notes, unless all stems are up.
+    let above = (start.is_none() && end.is_none())
+        || !start.iter().chain(end.iter()).all(|a| a.up);
+    let dir = if above { -1 } else { 1 };
+    let at = |a: &Anchor| (a.center(), a.slur_y(above), false);
+    let (start, end): (End, End) = match (&start, &end) {
+        (Some(s), Some(e)) => (at(s), at(e)),
+        (Some(s), None) => (at(s), (right, at(s).1 + dir * height, true)),
+        (None, Some(e)) => ((left, at(e).1 + dir * height, true), at(e)),
+        (None, None) => {
+            let y = lines?.0 - SLUR_GAP + dir * height;
+            ((left, y, true), (right, y, true))
+        }
+    };
+    let ((x0, y0, open0), (x1, y1, open1)) = (start, end);
+    // The arc is at its full height at an open end.
+    let rise = |y, open| if open { y } else { y + dir * height };
+    let (c0, c1) = ((x1 - x0) / 4, (x1 - x0) * 3 / 4);
+    let (cy0, cy1) = (rise(y0, open0), rise(y1, open1));
+    // Thickness tapers from the ends to the middle.
+    let (ends, mid) =
+        (meta.slur_endpoint_thickness, meta.slur_midpoint_thickness);
+    let thick = |open| if open { mid } else { ends };
+    let (t0, t1) = (thick(open0), thick(open1));
+    let tc = (mid * 8 - t0 - t1) / 6;
+    Some(format!(
+        "M{} {}C{} {} {} {} {} {}L{} {}C{} {} {} {} {} {}z",
+        x0,
+        y0,
+        x0 + c0,
+        cy0,
+        x0 + c1,
+        cy1,
+        x1,
+        y1,
+        x1,
+        y1 - dir * t1,
+        x0 + c1,
+        cy1 - dir * tc,
+        x0 + c0,
+        cy0 - dir * tc,
+        x0,
+        y0 - dir * t0,
+    ))
+}
+
+/// Create path data for the part of a glissando within a bar.  With no notes
+/// in the bar, it's drawn from edge to edge through the middle of the stave
+/// `lines` (top and bottom).
+fn glissando(
+    meta: &SfFontMetadata,
+    start: Option<Anchor>,
+    end: Option<Anchor>,
+    (left, right): (i32, i32),
+    lines: Option<(i32, i32)>,
+    wavy: bool,
+) -> Option<String> {
+    let ((x0, y0), (x1, y1)) = match (&start, &end) {
+        (Some(s), Some(e)) => {
+            ((s.x + s.width + LINE_GAP, s.high), (e.x - LINE_GAP, e.high))
+        }
+        (Some(s), None) => {
+            ((s.x + s.width + LINE_GAP, s.high), (right, s.high))
+        }
+        (None, Some(e)) => ((left, e.high), (e.x - LINE_GAP, e.high)),
+        (None, None) => {
+            let (top, bottom) = lines?;
+            let y = (top + bottom) / 2;
+            ((left, y), (right, y))
+        }
+    };
+    let t = meta.stem_thickness;
+    if !wavy {
+        return Some(format!(
+            "M{} {}L{} {}L{} {}L{} {}z",
+            x0,
+            y0 - t / 2,
+            x1,
+            y1 - t / 2,
+            x1,
+            y1 + t / 2,
+            x0,
+            y0 + t / 2,
+        ));
+    }
+    // Alternate quadratic curves above and below the line, for each edge.
+    let (dx, dy) = ((x1 - x0) as f32, (y1 - y0) as f32);
+    let length = (dx * dx + dy * dy).sqrt().max(1.0);
+    let halves = ((length / (WAVE_LENGTH / 2) as f32) as i32).max(1);
+    let (nx, ny) = (-dy / length, dx / length);
+    let point = |half: f32, ofs: f32| {
+        let along = half / halves as f32;
+        (
+            (x0 as f32 + dx * along + nx * ofs) as i32,
+            (y0 as f32 + dy * along + ny * ofs) as i32,
+        )
+    };
+    let edge = |ofs: f32, rev: bool| {
+        let mut d = String::new();
+        for h in 0..halves {
+            let half = if rev { halves - 1 - h } else { h };
+            let wave = if half % 2 == 0 { -2.0 } else { 2.0 };
+            let wave = wave * WAVE_HEIGHT as f32;
+            let (cx, cy) = point(half as f32 + 0.5, ofs + wave);
+            let end = if rev { half } else { half + 1 };
+            let (px, py) = point(end as f32, ofs);
+            d.push_str(&format!("Q{} {} {} {}", cx, cy, px, py));
+        }
+        d
+    };
+    let t = t as f32 / 2.0;
+    let (sx, sy) = point(0.0, -t);
+    let (ex, ey) = point(halves as f32, t);
+    Some(format!(
+        "M{} {}{}L{} {}{}z",
+        sx,
+        sy,
+        edge(-t, false),
+        ex,
+        ey,
+        edge(t, true)
+    ))
+}
+
+/// Create path data for a bend sliding into or out of a note.
+fn bend(
+    meta: &SfFontMetadata,
+    articulation: Articulation,
+    anchor: &Anchor,
+) -> Option<String> {
+    let before = anchor.x - LINE_GAP;
+    let after = anchor.x + anchor.width + LINE_GAP;
+    let (y, space) = (anchor.high, Stave::SPACE);
+    // From the far end, curving to meet the note.
+    let ((x0, y0), (x1, y1), (cx, cy)) = match articulation {
+        Articulation::BendUpInto => {
+            let x = before - BEND_WIDTH;
+            ((x, y + space), (before, y), (x + BEND_WIDTH / 2, y))
+        }
+        Articulation::BendDownInto => {
+            let x = before - BEND_WIDTH;
+            ((x, y - space), (before, y), (x + BEND_WIDTH / 2, y))
+        }
+        Articulation::BendUpOut => {
+            let x = after + BEND_WIDTH;
+            ((after, y), (x, y - space), (after + BEND_WIDTH / 2, y))
+        }
+        Articulation::BendDownOut => {
+            let x = after + BEND_WIDTH;
+            ((after, y), (x, y + space), (after + BEND_WIDTH / 2, y))
+        }
+        _ => return None,
+    };
+    let t = meta.stem_thickness;
+    Some(format!(
+        "M{} {}Q{} {} {} {}L{} {}Q{} {} {} {}z",
+        x0,
+        y0,
+        cx,
+        cy,
+        x1,
+        y1,
+        x1,
+        y1 + t,
+        cx,
+        cy + t,
+        x0,
+        y0 + t,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use scof::Movement;
+
+    /// Get the curved paths engraved in each bar of a movement.
+    fn curves(chans: &[&str], style: &str) -> Vec<Vec<String>> {
+        let mut text = "sig: 0\n  time: 2/4\n  tempo: 120\n".to_string();
+        for chan in chans {
+            text.push_str(&format!("bar:\n  chan: {}\n", chan));
+        }
+        let scof = Scof {
+            movement: vec![Movement::from_muon(&text).unwrap()],
+            style: scof::Style::from_muon(style).unwrap(),
+            ..Default::default()
+        };
+        let meta = crate::tests::meta();
+        (0..chans.len() as u16)
+            .map(|bar| {
+                let mut elem = crate::document::bar_elem(
+                    crate::document::pitch_range(&scof, 0),
+                );
+                elem.add_bar(&meta, &scof, &Cursor::new(0, bar, 0, 0));
+                elem.elements
+                    .iter()
+                    .filter_map(|e| match e {
+                        Element::Path(p) if p.d.contains(&['C', 'Q'][..]) => {
+                            Some(p.d.clone())
+                        }
+                        _ => None,
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn slurs() {
+        let bars = curves(&["1/4A3& 1/4B3", "1/4C4 1/4D4"], "");
+        assert_eq!(bars[0].len(), 1);
+        assert!(bars[1].is_empty());
+        // Stems up, so the slur is below the notes (curving down).
+        let d = &bars[0][0];
+        let numbers: Vec<i32> = d
+            .split(|c: char| c.is_ascii_alphabetic() || c == ' ')
+            .filter_map(|n| n.parse().ok())
+            .collect();
+        assert!(numbers[3] > numbers[1]);
+        // A slur over the barline is split, open at the bar edges.
+        let bars = curves(&["1/4R 1/4C4&", "1/4D4 1/4R"], "");
+        assert_eq!(bars[0].len(), 1);
+        assert_eq!(bars[1].len(), 1);
+        assert!(bars[1][0].starts_with("M0 "));
+        // A tie over the barline is split too, and flatter than a slur.
+        let bars = curves(&["1/4R 1/4C4)", "1/4C4 1/4R"], "");
+        assert_eq!(bars[0].len(), 1);
+        assert_eq!(bars[1].len(), 1);
+        let slurs = curves(&["1/4R 1/4C4&", "1/4D4 1/4R"], "");
+        assert_ne!(bars[0][0], slurs[0][0]);
+        // A slur through a bar of rests is drawn from edge to edge.
+        let bars = curves(&["1/4R 1/4C4&", "1/2R", "1/4D4 1/4R"], "");
+        assert_eq!(bars[1].len(), 1);
+        assert!(bars[1][0].starts_with("M0 "));
+    }
+
+    #[test]
+    fn lines() {
+        let bars = curves(&["1/4C4- 1/4G4-/", "1/2C4\\-"], "");
+        // Straight glissando (no curves), bend out of and bend into.
+        assert_eq!(bars[0].len(), 1);
+        assert_eq!(bars[0][0].matches('Q').count(), 2);
+        assert_eq!(bars[1].len(), 1);
+        let bars = curves(&["1/4C4- 1/4G4", "1/2R"], "wavy_glissando: true\n");
+        assert!(bars[0][0].matches('Q').count() > 2);
+        // A glissando through a bar of rests is drawn from edge to edge.
+        let bars =
+            curves(&["1/4R 1/4C4-", "1/2R", "1/2G4"], "wavy_glissando: true\n");
+        assert_eq!(bars[1].len(), 1);
+    }
+}
@@ -81,6 +81,13 @@ impl Rect {
             fill,
         }
     }
+
+    /// Get the fill color as red, green and blue components.
+    pub fn fill_rgb(&self) -> Option<[u8; 3]> {
+        let fill = self.fill.as_ref()?.trim_start_matches('#');
+        let rgb = u32::from_str_radix(fill, 16).ok()?;
+        Some([(rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8])
+    }
 }
 
 /// SVG `use` element
@@ -91,10 +98,20 @@ pub struct Use {
     pub y: i32,
     /// Element ID
     pub id: u16,
+    /// Scale of the glyph (1.0 is full size)
+    pub scale: f32,
 }
 
 impl fmt::Display for Use {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if (self.scale - 1.0).abs() > f32::EPSILON {
+            return write!(
+                f,
+                "<use transform='translate({} {}) scale({})' \
+                 xlink:href='#{:x}'/>",
+                self.x, self.y, self.scale, self.id
+            );
+        }
         write!(
             f,
             "<use x='{}' y='{}' xlink:href='#{:x}'/>",
@@ -106,7 +123,13 @@ impl fmt::Display for Use {
 impl Use {
     /// Create a new SVG `use` element
     pub fn new(x: i32, y: i32, id: u16) -> Self {
-        Use { x, y, id }
+        let scale = 1.0;
+        Use { x, y, id, scale }
+    }
+
+    /// Create a new SVG `use` element with a scaled glyph
+    pub fn scaled(x: i32, y: i32, id: u16, scale: f32) -> Self {
+        Use { x, y, id, scale }
     }
 }
 
@@ -128,7 +151,7 @@ impl fmt::Display for Group {
         if let Some(ro) = self.render_order {
             write!(f, " render-order=\"{}\"", ro)?;
         }
-        if self.x != 0 && self.y != 0 {
+        if self.x != 0 || self.y != 0 {
             write!(f, " transform='translate({} {})'>", self.x, self.y)?;
         } else {
             write!(f, ">")?;
@@ -187,6 +210,69 @@ impl Path {
     }
 }
 
+/// SVG `text` element
+pub struct Text {
+    /// X position
+    pub x: i32,
+    /// Y position of the baseline
+    pub y: i32,
+    /// Font size in user units
+    pub size: i32,
+    /// Italic style
+    pub italic: bool,
+    /// Text to show
+    pub text: String,
+}
+
+impl fmt::Display for Text {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "<text x='{}' y='{}' font-family='serif' font-size='{}'",
+            self.x, self.y, self.size
+        )?;
+        if self.italic {
+            write!(f, " font-style='italic'")?;
+        }
+        write!(f, ">")?;
+        for c in self.text.chars() {
+            match c {
+                '&' => write!(f, "&amp;")?,
+                '<' => write!(f, "&lt;")?,
+                '>' => write!(f, "&gt;")?,
+                c => write!(f, "{}", c)?,
+            }
+        }
+        write!(f, "</text>")
+    }
+}
+
+impl Text {
+    /// Create a new SVG `text` element
+    pub fn new<T: Into<String>>(
+        x: i32,
+        y: i32,
+        size: i32,
+        italic: bool,
+        text: T,
+    ) -> Self {
+        let text = text.into();
+        Text {
+            x,
+            y,
+            size,
+            italic,
+            text,
+        }
+    }
+
+    /// Estimate the width of the text (fonts aren't embedded, so there are
+    /// no metrics).
+    pub fn width(&self) -> i32 {
+        self.text.chars().count() as i32 * self.size / 2
+    }
+}
+
 /// SVG element
 pub enum Element {
     /// Group `g`
@@ -197,6 +283,8 @@ pub enum Element {
     Use(Use),
     /// `path`
     Path(Path),
+    /// `text`
+    Text(Text),
 }
 
 impl fmt::Display for Element {
@@ -206,6 +294,7 @@ impl fmt::Display for Element {
             Element::Rect(r) => r.fmt(f),
             Element::Use(u) => u.fmt(f),
             Element::Path(p) => p.fmt(f),
+            Element::Text(t) => t.fmt(f),
         }
     }
 }
@@ -223,12 +312,26 @@ mod tests {
         );
     }
 
+    #[test]
+    fn fill() {
+        let rect = Rect::new(0, 0, 1, 1, None, None, Some(0xFF9AF0));
+        assert_eq!(rect.fill_rgb(), Some([0xFF, 0x9A, 0xF0]));
+        let rect = Rect::new(0, 0, 1, 1, None, None, Some(0x00FF00));
+        assert_eq!(rect.fill_rgb(), Some([0x00, 0xFF, 0x00]));
+        let rect = Rect::new(0, 0, 1, 1, None, None, None);
+        assert_eq!(rect.fill_rgb(), None);
+    }
+
     #[test]
     fn glyph() {
         assert_eq!(
             Use::new(37, 21, Glyph::HarpStringNoise.into()).to_string(),
             "<use x='37' y='21' xlink:href='#68'/>"
         );
+        assert_eq!(
+            Use::scaled(37, 21, Glyph::Accent.into(), 0.5).to_string(),
+            "<use transform='translate(37 21) scale(0.5)' xlink:href='#72'/>"
+        );
     }
 
     #[test]
@@ -240,4 +343,21 @@ mod tests {
             "<g><use x='2' y='3' xlink:href='#3'/></g>"
         );
     }
+
+    #[test]
+    fn text() {
+        let text = Text::new(5, 10, 200, true, "D.S. <al> Coda");
+        assert_eq!(
+            text.to_string(),
+            "<text x='5' y='10' font-family='serif' font-size='200' \
+             font-style='italic'>D.S. &lt;al&gt; Coda</text>"
+        );
+        assert_eq!(text.width(), 1400);
+    }
+
+    #[test]
+    fn group_translate() {
+        let group = Group::new(40, 0, None);
+        assert_eq!(group.to_string(), "<g transform='translate(40 0)'></g>");
+    }
 }
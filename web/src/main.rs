@@ -33,7 +33,7 @@ use cala::task::{exec, wait};
 use std::panic;
 
 use scof::{Cursor, Fraction, Pitch, Steps};
-use scorefall_ink::Program;
+use scorefall_ink::{Direction, Program};
 use staverator::{BarElem, Element, SfFontMetadata, Stave, STAVE_SPACE};
 
 type Result<T> = core::result::Result<T, Box<dyn std::error::Error>>;
@@ -65,6 +65,15 @@ fn create_elem(screen: &Screen, elem: Element) -> Option<web_sys::Element> {
         },
         Element::Use(u) => {
             let id = format!("#{:x}", u.id);
+            if (u.scale - 1.0).abs() > f32::EPSILON {
+                let stamp = screen.new_use(0.0, 0.0, &id);
+                let transform = format!(
+                    "translate({} {}) scale({})",
+                    u.x, u.y, u.scale
+                );
+                stamp.0.set_attribute_ns(None, "transform", &transform).ok()?;
+                return Some(stamp.0);
+            }
             let stamp = screen.new_use(u.x as f32, u.y as f32, &id);
             stamp.0
         }
@@ -122,8 +131,40 @@ impl State {
     /// Input handler.
     fn event_input(&mut self, input: Input) {
         match input {
+            Input::Key(mods, Key::Z, true) if mods.ctrl() && mods.shift() => {
+                self.program.redo();
+                self.render_measures();
+            }
+            Input::Key(mods, Key::Z, true) if mods.ctrl() => {
+                self.program.undo();
+                self.render_measures();
+            }
+            Input::Key(mods, Key::Y, true) if mods.ctrl() => {
+                self.program.redo();
+                self.render_measures();
+            }
+            Input::Key(mods, Key::C, true) if mods.ctrl() => {
+                self.program.copy();
+            }
+            Input::Key(mods, Key::X, true) if mods.ctrl() => {
+                self.program.cut();
+                self.render_measures();
+            }
+            Input::Key(mods, Key::V, true) if mods.ctrl() => {
+                self.program.paste();
+                self.render_measures();
+            }
+            Input::Key(mods, key, true) if mods.ctrl() && mods.shift() && matches!(key, Key::H | Key::Left) => {
+                self.program.select_bar_left();
+                self.render_measures();
+            }
+            Input::Key(mods, key, true) if mods.ctrl() && mods.shift() && matches!(key, Key::L | Key::Right) => {
+                self.program.select_bar_right();
+                self.render_measures();
+            }
             Input::Key(mods, key, true) if mods.ctrl() && matches!(key, Key::H | Key::Left) => {
-                // TODO: Halve duration
+                self.program.halve_duration();
+                self.render_measures();
             }
             Input::Key(mods, key, true) if mods.ctrl() && matches!(key, Key::J | Key::Down) => {
                 self.program.down_half_step();
@@ -134,11 +175,13 @@ impl State {
                 self.render_measures();
             }
             Input::Key(mods, key, true) if mods.ctrl() && matches!(key, Key::L | Key::Right) => {
-                // TODO: Double duration
+                self.program.double_duration();
+                self.render_measures();
             }
 
             Input::Key(mods, key, true) if mods.alt() && matches!(key, Key::H | Key::Left) => {
-                // TODO: Move selection to the left
+                self.program.shift_selection(Direction::Left, Fraction::new(1, 8));
+                self.render_measures();
             }
             Input::Key(mods, key, true) if mods.alt() && matches!(key, Key::J | Key::Down) => {
                 self.program.down_quarter_step();
@@ -149,21 +192,34 @@ impl State {
                 self.render_measures();
             }
             Input::Key(mods, key, true) if mods.alt() && matches!(key, Key::L | Key::Right) => {
-                // TODO: Move selection to the right
+                self.program.shift_selection(Direction::Right, Fraction::new(1, 8));
+                self.render_measures();
             }
             Input::Key(mods, key, true) if mods.shift() && matches!(key, Key::H | Key::Left) => {
-                // TODO: Select left
+                self.program.select_left();
+                self.render_measures();
             }
             Input::Key(mods, key, true) if mods.shift() && matches!(key, Key::J | Key::Down) => {
-                // TODO: Select down
+                self.program.select_down();
+                self.render_measures();
             }
             Input::Key(mods, key, true) if mods.shift() && matches!(key, Key::K | Key::Up) => {
-                // TODO: Select up
+                self.program.select_up();
+                self.render_measures();
             }
             Input::Key(mods, key, true) if mods.shift() && matches!(key, Key::L | Key::Right) => {
-                // TODO: Select right
+                self.program.select_right();
+                self.render_measures();
             }
 
+            Input::Key(mods, Key::PageUp, true) if mods.none() => {
+                self.program.prev_rehearsal();
+                self.render_measures();
+            }
+            Input::Key(mods, Key::PageDown, true) if mods.none() => {
+                self.program.next_rehearsal();
+                self.render_measures();
+            }
             Input::Key(mods, key, true) if mods.none() && matches!(key, Key::H | Key::Left) => {
                 self.program.left();
                 self.render_measures();